@@ -0,0 +1,1015 @@
+use std::collections::HashMap;
+
+use crate::{
+    bus::ROM_START,
+    cpu::AddressingMode,
+    opcodes::{all_opcodes, OpCode},
+    rom::{nrom_image, CHR_ROM_BANK_SIZE, PRG_ROM_BANK_SIZE},
+};
+
+const DEFAULT_ORIGIN: u16 = 0x0600;
+const NMI_VECTOR: u16 = 0xFFFA;
+const OVERFLOW: &str = "expression overflows 64 bits";
+
+pub struct Program {
+    segments: Vec<Segment>,
+    labels: HashMap<String, u16>,
+}
+
+struct Segment {
+    origin: u16,
+    bytes: Vec<u8>,
+    line: usize,
+}
+
+impl Program {
+    pub fn label(&self, name: &str) -> Option<u16> {
+        self.labels.get(name).copied()
+    }
+
+    pub fn origin(&self) -> u16 {
+        self.segments
+            .iter()
+            .map(|s| s.origin)
+            .min()
+            .unwrap_or(DEFAULT_ORIGIN)
+    }
+
+    /**
+     * Everything from the lowest `.org` to the last emitted byte, with gaps zero filled.
+     * This is what `CPU::load` expects for programs assembled at $0600.
+     */
+    pub fn to_binary(&self) -> Vec<u8> {
+        let origin = self.origin() as usize;
+        let end = self
+            .segments
+            .iter()
+            .map(|s| s.origin as usize + s.bytes.len())
+            .max()
+            .unwrap_or(origin);
+        let mut binary = vec![0; end - origin];
+        for segment in &self.segments {
+            let start = segment.origin as usize - origin;
+            binary[start..start + segment.bytes.len()].copy_from_slice(&segment.bytes);
+        }
+        binary
+    }
+
+    /**
+     * A 32KiB NROM image. Unless the program fills in $FFFA-$FFFF itself, the vectors
+     * point at the `nmi`, `reset` and `irq` labels, falling back to the reset address
+     * (or the lowest `.org`) for whichever are missing.
+     */
+    pub fn to_ines(&self) -> Result<Vec<u8>, String> {
+        let mut prg = vec![0; 2 * PRG_ROM_BANK_SIZE];
+        let mut has_vectors = false;
+        for segment in &self.segments {
+            if segment.origin < ROM_START {
+                return Err(format!(
+                    "line {}: code at ${:04X} is outside PRG ROM (${:04X}-$FFFF), assemble it as a raw binary instead",
+                    segment.line, segment.origin, ROM_START
+                ));
+            }
+            let start = (segment.origin - ROM_START) as usize;
+            prg[start..start + segment.bytes.len()].copy_from_slice(&segment.bytes);
+            has_vectors |= segment.origin as usize + segment.bytes.len() > NMI_VECTOR as usize;
+        }
+
+        if !has_vectors {
+            let reset = self.label("reset").unwrap_or(self.origin());
+            let nmi = self.label("nmi").unwrap_or(reset);
+            let irq = self.label("irq").unwrap_or(reset);
+            let vectors = [nmi, reset, irq]
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect::<Vec<u8>>();
+            let start = (NMI_VECTOR - ROM_START) as usize;
+            prg[start..].copy_from_slice(&vectors);
+        }
+
+        Ok(nrom_image(&prg, &vec![0; CHR_ROM_BANK_SIZE]))
+    }
+}
+
+pub fn assemble_file(path: &str) -> Result<Program, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {path}: {e}"))?;
+    assemble(&source).map_err(|e| format!("{path}: {e}"))
+}
+
+/**
+ * Two pass 6502 assembler.
+ *
+ * Syntax is a small common subset of ca65 and easy6502:
+ *  - `label:` before any statement
+ *  - `NAME = expr` or `define NAME expr` for constants
+ *  - `.org expr`, `.byte`/`.db`/`dcb` (numbers or "strings"), `.word`/`.dw`
+ *  - every mnemonic in the opcode table, unofficial ones without the `*`
+ *  - `a:`/`z:` in front of an operand force absolute/zero page addressing
+ *  - expressions with $hex, %binary, decimal and 'c' literals, `*` for the current
+ *    address, `< >` for low/high byte, and + - * / % & | ^ << >> ~ with C precedence
+ *
+ * The first pass sizes every instruction. Operands that are not known yet (forward
+ * references) are assumed to be 16 bits, and the second pass sticks to that choice
+ * so that label addresses stay put.
+ */
+pub fn assemble(source: &str) -> Result<Program, String> {
+    let lines = source
+        .lines()
+        .enumerate()
+        .map(|(i, text)| parse_line(text).map_err(|e| format!("line {}: {}", i + 1, e)))
+        .collect::<Result<Vec<Line>, String>>()?;
+
+    let mut assembler = Assembler {
+        symbols: HashMap::new(),
+        opcodes: vec![None; lines.len()],
+    };
+    assembler.first_pass(&lines)?;
+    let segments = assembler.second_pass(&lines)?;
+
+    let labels = lines
+        .iter()
+        .flat_map(|line| line.labels.iter())
+        .map(|name| (name.clone(), assembler.symbols[name] as u16))
+        .collect();
+    Ok(Program { segments, labels })
+}
+
+struct Line {
+    labels: Vec<String>,
+    statement: Option<Statement>,
+}
+
+enum Statement {
+    Constant(String, Expr),
+    Org(Expr),
+    Bytes(Vec<Data>),
+    Words(Vec<Expr>),
+    Instruction(String, OperandSyntax),
+}
+
+enum Data {
+    Byte(Expr),
+    Text(Vec<u8>),
+}
+
+enum OperandSyntax {
+    None,
+    Accumulator,
+    Immediate(Expr),
+    Direct(Expr, Index, Width),
+    Indirect(Expr),
+    IndirectX(Expr),
+    IndirectY(Expr),
+}
+
+#[derive(PartialEq)]
+enum Index {
+    None,
+    X,
+    Y,
+}
+
+#[derive(PartialEq)]
+enum Width {
+    Auto,
+    ZeroPage,
+    Absolute,
+}
+
+struct Assembler {
+    symbols: HashMap<String, i64>,
+    /// encoding picked for each line in the first pass
+    opcodes: Vec<Option<&'static OpCode>>,
+}
+
+impl Assembler {
+    fn first_pass(&mut self, lines: &[Line]) -> Result<(), String> {
+        let mut pc = DEFAULT_ORIGIN as i64;
+        for (number, line) in lines.iter().enumerate() {
+            let fail = |e: String| format!("line {}: {}", number + 1, e);
+            for label in &line.labels {
+                if self.symbols.insert(label.clone(), pc).is_some() {
+                    return Err(fail(format!("{label} is already defined")));
+                }
+            }
+
+            match &line.statement {
+                None => {}
+                Some(Statement::Constant(name, expr)) => {
+                    if self.symbols.contains_key(name) {
+                        return Err(fail(format!("{name} is already defined")));
+                    }
+                    if let Some(value) = expr.eval(&self.symbols, pc).map_err(fail)? {
+                        self.symbols.insert(name.clone(), value);
+                    }
+                }
+                Some(Statement::Org(expr)) => {
+                    pc = expr
+                        .eval(&self.symbols, pc)
+                        .map_err(fail)?
+                        .ok_or_else(|| fail(".org must not use forward references".to_string()))?;
+                    check_range(pc, 0, 0xFFFF).map_err(fail)?;
+                }
+                Some(Statement::Bytes(data)) => {
+                    pc += data
+                        .iter()
+                        .map(|d| match d {
+                            Data::Byte(_) => 1,
+                            Data::Text(text) => text.len() as i64,
+                        })
+                        .sum::<i64>();
+                }
+                Some(Statement::Words(words)) => pc += 2 * words.len() as i64,
+                Some(Statement::Instruction(mnemonic, operand)) => {
+                    let value = match operand.expr() {
+                        Some(expr) => expr.eval(&self.symbols, pc).map_err(fail)?,
+                        None => None,
+                    };
+                    let opcode = select_opcode(mnemonic, operand, value).map_err(fail)?;
+                    self.opcodes[number] = Some(opcode);
+                    pc += 1 + opcode.additional_bytes as i64;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn second_pass(&mut self, lines: &[Line]) -> Result<Vec<Segment>, String> {
+        let mut segments = vec![Segment {
+            origin: DEFAULT_ORIGIN,
+            bytes: vec![],
+            line: 1,
+        }];
+        for (number, line) in lines.iter().enumerate() {
+            let fail = |e: String| format!("line {}: {}", number + 1, e);
+            let segment = segments.last().unwrap();
+            let pc = segment.origin as i64 + segment.bytes.len() as i64;
+
+            let bytes = match &line.statement {
+                None => continue,
+                Some(Statement::Constant(name, expr)) => {
+                    let value = self.resolve(expr, pc).map_err(fail)?;
+                    self.symbols.insert(name.clone(), value);
+                    continue;
+                }
+                Some(Statement::Org(expr)) => {
+                    let origin = self.resolve(expr, pc).map_err(fail)? as u16;
+                    segments.push(Segment {
+                        origin,
+                        bytes: vec![],
+                        line: number + 1,
+                    });
+                    continue;
+                }
+                Some(Statement::Bytes(data)) => {
+                    let mut bytes = vec![];
+                    for item in data {
+                        match item {
+                            Data::Byte(expr) => {
+                                let value = self.resolve(expr, pc).map_err(fail)?;
+                                check_range(value, -128, 0xFF).map_err(fail)?;
+                                bytes.push(value as u8);
+                            }
+                            Data::Text(text) => bytes.extend(text),
+                        }
+                    }
+                    bytes
+                }
+                Some(Statement::Words(words)) => {
+                    let mut bytes = vec![];
+                    for expr in words {
+                        let value = self.resolve(expr, pc).map_err(fail)?;
+                        check_range(value, -32768, 0xFFFF).map_err(fail)?;
+                        bytes.extend((value as u16).to_le_bytes());
+                    }
+                    bytes
+                }
+                Some(Statement::Instruction(_, operand)) => {
+                    let opcode = self.opcodes[number].unwrap();
+                    let mut bytes = vec![opcode.code];
+                    if let Some(expr) = operand.expr() {
+                        let value = self.resolve(expr, pc).map_err(fail)?;
+                        bytes.extend(encode_operand(opcode, value, pc).map_err(fail)?);
+                    }
+                    bytes
+                }
+            };
+            if pc + bytes.len() as i64 > 0x10000 {
+                return Err(fail("program runs past $FFFF".to_string()));
+            }
+            segments.last_mut().unwrap().bytes.extend(bytes);
+        }
+
+        segments.retain(|s| !s.bytes.is_empty());
+        segments.sort_by_key(|s| s.origin);
+        for pair in segments.windows(2) {
+            if pair[0].origin as usize + pair[0].bytes.len() > pair[1].origin as usize {
+                return Err(format!(
+                    "line {}: .org ${:04X} overlaps code from line {}",
+                    pair[1].line, pair[1].origin, pair[0].line
+                ));
+            }
+        }
+        Ok(segments)
+    }
+
+    fn resolve(&self, expr: &Expr, pc: i64) -> Result<i64, String> {
+        expr.eval(&self.symbols, pc)?
+            .ok_or_else(|| format!("unknown symbol {}", expr.first_unknown(&self.symbols)))
+    }
+}
+
+fn check_range(value: i64, min: i64, max: i64) -> Result<(), String> {
+    if value < min || value > max {
+        return Err(format!("value {value} does not fit"));
+    }
+    Ok(())
+}
+
+fn encode_operand(opcode: &OpCode, value: i64, pc: i64) -> Result<Vec<u8>, String> {
+    let is_branch = opcode.mode == AddressingMode::Implied && opcode.additional_bytes == 1;
+    if is_branch {
+        let offset = value - (pc + 2);
+        if !(-128..=127).contains(&offset) {
+            return Err(format!("branch target is {offset} bytes away"));
+        }
+        return Ok(vec![offset as u8]);
+    }
+
+    match opcode.additional_bytes {
+        1 => {
+            let min = if opcode.mode == AddressingMode::Immediate {
+                -128
+            } else {
+                0
+            };
+            check_range(value, min, 0xFF)?;
+            Ok(vec![value as u8])
+        }
+        _ => {
+            check_range(value, 0, 0xFFFF)?;
+            Ok((value as u16).to_le_bytes().to_vec())
+        }
+    }
+}
+
+/**
+ * Alternative names other assemblers use for unofficial opcodes.
+ */
+fn canonical_mnemonic(mnemonic: &str) -> String {
+    let upper = mnemonic.to_ascii_uppercase();
+    match upper.as_str() {
+        "ISC" | "INS" => "ISB",
+        "SBX" => "AXS",
+        "ASR" => "ALR",
+        "SHA" => "AHX",
+        "DCM" => "DCP",
+        other => other,
+    }
+    .to_string()
+}
+
+fn find_opcode(mnemonic: &str, mode: &AddressingMode, len: u8) -> Option<&'static OpCode> {
+    all_opcodes()
+        .filter(|op| op.mnemonic.trim_start_matches('*') == mnemonic)
+        .filter(|op| &op.mode == mode && op.additional_bytes == len)
+        .min_by_key(|op| (op.mnemonic.starts_with('*'), op.code))
+}
+
+/**
+ * Picks the encoding for an instruction. `value` is the operand, if it is already known.
+ */
+fn select_opcode(
+    mnemonic: &str,
+    operand: &OperandSyntax,
+    value: Option<i64>,
+) -> Result<&'static OpCode, String> {
+    let mnemonic = canonical_mnemonic(mnemonic);
+    let unsupported = || format!("{mnemonic} does not support this addressing mode");
+    if all_opcodes().all(|op| op.mnemonic.trim_start_matches('*') != mnemonic) {
+        return Err(format!("unknown instruction {mnemonic}"));
+    }
+
+    // jumps and branches are filed under Implied in the opcode table
+    if let Some(branch) = find_opcode(&mnemonic, &AddressingMode::Implied, 1) {
+        return match operand {
+            OperandSyntax::Direct(_, Index::None, _) => Ok(branch),
+            _ => Err(unsupported()),
+        };
+    }
+    let code = match (mnemonic.as_str(), operand) {
+        ("JMP", OperandSyntax::Direct(_, Index::None, _)) => Some(0x4c),
+        ("JMP", OperandSyntax::Indirect(_)) => Some(0x6c),
+        ("JSR", OperandSyntax::Direct(_, Index::None, _)) => Some(0x20),
+        ("JMP", _) | ("JSR", _) => return Err(unsupported()),
+        _ => None,
+    };
+    if let Some(code) = code {
+        return all_opcodes()
+            .find(|op| op.code == code)
+            .ok_or_else(unsupported);
+    }
+
+    let found = match operand {
+        OperandSyntax::None | OperandSyntax::Accumulator => {
+            find_opcode(&mnemonic, &AddressingMode::Implied, 0)
+        }
+        OperandSyntax::Immediate(_) => find_opcode(&mnemonic, &AddressingMode::Immediate, 1),
+        OperandSyntax::IndirectX(_) => find_opcode(&mnemonic, &AddressingMode::Indirect_X, 1),
+        OperandSyntax::IndirectY(_) => find_opcode(&mnemonic, &AddressingMode::Indirect_Y, 1),
+        OperandSyntax::Indirect(_) => None,
+        OperandSyntax::Direct(_, index, width) => {
+            let (zero_page, absolute) = match index {
+                Index::None => (AddressingMode::ZeroPage, AddressingMode::Absolute),
+                Index::X => (AddressingMode::ZeroPage_X, AddressingMode::Absolute_X),
+                Index::Y => (AddressingMode::ZeroPage_Y, AddressingMode::Absolute_Y),
+            };
+            let zero_page = find_opcode(&mnemonic, &zero_page, 1);
+            let absolute = find_opcode(&mnemonic, &absolute, 2);
+            let fits_zero_page = value.is_some_and(|v| (0..=0xFF).contains(&v));
+            match width {
+                Width::ZeroPage => zero_page,
+                Width::Absolute => absolute,
+                Width::Auto if fits_zero_page || absolute.is_none() => zero_page.or(absolute),
+                Width::Auto => absolute,
+            }
+        }
+    };
+    found.ok_or_else(unsupported)
+}
+
+impl OperandSyntax {
+    fn expr(&self) -> Option<&Expr> {
+        match self {
+            OperandSyntax::None | OperandSyntax::Accumulator => None,
+            OperandSyntax::Immediate(expr)
+            | OperandSyntax::Direct(expr, _, _)
+            | OperandSyntax::Indirect(expr)
+            | OperandSyntax::IndirectX(expr)
+            | OperandSyntax::IndirectY(expr) => Some(expr),
+        }
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '.' || c == '@'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '@'
+}
+
+/**
+ * Drops a trailing `;` comment, leaving quoted semicolons alone.
+ */
+fn strip_comment(text: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, ';') => return &text[..i],
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    text
+}
+
+/**
+ * Splits on commas that are not nested in parentheses or quotes.
+ */
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if q == c => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                parts.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(text[start..].trim());
+    parts
+}
+
+fn parse_line(text: &str) -> Result<Line, String> {
+    let mut rest = strip_comment(text).trim();
+    let mut labels = vec![];
+
+    // any number of `label:` prefixes
+    loop {
+        let ident_len = rest
+            .char_indices()
+            .take_while(|&(i, c)| {
+                if i == 0 {
+                    is_ident_start(c)
+                } else {
+                    is_ident_char(c)
+                }
+            })
+            .count();
+        if ident_len > 0 && rest[ident_len..].starts_with(':') {
+            labels.push(rest[..ident_len].to_string());
+            rest = rest[ident_len + 1..].trim_start();
+        } else {
+            break;
+        }
+    }
+
+    if rest.is_empty() {
+        return Ok(Line {
+            labels,
+            statement: None,
+        });
+    }
+
+    if let Some((name, value)) = rest.split_once('=') {
+        let name = name.trim();
+        if name.starts_with(is_ident_start) && name.chars().all(is_ident_char) {
+            return Ok(Line {
+                labels,
+                statement: Some(Statement::Constant(name.to_string(), Expr::parse(value)?)),
+            });
+        }
+    }
+
+    let (keyword, args) = match rest.find(char::is_whitespace) {
+        Some(i) => (&rest[..i], rest[i..].trim()),
+        None => (rest, ""),
+    };
+    let statement = match keyword.to_ascii_lowercase().as_str() {
+        "define" => {
+            let (name, value) = args
+                .split_once(char::is_whitespace)
+                .ok_or("define needs a name and a value")?;
+            Statement::Constant(name.to_string(), Expr::parse(value)?)
+        }
+        ".org" => Statement::Org(Expr::parse(args)?),
+        ".byte" | ".db" | "dcb" => Statement::Bytes(
+            split_top_level(args)
+                .into_iter()
+                .map(|item| {
+                    if item.len() >= 2 && item.starts_with('"') && item.ends_with('"') {
+                        Ok(Data::Text(item.as_bytes()[1..item.len() - 1].to_vec()))
+                    } else {
+                        Expr::parse(item).map(Data::Byte)
+                    }
+                })
+                .collect::<Result<Vec<Data>, String>>()?,
+        ),
+        ".word" | ".dw" => Statement::Words(
+            split_top_level(args)
+                .into_iter()
+                .map(Expr::parse)
+                .collect::<Result<Vec<Expr>, String>>()?,
+        ),
+        _ if keyword.starts_with('.') => return Err(format!("unknown directive {keyword}")),
+        _ => Statement::Instruction(keyword.to_string(), parse_operand(keyword, args)?),
+    };
+    Ok(Line {
+        labels,
+        statement: Some(statement),
+    })
+}
+
+fn parse_operand(mnemonic: &str, text: &str) -> Result<OperandSyntax, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(OperandSyntax::None);
+    }
+    if text.eq_ignore_ascii_case("a") {
+        return Ok(OperandSyntax::Accumulator);
+    }
+    if let Some(value) = text.strip_prefix('#') {
+        return Ok(OperandSyntax::Immediate(Expr::parse(value)?));
+    }
+
+    let parts = split_top_level(text);
+    if text.starts_with('(') {
+        let close = matching_paren(text).ok_or("unbalanced parentheses")?;
+        let inner = &text[1..close];
+        let inner_parts = split_top_level(inner);
+        let after = text[close + 1..].trim();
+        match (inner_parts.as_slice(), after) {
+            ([addr, x], "") if x.eq_ignore_ascii_case("x") => {
+                return Ok(OperandSyntax::IndirectX(Expr::parse(addr)?))
+            }
+            ([addr], "") if mnemonic.eq_ignore_ascii_case("jmp") => {
+                return Ok(OperandSyntax::Indirect(Expr::parse(addr)?))
+            }
+            ([addr], y) if y.starts_with(',') && y[1..].trim().eq_ignore_ascii_case("y") => {
+                return Ok(OperandSyntax::IndirectY(Expr::parse(addr)?))
+            }
+            // otherwise the parentheses just group an expression
+            _ => {}
+        }
+    }
+
+    let (addr, index) = match parts.as_slice() {
+        [addr] => (*addr, Index::None),
+        [addr, x] if x.eq_ignore_ascii_case("x") => (*addr, Index::X),
+        [addr, y] if y.eq_ignore_ascii_case("y") => (*addr, Index::Y),
+        _ => return Err(format!("cannot parse operand {text}")),
+    };
+    let (addr, width) = match addr.get(..2).map(|p| p.to_ascii_lowercase()) {
+        Some(prefix) if prefix == "a:" => (&addr[2..], Width::Absolute),
+        Some(prefix) if prefix == "z:" => (&addr[2..], Width::ZeroPage),
+        _ => (addr, Width::Auto),
+    };
+    Ok(OperandSyntax::Direct(Expr::parse(addr)?, index, width))
+}
+
+fn matching_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum BinaryOp {
+    Or,
+    Xor,
+    And,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+impl BinaryOp {
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::Xor => 2,
+            BinaryOp::And => 3,
+            BinaryOp::Shl | BinaryOp::Shr => 4,
+            BinaryOp::Add | BinaryOp::Sub => 5,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 6,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum UnaryOp {
+    Neg,
+    Not,
+    Low,
+    High,
+}
+
+#[derive(Debug, PartialEq)]
+enum Expr {
+    Number(i64),
+    Symbol(String),
+    CurrentAddress,
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn parse(text: &str) -> Result<Expr, String> {
+        let mut parser = ExprParser {
+            chars: text.trim().chars().collect(),
+            pos: 0,
+        };
+        let expr = parser.binary(0)?;
+        parser.skip_whitespace();
+        if parser.pos < parser.chars.len() {
+            return Err(format!("cannot parse expression {}", text.trim()));
+        }
+        Ok(expr)
+    }
+
+    /**
+     * Ok(None) means some symbol is not defined (yet).
+     */
+    fn eval(&self, symbols: &HashMap<String, i64>, pc: i64) -> Result<Option<i64>, String> {
+        Ok(match self {
+            Expr::Number(n) => Some(*n),
+            Expr::Symbol(name) => symbols.get(name).copied(),
+            Expr::CurrentAddress => Some(pc),
+            Expr::Unary(op, expr) => match expr.eval(symbols, pc)? {
+                Some(v) => Some(match op {
+                    UnaryOp::Neg => v.checked_neg().ok_or(OVERFLOW)?,
+                    UnaryOp::Not => !v,
+                    UnaryOp::Low => v & 0xFF,
+                    UnaryOp::High => (v >> 8) & 0xFF,
+                }),
+                None => None,
+            },
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = match (lhs.eval(symbols, pc)?, rhs.eval(symbols, pc)?) {
+                    (Some(lhs), Some(rhs)) => (lhs, rhs),
+                    _ => return Ok(None),
+                };
+                if rhs == 0 && matches!(op, BinaryOp::Div | BinaryOp::Mod) {
+                    return Err("division by zero".to_string());
+                }
+                let value = match op {
+                    BinaryOp::Or => Some(lhs | rhs),
+                    BinaryOp::Xor => Some(lhs ^ rhs),
+                    BinaryOp::And => Some(lhs & rhs),
+                    BinaryOp::Shl => Some(lhs << (rhs & 63)),
+                    BinaryOp::Shr => Some(lhs >> (rhs & 63)),
+                    BinaryOp::Add => lhs.checked_add(rhs),
+                    BinaryOp::Sub => lhs.checked_sub(rhs),
+                    BinaryOp::Mul => lhs.checked_mul(rhs),
+                    BinaryOp::Div => lhs.checked_div(rhs),
+                    BinaryOp::Mod => lhs.checked_rem(rhs),
+                };
+                Some(value.ok_or(OVERFLOW)?)
+            }
+        })
+    }
+
+    fn first_unknown(&self, symbols: &HashMap<String, i64>) -> String {
+        match self {
+            Expr::Symbol(name) if !symbols.contains_key(name) => name.clone(),
+            Expr::Unary(_, expr) => expr.first_unknown(symbols),
+            Expr::Binary(_, lhs, rhs) => {
+                let lhs = lhs.first_unknown(symbols);
+                if lhs.is_empty() {
+                    rhs.first_unknown(symbols)
+                } else {
+                    lhs
+                }
+            }
+            _ => String::new(),
+        }
+    }
+}
+
+struct ExprParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn binary_op(&mut self) -> Option<(BinaryOp, usize)> {
+        let next = self.chars.get(self.pos + 1).copied();
+        Some(match (self.peek()?, next) {
+            ('<', Some('<')) => (BinaryOp::Shl, 2),
+            ('>', Some('>')) => (BinaryOp::Shr, 2),
+            ('|', _) => (BinaryOp::Or, 1),
+            ('^', _) => (BinaryOp::Xor, 1),
+            ('&', _) => (BinaryOp::And, 1),
+            ('+', _) => (BinaryOp::Add, 1),
+            ('-', _) => (BinaryOp::Sub, 1),
+            ('*', _) => (BinaryOp::Mul, 1),
+            ('/', _) => (BinaryOp::Div, 1),
+            ('%', _) => (BinaryOp::Mod, 1),
+            _ => return None,
+        })
+    }
+
+    /**
+     * Precedence climbing over left associative operators.
+     */
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some((op, len)) = self.binary_op() {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.pos += len;
+            let rhs = self.binary(op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let op = match self.peek() {
+            Some('-') => UnaryOp::Neg,
+            Some('~') => UnaryOp::Not,
+            Some('<') => UnaryOp::Low,
+            Some('>') => UnaryOp::High,
+            _ => return self.primary(),
+        };
+        self.pos += 1;
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let c = self.peek().ok_or("missing value")?;
+        let start = self.pos;
+        match c {
+            '(' => {
+                self.pos += 1;
+                let expr = self.binary(0)?;
+                if self.peek() != Some(')') {
+                    return Err("missing )".to_string());
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            '*' => {
+                self.pos += 1;
+                Ok(Expr::CurrentAddress)
+            }
+            '\'' => {
+                let value = self.chars.get(self.pos + 1).copied();
+                if self.chars.get(self.pos + 2) != Some(&'\'') {
+                    return Err("unterminated character literal".to_string());
+                }
+                self.pos += 3;
+                Ok(Expr::Number(value.unwrap() as i64))
+            }
+            '$' | '%' => {
+                self.pos += 1;
+                let radix = if c == '$' { 16 } else { 2 };
+                self.number(radix, start)
+            }
+            '0' if matches!(self.chars.get(self.pos + 1), Some('x') | Some('X')) => {
+                self.pos += 2;
+                self.number(16, start)
+            }
+            c if c.is_ascii_digit() => self.number(10, start),
+            c if is_ident_start(c) => {
+                while self.chars.get(self.pos).is_some_and(|&c| is_ident_char(c)) {
+                    self.pos += 1;
+                }
+                Ok(Expr::Symbol(self.chars[start..self.pos].iter().collect()))
+            }
+            c => Err(format!("unexpected '{c}'")),
+        }
+    }
+
+    fn number(&mut self, radix: u32, start: usize) -> Result<Expr, String> {
+        let digits_start = self.pos;
+        while self.chars.get(self.pos).is_some_and(|c| c.is_digit(radix)) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[digits_start..self.pos].iter().collect();
+        i64::from_str_radix(&digits, radix)
+            .map(Expr::Number)
+            .map_err(|_| {
+                let text: String = self.chars[start..self.pos].iter().collect();
+                format!("invalid number {text}")
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bytes(source: &str) -> Vec<u8> {
+        assemble(source).unwrap().to_binary()
+    }
+
+    #[test]
+    fn test_addressing_modes() {
+        let source = "
+            lda #$05
+            lda $10
+            lda $10,x
+            ldx $10,y
+            lda $1234
+            lda $1234,x
+            lda $1234,y
+            lda ($20,x)
+            lda ($20),y
+            asl
+            asl a
+            jmp ($0300)
+            lda a:$10
+            sta $10,y
+        ";
+        assert_eq!(
+            bytes(source),
+            vec![
+                0xa9, 0x05, 0xa5, 0x10, 0xb5, 0x10, 0xb6, 0x10, 0xad, 0x34, 0x12, 0xbd, 0x34, 0x12,
+                0xb9, 0x34, 0x12, 0xa1, 0x20, 0xb1, 0x20, 0x0a, 0x0a, 0x6c, 0x00, 0x03, 0xad, 0x10,
+                0x00, 0x99, 0x10, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_labels_and_forward_references() {
+        let source = "
+            .org $8000
+            reset: jmp start
+            start:
+              ldx #count
+            loop:
+              dex
+              bne loop
+              lda table,x
+              beq reset
+            table: .byte 1, 2, \"hi\"
+            .word table, $beef
+            count = 3
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(program.label("start"), Some(0x8003));
+        assert_eq!(
+            program.to_binary(),
+            vec![
+                0x4c, 0x03, 0x80, 0xa2, 0x03, 0xca, 0xd0, 0xfd, 0xbd, 0x0d, 0x80, 0xf0, 0xf3, 0x01,
+                0x02, b'h', b'i', 0x0d, 0x80, 0xef, 0xbe,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expressions() {
+        let source = "
+            define base $1234
+            lda #<base
+            lda #>base
+            lda #(1 + 2) * 3
+            lda #%1010 | 1 << 4
+            lda #'A' - 1
+            .word * + 2
+            lda #-1
+        ";
+        assert_eq!(
+            bytes(source),
+            vec![
+                0xa9, 0x34, 0xa9, 0x12, 0xa9, 0x09, 0xa9, 0x1a, 0xa9, 0x40, 0x0c, 0x06, 0xa9, 0xff,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unofficial_opcodes() {
+        assert_eq!(
+            bytes("lax $10\nnop #$01\nnop\ndcp $10,x"),
+            vec![0xa7, 0x10, 0x80, 0x01, 0xea, 0xd7, 0x10]
+        );
+    }
+
+    fn error(source: &str) -> String {
+        assemble(source).err().unwrap()
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(error("lda missing").contains("unknown symbol missing"));
+        assert!(error("foo #1").contains("unknown instruction"));
+        assert!(error("lda ($10,y)").contains("line 1"));
+        assert!(error("x: nop\nx: nop").contains("line 2"));
+        let far_branch = format!("loop: {}\nbne loop", "nop\n".repeat(200));
+        assert!(error(&far_branch).contains("branch target"));
+        assert!(error(".word $7FFFFFFFFFFFFFFF*2").contains("overflows"));
+        assert!(error("lda #-(-$7FFFFFFFFFFFFFFF-1)").contains("overflows"));
+    }
+
+    #[test]
+    fn test_ines_image() {
+        let program = assemble(".org $c000\nnmi: rti\nreset: jmp reset").unwrap();
+        let image = program.to_ines().unwrap();
+        assert_eq!(image.len(), 16 + 2 * PRG_ROM_BANK_SIZE + CHR_ROM_BANK_SIZE);
+        let rom = crate::rom::Rom::new(&image).unwrap();
+        assert_eq!(rom.prg_rom[0x4000..0x4004], [0x40, 0x4c, 0x01, 0xc0]);
+        assert_eq!(rom.prg_rom[0x7ffa..], [0x00, 0xc0, 0x01, 0xc0, 0x01, 0xc0]);
+
+        assert!(assemble(".org $0600\nnop").unwrap().to_ines().is_err());
+    }
+
+    #[test]
+    fn test_snake_source() {
+        let program = assemble_file("snake.asm").unwrap();
+        assert_eq!(program.origin(), 0x0600);
+        let binary = program.to_binary();
+        assert_eq!(binary.len(), 0x135);
+        assert_eq!(binary[..6], [0x20, 0x06, 0x06, 0x20, 0x38, 0x06]);
+        assert_eq!(program.label("gameOver"), Some(0x0735));
+    }
+}
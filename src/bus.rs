@@ -1,15 +1,52 @@
-use crate::{cpu::Mem, ppu::PPU, rom::Rom};
+use crate::{
+    cdl::SharedLogger,
+    cheats::Cheat,
+    cpu::{AddressingMode, Mem, Peek},
+    frame::Frame,
+    joypad::{Joypad, JoypadButton, FOUR_SCORE_SIGNATURES},
+    opcodes::get_opcode_details,
+    ppu::PPU,
+    region::Region,
+    rom::{Rom, PRG_ROM_BANK_SIZE},
+};
 const RAM: u16 = 0x0000;
 const RAM_MIRRORS_END: u16 = 0x1FFF;
 const PPU_REGISTERS: u16 = 0x2000;
 const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const APU_REGISTERS: u16 = 0x4000;
+const OAM_DMA: u16 = 0x4014;
+const JOYPAD_1: u16 = 0x4016;
+const JOYPAD_2: u16 = 0x4017;
+const APU_REGISTERS_END: u16 = 0x4017;
+pub const PRG_RAM: u16 = 0x6000;
+pub const PRG_RAM_END: u16 = 0x7FFF;
 pub const ROM_START: u16 = 0x8000;
 
+/**
+ * Internal RAM with its mirrors, or cartridge RAM.
+ */
+pub fn is_ram(addr: u16) -> bool {
+    matches!(addr, RAM..=RAM_MIRRORS_END | PRG_RAM..=PRG_RAM_END)
+}
+
 pub struct Bus {
     vram: [u8; 2048],
     ppu: PPU,
     prg_rom: Vec<u8>,
+    /// cartridge RAM (work RAM, or battery backed save RAM)
+    prg_ram: [u8; 0x2000],
+    /// bytes written back at the start of every vblank, for cheats
+    frozen: Vec<(u16, u8)>,
+    cheats: Vec<Cheat>,
+    /// switches all cheats off without forgetting which are on
+    cheats_enabled: bool,
     cycles: usize,
+    cdl: Option<SharedLogger>,
+    joypads: [Joypad; 2],
+    frame_complete: bool,
+    region: Region,
+    /// PPU dots owed from a fraction of a CPU cycle (PAL runs 3.2 dots per cycle)
+    dot_remainder: usize,
 }
 
 enum BusDevice {
@@ -33,29 +70,259 @@ impl Bus {
             vram: [0; 2048],
             ppu,
             prg_rom: rom.prg_rom,
+            prg_ram: [0; 0x2000],
+            frozen: vec![],
+            cheats: vec![],
+            cheats_enabled: true,
             cycles: 0,
+            cdl: None,
+            joypads: [Joypad::new(), Joypad::new()],
+            frame_complete: false,
+            region: Region::Ntsc,
+            dot_remainder: 0,
         }
     }
 
-    fn prg_read(&self, addr: u16) -> u8 {
+    pub fn attach_cdl(&mut self, logger: SharedLogger) {
+        self.ppu.attach_cdl(logger.clone());
+        self.cdl = Some(logger);
+    }
+
+    /**
+     * Where a CPU address lands in PRG ROM. Banking mappers hook in here so the
+     * code/data logger records offsets into the image rather than CPU addresses.
+     */
+    fn prg_offset(&self, addr: u16) -> usize {
         let rom_relative_addr = addr - ROM_START;
-        self.prg_rom[(if rom_relative_addr >= 0x4000 && self.prg_rom.len() == 0x4000 {
+        (if rom_relative_addr >= 0x4000 && self.prg_rom.len() == 0x4000 {
             rom_relative_addr % 0x4000
         } else {
             rom_relative_addr
-        }) as usize]
+        }) as usize
+    }
+
+    fn prg_read(&self, addr: u16) -> u8 {
+        let data = self.prg_rom[self.prg_offset(addr)];
+        self.active_cheats()
+            .find_map(|cheat| cheat.replace(addr, data))
+            .unwrap_or(data)
+    }
+
+    /**
+     * Reads an opcode. Unlike `mem_read`, the byte and its operands are logged as code.
+     */
+    pub fn fetch_opcode(&mut self, addr: u16) -> u8 {
+        if addr < ROM_START {
+            return self.mem_read(addr);
+        }
+        let opcode = self.prg_read(addr);
+        if let Some(cdl) = &self.cdl {
+            let (len, indirect) = match get_opcode_details(&opcode) {
+                Some(details) => (
+                    1 + details.additional_bytes as usize,
+                    matches!(
+                        details.mode,
+                        AddressingMode::Indirect_X | AddressingMode::Indirect_Y
+                    ),
+                ),
+                None => (1, false),
+            };
+            cdl.borrow_mut()
+                .log_instruction(self.prg_offset(addr), addr, len, indirect);
+        }
+        opcode
+    }
+
+    pub fn log_indirect_jump(&mut self, target: u16) {
+        if target < ROM_START {
+            return;
+        }
+        if let Some(cdl) = &self.cdl {
+            cdl.borrow_mut()
+                .log_indirect_jump(self.prg_offset(target), target);
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.set_region(region);
     }
 
     pub fn tick(&mut self, cycles: u8) {
-        self.ppu.tick(cycles * 3);
+        let (numerator, denominator) = self.region.dots_per_cycle();
+        let dots = cycles as usize * numerator + self.dot_remainder;
+        self.dot_remainder = dots % denominator;
+        if self.ppu.tick((dots / denominator) as u8) {
+            self.frame_complete = true;
+            let frozen: Vec<(u16, u8)> = (self.frozen.iter().copied())
+                .chain(
+                    self.active_cheats()
+                        .filter(|cheat| !cheat.is_rom())
+                        .map(|cheat| (cheat.addr, cheat.value)),
+                )
+                .collect();
+            for (addr, data) in frozen {
+                // only RAM gets frozen, which `poke` always writes
+                let _ = self.poke(addr, data);
+            }
+        }
         self.cycles += cycles as usize;
     }
 
+    /**
+     * True once per frame, after the PPU has entered vblank.
+     */
+    pub fn take_frame(&mut self) -> bool {
+        std::mem::take(&mut self.frame_complete)
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        self.ppu.render(frame);
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        &self.ppu
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut PPU {
+        &mut self.ppu
+    }
+
+    /**
+     * Keeps `addr` at `data`: it is written now and again at the start of every
+     * vblank, before the program's NMI handler runs. Only RAM can be frozen.
+     */
+    pub fn freeze(&mut self, addr: u16, data: u8) -> Result<(), String> {
+        if !is_ram(addr) {
+            return Err(format!("${addr:04X} is not RAM"));
+        }
+        self.unfreeze(addr);
+        self.frozen.push((addr, data));
+        self.poke(addr, data)
+    }
+
+    pub fn unfreeze(&mut self, addr: u16) {
+        self.frozen.retain(|&(frozen, _)| frozen != addr);
+    }
+
+    pub fn frozen(&self) -> &[(u16, u8)] {
+        &self.frozen
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    /**
+     * ROM cheats apply from the next read, RAM ones from the next vblank.
+     */
+    pub fn cheats_mut(&mut self) -> &mut Vec<Cheat> {
+        &mut self.cheats
+    }
+
+    pub fn cheats_enabled(&self) -> bool {
+        self.cheats_enabled
+    }
+
+    pub fn set_cheats_enabled(&mut self, enabled: bool) {
+        self.cheats_enabled = enabled;
+    }
+
+    fn active_cheats(&self) -> impl Iterator<Item = &Cheat> {
+        (self.cheats.iter()).filter(|cheat| self.cheats_enabled && cheat.enabled)
+    }
+
+    pub fn prg_rom(&self) -> &[u8] {
+        &self.prg_rom
+    }
+
+    pub fn prg_rom_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_rom
+    }
+
+    /**
+     * Buttons held by `player` 0 to 3. Players 3 and 4 share the ports with 1 and 2
+     * through a Four Score, and are ignored without one.
+     */
+    pub fn set_buttons(&mut self, player: usize, buttons: JoypadButton) {
+        match player {
+            0 | 1 => self.joypads[player].set_buttons(buttons),
+            _ => self.joypads[player - 2].set_second_buttons(buttons),
+        }
+    }
+
+    pub fn set_four_score(&mut self, attached: bool) {
+        for (joypad, signature) in self.joypads.iter_mut().zip(FOUR_SCORE_SIGNATURES) {
+            if attached {
+                joypad.attach_four_score(signature);
+            } else {
+                joypad.detach_four_score();
+            }
+        }
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    /**
+     * (scanline, dot) the PPU is currently on.
+     */
+    pub fn ppu_position(&self) -> (u16, usize) {
+        (self.ppu.scanline(), self.ppu.dot())
+    }
+
+    /**
+     * The 16KiB PRG bank mapped at `addr`, or `None` outside of ROM.
+     */
+    pub fn prg_bank(&self, addr: u16) -> Option<usize> {
+        if addr < ROM_START {
+            return None;
+        }
+        Some(self.prg_offset(addr) / PRG_ROM_BANK_SIZE)
+    }
+
     pub fn check_nmi(&mut self) -> Option<bool> {
         self.ppu.poll_nmi()
     }
 }
 
+/**
+ * ROM can be patched through `poke`. The APU's registers read as 0 until there is
+ * an APU.
+ */
+impl Peek for Bus {
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            RAM..=RAM_MIRRORS_END => self.vram[BusDevice::CPU.mirror_addr(addr) as usize],
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
+                self.ppu.peek(BusDevice::PPU.mirror_addr(addr))
+            }
+            JOYPAD_1 => self.joypads[0].peek(),
+            JOYPAD_2 => self.joypads[1].peek(),
+            PRG_RAM..=PRG_RAM_END => self.prg_ram[(addr - PRG_RAM) as usize],
+            ROM_START..=0xFFFF => self.prg_read(addr),
+            _ => 0,
+        }
+    }
+
+    fn poke(&mut self, addr: u16, data: u8) -> Result<(), String> {
+        match addr {
+            RAM..=RAM_MIRRORS_END => self.vram[BusDevice::CPU.mirror_addr(addr) as usize] = data,
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
+                return self.ppu.poke(BusDevice::PPU.mirror_addr(addr), data)
+            }
+            PRG_RAM..=PRG_RAM_END => self.prg_ram[(addr - PRG_RAM) as usize] = data,
+            ROM_START..=0xFFFF => {
+                let offset = self.prg_offset(addr);
+                self.prg_rom[offset] = data;
+            }
+            _ => return Err(format!("${addr:04X} is a register")),
+        }
+        Ok(())
+    }
+}
+
 impl Mem for Bus {
     fn mem_read(&mut self, addr: u16) -> u8 {
         match addr {
@@ -63,7 +330,16 @@ impl Mem for Bus {
             PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
                 self.ppu.mem_read(BusDevice::PPU.mirror_addr(addr))
             }
-            ROM_START..=0xFFFF => self.prg_read(addr),
+            JOYPAD_1 => self.joypads[0].read(),
+            JOYPAD_2 => self.joypads[1].read(),
+            APU_REGISTERS..=APU_REGISTERS_END => 0,
+            PRG_RAM..=PRG_RAM_END => self.prg_ram[(addr - PRG_RAM) as usize],
+            ROM_START..=0xFFFF => {
+                if let Some(cdl) = &self.cdl {
+                    cdl.borrow_mut().log_prg_read(self.prg_offset(addr), addr);
+                }
+                self.prg_read(addr)
+            }
             _ => {
                 println!("{}", format!("Out of range: {}", addr));
                 0
@@ -77,6 +353,21 @@ impl Mem for Bus {
             PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
                 self.ppu.mem_write(BusDevice::PPU.mirror_addr(addr), data)
             }
+            OAM_DMA => {
+                let page = (data as u16) << 8;
+                let mut buffer = [0; 256];
+                for (i, byte) in buffer.iter_mut().enumerate() {
+                    *byte = self.mem_read(page + i as u16);
+                }
+                self.ppu.write_oam_dma(&buffer);
+            }
+            JOYPAD_1 => {
+                // the strobe line goes to both controllers
+                self.joypads[0].write(data);
+                self.joypads[1].write(data);
+            }
+            APU_REGISTERS..=APU_REGISTERS_END => { /* no APU yet */ }
+            PRG_RAM..=PRG_RAM_END => self.prg_ram[(addr - PRG_RAM) as usize] = data,
             ROM_START..=0xFFFF => panic!(
                 "{}",
                 format!("Invalid request to write to ROM PRG: {}", addr)
@@ -85,3 +376,35 @@ impl Mem for Bus {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rom::test::test_rom;
+
+    #[test]
+    fn test_oam_dma_copies_a_page() {
+        let mut bus = Bus::new(test_rom());
+        for i in 0..=0xFF {
+            bus.mem_write(0x0200 + i, i as u8);
+        }
+        // the copy starts at OAMADDR and wraps around
+        bus.mem_write(0x2003, 4);
+        bus.mem_write(OAM_DMA, 0x02);
+        assert_eq!(bus.mem_read(0x2004), 0x00);
+        bus.mem_write(0x2003, 0);
+        assert_eq!(bus.mem_read(0x2004), 0xFC);
+    }
+
+    #[test]
+    fn test_strobe_reaches_both_controllers() {
+        let mut bus = Bus::new(test_rom());
+        bus.set_buttons(0, JoypadButton::BUTTON_A);
+        bus.set_buttons(1, JoypadButton::BUTTON_B);
+        bus.mem_write(JOYPAD_1, 1);
+        bus.mem_write(JOYPAD_1, 0);
+        let first = [bus.mem_read(JOYPAD_1), bus.mem_read(JOYPAD_1)];
+        let second = [bus.mem_read(JOYPAD_2), bus.mem_read(JOYPAD_2)];
+        assert_eq!((first, second), ([1, 0], [0, 1]));
+    }
+}
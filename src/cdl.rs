@@ -0,0 +1,218 @@
+use std::{cell::RefCell, ops::Range, rc::Rc};
+
+use bitflags::bitflags;
+
+bitflags! {
+    // x P d c A A D C
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct PrgAccess: u8 {
+        const CODE          = 0b0000_0001;
+        const DATA          = 0b0000_0010;
+        const BANK_LO       = 0b0000_0100;
+        const BANK_HI       = 0b0000_1000;
+        const INDIRECT_CODE = 0b0001_0000;
+        const INDIRECT_DATA = 0b0010_0000;
+        const PCM_DATA      = 0b0100_0000;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct ChrAccess: u8 {
+        const DRAWN = 0b0000_0001;
+        const READ  = 0b0000_0010;
+    }
+}
+
+pub type SharedLogger = Rc<RefCell<CodeDataLogger>>;
+
+/**
+ * Code/Data Logger, writing the same .cdl layout as FCEUX: one byte per PRG ROM byte
+ * followed by one byte per CHR ROM byte.
+ * https://fceux.com/web/help/CodeDataLogger.html
+ */
+pub struct CodeDataLogger {
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+    /// PRG offsets of the instruction being executed, so operand fetches are not counted as data
+    instruction: Range<usize>,
+    indirect: bool,
+}
+
+impl CodeDataLogger {
+    pub fn new(prg_len: usize, chr_len: usize) -> Self {
+        CodeDataLogger {
+            prg: vec![0; prg_len],
+            chr: vec![0; chr_len],
+            instruction: 0..0,
+            indirect: false,
+        }
+    }
+
+    /**
+     * Continues logging on top of an existing .cdl file, as long as it was made for
+     * a ROM of the same size. Otherwise starts from scratch.
+     */
+    pub fn resume(path: &str, prg_len: usize, chr_len: usize) -> Self {
+        let mut logger = CodeDataLogger::new(prg_len, chr_len);
+        if let Ok(bytes) = std::fs::read(path) {
+            if bytes.len() == prg_len + chr_len {
+                logger.prg.copy_from_slice(&bytes[..prg_len]);
+                logger.chr.copy_from_slice(&bytes[prg_len..]);
+            } else {
+                println!("{path} does not match this ROM, starting a new log");
+            }
+        }
+        logger
+    }
+
+    pub fn shared(self) -> SharedLogger {
+        Rc::new(RefCell::new(self))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut bytes = self.prg.clone();
+        bytes.extend(&self.chr);
+        std::fs::write(path, bytes).map_err(|e| format!("Cannot write {path}: {e}"))
+    }
+
+    /**
+     * Marks the opcode and operand bytes of the instruction about to execute.
+     * `offset` is where `addr` lands in PRG ROM after banking.
+     */
+    pub fn log_instruction(&mut self, offset: usize, addr: u16, len: usize, indirect: bool) {
+        self.instruction = offset..offset + len;
+        self.indirect = indirect;
+        for i in self.instruction.clone() {
+            self.mark_prg(i, addr, PrgAccess::CODE);
+        }
+    }
+
+    /**
+     * The target of an indirect JMP.
+     */
+    pub fn log_indirect_jump(&mut self, offset: usize, addr: u16) {
+        self.mark_prg(offset, addr, PrgAccess::INDIRECT_CODE);
+    }
+
+    pub fn log_prg_read(&mut self, offset: usize, addr: u16) {
+        if self.instruction.contains(&offset) {
+            return;
+        }
+        let mut access = PrgAccess::DATA;
+        if self.indirect {
+            access |= PrgAccess::INDIRECT_DATA;
+        }
+        self.mark_prg(offset, addr, access);
+    }
+
+    pub fn log_chr(&mut self, offset: usize, access: ChrAccess) {
+        if let Some(byte) = self.chr.get_mut(offset) {
+            *byte |= access.bits();
+        }
+    }
+
+    /**
+     * Bits 2-3 record which 8KiB window ($8000/$A000/$C000/$E000) the byte was mapped into.
+     */
+    fn mark_prg(&mut self, offset: usize, addr: u16, access: PrgAccess) {
+        let bank = (((addr >> 13) & 0b11) as u8) << 2;
+        if let Some(byte) = self.prg.get_mut(offset) {
+            *byte |= access.bits() | bank;
+        }
+    }
+
+    /**
+     * (code bytes, data bytes, unlogged bytes) of PRG ROM.
+     */
+    pub fn prg_coverage(&self) -> (usize, usize, usize) {
+        let (mut code, mut data, mut unlogged) = (0, 0, 0);
+        for &byte in &self.prg {
+            let access = PrgAccess::from_bits_truncate(byte);
+            if access.contains(PrgAccess::CODE) {
+                code += 1;
+            }
+            if access.contains(PrgAccess::DATA) {
+                data += 1;
+            }
+            if !access.intersects(PrgAccess::CODE | PrgAccess::DATA) {
+                unlogged += 1;
+            }
+        }
+        (code, data, unlogged)
+    }
+
+    /**
+     * (drawn bytes, read bytes, unused bytes) of CHR ROM.
+     */
+    pub fn chr_coverage(&self) -> (usize, usize, usize) {
+        let count = |access: ChrAccess| {
+            (self.chr.iter())
+                .filter(|&&byte| ChrAccess::from_bits_truncate(byte).intersects(access))
+                .count()
+        };
+        let unused = self.chr.len() - count(ChrAccess::all());
+        (count(ChrAccess::DRAWN), count(ChrAccess::READ), unused)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::bus::Bus;
+    use crate::cpu::{Mem, CPU};
+    use crate::emulator::Emulator;
+    use crate::region::Region;
+    use crate::rom::{nrom_image, Rom, CHR_ROM_BANK_SIZE, PRG_ROM_BANK_SIZE};
+
+    #[test]
+    fn test_code_and_data_are_logged() {
+        let mut prg = vec![0; 2 * PRG_ROM_BANK_SIZE];
+        // $C000: LDA $C010; LDX #$00; LDA ($10),Y; BRK
+        prg[0x4000..0x4008].copy_from_slice(&[0xad, 0x10, 0xc0, 0xa2, 0x00, 0xb1, 0x10, 0x00]);
+        let rom = Rom::new(&nrom_image(&prg, &vec![0; CHR_ROM_BANK_SIZE])).unwrap();
+
+        let logger = CodeDataLogger::new(2 * PRG_ROM_BANK_SIZE, CHR_ROM_BANK_SIZE).shared();
+        let mut bus = Bus::new(rom);
+        bus.attach_cdl(logger.clone());
+        let mut cpu = CPU::new(bus);
+        // ($10) points at $C020
+        cpu.mem_write(0x10, 0x20);
+        cpu.mem_write(0x11, 0xc0);
+        cpu.program_counter = 0xc000;
+        cpu.run_with_callback(|_| {});
+
+        let logger = logger.borrow();
+        let bank = 0b1000; // $C000-$DFFF
+        assert_eq!(logger.prg[0x4000], PrgAccess::CODE.bits() | bank);
+        assert_eq!(logger.prg[0x4002], PrgAccess::CODE.bits() | bank);
+        assert_eq!(logger.prg[0x4010], PrgAccess::DATA.bits() | bank);
+        assert_eq!(
+            logger.prg[0x4020],
+            (PrgAccess::DATA | PrgAccess::INDIRECT_DATA).bits() | bank
+        );
+        assert_eq!(logger.prg[0x4011], 0);
+        let (code, data, _) = logger.prg_coverage();
+        assert_eq!((code, data), (8, 2));
+    }
+
+    #[test]
+    fn test_drawn_tiles_are_logged() {
+        // the nametables are blank, so only tile 0 is drawn
+        let program = "  .org $8000\nreset:\n  lda #%00001010\n  sta $2001\nloop:\n  jmp loop\n";
+        let rom = Rom::new(&assemble(program).unwrap().to_ines().unwrap()).unwrap();
+        let logger = CodeDataLogger::new(rom.prg_rom.len(), rom.chr_rom.len()).shared();
+        let mut emulator = Emulator::new(rom, Region::Ntsc);
+        emulator.cpu.bus.attach_cdl(logger.clone());
+        for _ in 0..2 {
+            emulator.run_frame().unwrap();
+        }
+
+        let logger = logger.borrow();
+        assert_eq!(logger.chr[0], ChrAccess::DRAWN.bits());
+        assert_eq!(logger.chr[15], ChrAccess::DRAWN.bits());
+        assert_eq!(logger.chr[16], 0);
+        assert_eq!(logger.chr_coverage(), (16, 0, CHR_ROM_BANK_SIZE - 16));
+    }
+}
@@ -0,0 +1,227 @@
+use std::path::{Path, PathBuf};
+
+use crate::bus::{is_ram, ROM_START};
+
+/// Game Genie letters, in the order of the values they stand for
+const GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+/**
+ * One code from a cheat file. Codes for ROM addresses change what the CPU reads
+ * there; codes for RAM freeze it.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheat {
+    /// as typed, e.g. `SXIOPO` or `0075:09`
+    pub code: String,
+    pub name: String,
+    pub enabled: bool,
+    pub addr: u16,
+    pub value: u8,
+    /// only replace reads that would have returned this
+    pub compare: Option<u8>,
+}
+
+impl Cheat {
+    /**
+     * A 6 or 8 letter Game Genie code, or `AAAA:VV` (Pro Action Replay style) where
+     * `AAAA` is a RAM or ROM address. ROM codes can add a compare value, `AAAA?CC:VV`.
+     */
+    pub fn parse(code: &str, name: &str) -> Result<Self, String> {
+        let (addr, value, compare) = match code.split_once(':') {
+            Some(_) => decode_raw(code)?,
+            None => decode_game_genie(code)?,
+        };
+        Ok(Cheat {
+            code: code.to_uppercase(),
+            name: name.to_string(),
+            enabled: true,
+            addr,
+            value,
+            compare,
+        })
+    }
+
+    pub fn is_rom(&self) -> bool {
+        self.addr >= ROM_START
+    }
+
+    /**
+     * What a read of `addr` returns with the cheat on, where it would have been `data`.
+     */
+    pub fn replace(&self, addr: u16, data: u8) -> Option<u8> {
+        (addr == self.addr && self.compare.is_none_or(|compare| compare == data))
+            .then_some(self.value)
+    }
+}
+
+/**
+ * Game Genie codes scramble the bits of an address in $8000-$FFFF, a value and,
+ * with 8 letters, a compare value. https://www.nesdev.org/wiki/Game_Genie
+ */
+pub fn decode_game_genie(code: &str) -> Result<(u16, u8, Option<u8>), String> {
+    let n: Vec<u16> = code
+        .chars()
+        .map(|letter| {
+            GAME_GENIE_LETTERS
+                .find(letter.to_ascii_uppercase())
+                .map(|value| value as u16)
+                .ok_or(format!(
+                    "Invalid Game Genie code {code}: no letter {letter}"
+                ))
+        })
+        .collect::<Result<_, _>>()?;
+    if n.len() != 6 && n.len() != 8 {
+        return Err(format!(
+            "Invalid Game Genie code {code}: expected 6 or 8 letters"
+        ));
+    }
+    let addr = 0x8000
+        | ((n[3] & 7) << 12)
+        | ((n[5] & 7) << 8)
+        | ((n[4] & 8) << 8)
+        | ((n[2] & 7) << 4)
+        | ((n[1] & 8) << 4)
+        | (n[4] & 7)
+        | (n[3] & 8);
+    // the last letter's high bit belongs to the value in both lengths
+    let last = n[n.len() - 1];
+    let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7) | (last & 8);
+    let compare =
+        (n.len() == 8).then(|| ((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8));
+    Ok((addr, value as u8, compare.map(|compare| compare as u8)))
+}
+
+/**
+ * `AAAA:VV` or `AAAA?CC:VV`, in hex.
+ */
+pub fn decode_raw(code: &str) -> Result<(u16, u8, Option<u8>), String> {
+    let invalid = || format!("Invalid code {code}: expected AAAA:VV or AAAA?CC:VV");
+    let (addr, value) = code.split_once(':').ok_or_else(invalid)?;
+    let (addr, compare) = match addr.split_once('?') {
+        Some((addr, compare)) => (addr, Some(compare)),
+        None => (addr, None),
+    };
+    let addr = u16::from_str_radix(addr, 16).map_err(|_| invalid())?;
+    let value = u8::from_str_radix(value, 16).map_err(|_| invalid())?;
+    let compare = compare
+        .map(|compare| u8::from_str_radix(compare, 16).map_err(|_| invalid()))
+        .transpose()?;
+    match (addr, compare) {
+        (ROM_START.., _) => Ok((addr, value, compare)),
+        (_, None) if is_ram(addr) => Ok((addr, value, None)),
+        (_, None) => Err(format!(
+            "Invalid code {code}: ${addr:04X} is not RAM or ROM"
+        )),
+        (_, Some(_)) => Err(format!("Invalid code {code}: only ROM codes compare")),
+    }
+}
+
+/**
+ * The cheats for `game.nes` live in `game.cht`, next to it.
+ */
+pub fn path_for_rom(rom_path: &str) -> PathBuf {
+    Path::new(rom_path).with_extension("cht")
+}
+
+/**
+ * A cheat file has a code per line, then an optional name. `#` starts a comment,
+ * and a leading `-` turns a cheat off:
+ *
+ *     SXIOPO Infinite lives
+ *     -0075:09 Start with 9 lives
+ */
+pub fn parse_cheats(text: &str) -> Result<Vec<Cheat>, String> {
+    let mut cheats = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (enabled, line) = match line.strip_prefix('-') {
+            Some(line) => (false, line),
+            None => (true, line),
+        };
+        let (code, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let mut cheat =
+            Cheat::parse(code, name.trim()).map_err(|e| format!("line {}: {e}", number + 1))?;
+        cheat.enabled = enabled;
+        cheats.push(cheat);
+    }
+    Ok(cheats)
+}
+
+pub fn load_cheats(path: &Path) -> Result<Vec<Cheat>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+    parse_cheats(&text).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+pub fn save_cheats(path: &Path, cheats: &[Cheat]) -> Result<(), String> {
+    let text: String = cheats
+        .iter()
+        .map(|cheat| {
+            let off = if cheat.enabled { "" } else { "-" };
+            format!("{off}{} {}\n", cheat.code, cheat.name)
+        })
+        .collect();
+    std::fs::write(path, text).map_err(|e| format!("Cannot write {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bus::Bus,
+        cpu::{Mem, Peek},
+        rom::test::test_rom,
+    };
+
+    #[test]
+    fn test_decoding() {
+        // Super Mario Bros.: infinite lives
+        assert_eq!(decode_game_genie("SXIOPO"), Ok((0x91D9, 0xAD, None)));
+        let (addr, value, compare) = decode_game_genie("SXIOPOVN").unwrap();
+        assert_eq!((addr, value), (0x91D9, 0xAD));
+        assert!(compare.is_some());
+        assert!(decode_game_genie("SXIOP").is_err());
+        assert!(decode_game_genie("SXIOPB").is_err());
+
+        assert_eq!(decode_raw("0075:09"), Ok((0x0075, 0x09, None)));
+        assert_eq!(decode_raw("91D9?DE:AD"), Ok((0x91D9, 0xAD, Some(0xDE))));
+        assert!(decode_raw("2002:00").is_err());
+        assert!(decode_raw("0075?01:09").is_err());
+    }
+
+    #[test]
+    fn test_cheat_files() {
+        let cheats =
+            parse_cheats("# Super Mario Bros.\nsxiopo Infinite lives\n-0075:09\n").unwrap();
+        assert_eq!(cheats.len(), 2);
+        assert_eq!(cheats[0].code, "SXIOPO");
+        assert_eq!(cheats[0].name, "Infinite lives");
+        assert!(!cheats[1].enabled);
+        assert_eq!(cheats[0].replace(0x91D9, 0xDE), Some(0xAD));
+        assert_eq!(cheats[0].replace(0x91DA, 0xDE), None);
+        assert_eq!(
+            parse_cheats("0075:09\nNOPE\n"),
+            Err("line 2: Invalid Game Genie code NOPE: expected 6 or 8 letters".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cheats_on_the_bus() {
+        // PRG ROM is all $01
+        let mut bus = Bus::new(test_rom());
+        *bus.cheats_mut() = parse_cheats("8000?01:05\n8001?02:05\n0300:63\n").unwrap();
+        assert_eq!(bus.mem_read(0x8000), 0x05);
+        assert_eq!(bus.peek(0x8000), 0x05);
+        assert_eq!(bus.mem_read(0x8001), 0x01);
+        while !bus.take_frame() {
+            bus.tick(1);
+        }
+        assert_eq!(bus.mem_read(0x0300), 0x63);
+
+        bus.set_cheats_enabled(false);
+        assert_eq!(bus.mem_read(0x8000), 0x01);
+    }
+}
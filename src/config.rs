@@ -0,0 +1,715 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    emulator::AUDIO_SAMPLE_RATE,
+    joypad::{JoypadButton, PLAYERS},
+    overscan::{Overscan, MAX_EDGE},
+    palette::NtscParams,
+    ppu::viewer::Viewer,
+    region::Region,
+    video::{ntsc::NtscPreset, scale::Scaler},
+};
+
+const RECENT_ROMS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    Pad(JoypadButton),
+    /// pressed and released over and over while held, at `InputConfig::turbo_rate`
+    Turbo(JoypadButton),
+}
+
+/// names used for controller buttons in `[playerN.keys]` and `[playerN.gamepad]`,
+/// in the order the rebinding prompt asks for them
+pub const BUTTON_NAMES: [(&str, Button); 10] = [
+    ("up", Button::Pad(JoypadButton::UP)),
+    ("down", Button::Pad(JoypadButton::DOWN)),
+    ("left", Button::Pad(JoypadButton::LEFT)),
+    ("right", Button::Pad(JoypadButton::RIGHT)),
+    ("select", Button::Pad(JoypadButton::SELECT)),
+    ("start", Button::Pad(JoypadButton::START)),
+    ("b", Button::Pad(JoypadButton::BUTTON_B)),
+    ("a", Button::Pad(JoypadButton::BUTTON_A)),
+    ("turbo_b", Button::Turbo(JoypadButton::BUTTON_B)),
+    ("turbo_a", Button::Turbo(JoypadButton::BUTTON_A)),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hotkey {
+    Quit,
+    ToggleTrace,
+    Pause,
+    FrameAdvance,
+    /// held down rather than pressed
+    FastForward,
+    SlowMotion,
+    /// walks through every button of a player, asking for a new key or pad button
+    Rebind,
+    Screenshot,
+    /// starts or stops recording video and sound
+    Record,
+    /// open or close a PPU viewer window
+    Viewer(Viewer),
+    /// colours the pattern table viewer with the next palette
+    ViewerPalette,
+    /// saves what the open viewers show as PNG
+    ExportViewers,
+    /// shows or hides the memory viewer in the terminal
+    MemoryViewer,
+    /// turns all cheats on or off
+    ToggleCheats,
+}
+
+const HOTKEY_NAMES: [(&str, Hotkey); 17] = [
+    ("quit", Hotkey::Quit),
+    ("toggle_trace", Hotkey::ToggleTrace),
+    ("pause", Hotkey::Pause),
+    ("frame_advance", Hotkey::FrameAdvance),
+    ("fast_forward", Hotkey::FastForward),
+    ("slow_motion", Hotkey::SlowMotion),
+    ("rebind", Hotkey::Rebind),
+    ("screenshot", Hotkey::Screenshot),
+    ("record", Hotkey::Record),
+    ("nametables", Hotkey::Viewer(Viewer::Nametables)),
+    ("pattern_tables", Hotkey::Viewer(Viewer::PatternTables)),
+    ("palette_viewer", Hotkey::Viewer(Viewer::Palette)),
+    ("oam_viewer", Hotkey::Viewer(Viewer::Oam)),
+    ("viewer_palette", Hotkey::ViewerPalette),
+    ("export_viewers", Hotkey::ExportViewers),
+    ("memory_viewer", Hotkey::MemoryViewer),
+    ("toggle_cheats", Hotkey::ToggleCheats),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoConfig {
+    pub scale: u32,
+    pub fullscreen: bool,
+    /// `none`, or the NTSC filter's `composite`, `svideo` or `rgb`
+    pub filter: String,
+    /// `none`, `integer`, `scale2x`, `scale3x` or `hq2x`
+    pub scaler: String,
+    /// percent the gaps between lines are darkened by, 0 for none
+    pub scanlines: u32,
+    /// stretch the picture to the 8:7 pixels a TV shows
+    pub aspect_8_7: bool,
+    /// `default`, `ntsc` to generate one from `[ntsc]`, or the path of a `.pal` file
+    pub palette: String,
+    /// also save the palette indices with each screenshot
+    pub screenshot_indices: bool,
+    /// `y4m`, `rgb`, or any extension ffmpeg can write, like `mp4`
+    pub record_format: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioConfig {
+    pub sample_rate: u32,
+    /// how much audio to keep queued ahead of the speakers
+    pub latency_ms: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputConfig {
+    /// plug a Four Score into the console for players 3 and 4
+    pub four_score: bool,
+    /// how far an analog stick has to move to press the d-pad, in percent
+    pub analog_threshold: u32,
+    /// turbo presses per second
+    pub turbo_rate: u32,
+}
+
+/**
+ * Edges hidden by region, unless the game has its own under `[overscan.games]`,
+ * keyed by ROM file name without the extension. Dendy consoles were sold with PAL
+ * sets, so they share `pal`.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverscanConfig {
+    pub ntsc: Overscan,
+    pub pal: Overscan,
+    pub games: Vec<(String, Overscan)>,
+}
+
+impl OverscanConfig {
+    pub fn for_game(&self, name: &str, region: Region) -> Overscan {
+        match self.games.iter().find(|(game, _)| game == name) {
+            Some(&(_, overscan)) => overscan,
+            None if region != Region::Ntsc => self.pal,
+            None => self.ntsc,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathConfig {
+    /// empty keeps files next to the ROM
+    pub saves: String,
+    pub states: String,
+    pub screenshots: String,
+    pub recordings: String,
+}
+
+/**
+ * Key and SDL game controller button names for one player, empty when unbound.
+ * Keys use SDL's names (https://wiki.libsdl.org/SDL2/SDL_Keycode), controller
+ * buttons SDL's `a`, `b`, `x`, `y`, `back`, `start`, `dpup`, `dpdown`, ...
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerConfig {
+    /// part of the name of the game controller this player uses; empty takes the
+    /// next one plugged in
+    pub device: String,
+    pub keys: Vec<(Button, String)>,
+    pub gamepad: Vec<(Button, String)>,
+}
+
+/**
+ * Settings kept in `config.toml` in the user's config directory. Only the parts of
+ * TOML the file itself uses are understood: tables, strings, numbers, booleans and
+ * single-line arrays.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub video: VideoConfig,
+    pub audio: AudioConfig,
+    pub input: InputConfig,
+    pub paths: PathConfig,
+    pub ntsc: NtscParams,
+    pub overscan: OverscanConfig,
+    pub hotkeys: Vec<(Hotkey, String)>,
+    pub players: [PlayerConfig; PLAYERS],
+    pub recent_roms: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let bindings = |names: [&str; 10]| {
+            BUTTON_NAMES
+                .iter()
+                .zip(names)
+                .map(|(&(_, button), name)| (button, name.to_string()))
+                .collect()
+        };
+        let player = |keys| PlayerConfig {
+            device: String::new(),
+            keys: bindings(keys),
+            gamepad: bindings([
+                "dpup", "dpdown", "dpleft", "dpright", "back", "start", "a", "b", "x", "y",
+            ]),
+        };
+        Config {
+            video: VideoConfig {
+                scale: 3,
+                fullscreen: false,
+                filter: "none".to_string(),
+                scaler: "none".to_string(),
+                scanlines: 0,
+                aspect_8_7: false,
+                palette: "default".to_string(),
+                screenshot_indices: false,
+                record_format: "y4m".to_string(),
+            },
+            audio: AudioConfig {
+                sample_rate: AUDIO_SAMPLE_RATE,
+                latency_ms: 50,
+            },
+            input: InputConfig {
+                four_score: false,
+                analog_threshold: 50,
+                turbo_rate: 15,
+            },
+            paths: PathConfig {
+                saves: String::new(),
+                states: String::new(),
+                screenshots: String::new(),
+                recordings: String::new(),
+            },
+            ntsc: NtscParams::default(),
+            overscan: OverscanConfig {
+                ntsc: Overscan::for_region(Region::Ntsc),
+                pal: Overscan::for_region(Region::Pal),
+                games: vec![],
+            },
+            hotkeys: HOTKEY_NAMES
+                .iter()
+                .zip([
+                    "Escape", "F8", "P", "\\", "Tab", "-", "F2", "F12", "F9", "F3", "F4", "F5",
+                    "F6", "F7", "F10", "F11", "F1",
+                ])
+                .map(|(&(_, hotkey), key)| (hotkey, key.to_string()))
+                .collect(),
+            players: [
+                player([
+                    "Up", "Down", "Left", "Right", "Space", "Return", "S", "A", "W", "Q",
+                ]),
+                player([""; 10]),
+                player([""; 10]),
+                player([""; 10]),
+            ],
+            recent_roms: vec![],
+        }
+    }
+}
+
+/**
+ * `$XDG_CONFIG_HOME/nes-emulator/config.toml`, `~/.config/...` without it, and the
+ * platform's own application data directory on Windows and macOS.
+ */
+pub fn default_path() -> Option<PathBuf> {
+    let env = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty());
+    let dir = if cfg!(windows) {
+        env("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env("HOME").map(|home| Path::new(&home).join("Library/Application Support"))
+    } else {
+        env("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+    dir.map(|dir| dir.join("nes-emulator").join("config.toml"))
+}
+
+impl Config {
+    /**
+     * Reads the config, writing the defaults there first if it doesn't exist yet.
+     */
+    pub fn load_or_create(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            let config = Config::default();
+            config.save(path)?;
+            return Ok(config);
+        }
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+        Config::parse(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Cannot create {}: {e}", dir.display()))?;
+        }
+        std::fs::write(path, self.to_toml())
+            .map_err(|e| format!("Cannot write {}: {e}", path.display()))
+    }
+
+    /**
+     * Moves `rom` to the front of the recent list.
+     */
+    pub fn add_recent_rom(&mut self, rom: &str) {
+        self.recent_roms.retain(|path| path != rom);
+        self.recent_roms.insert(0, rom.to_string());
+        self.recent_roms.truncate(RECENT_ROMS);
+    }
+
+    /**
+     * Settings missing from `text` keep their defaults.
+     */
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+        let mut section = String::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |e: String| format!("line {}: {e}", n + 1);
+            if let Some(header) = line.strip_prefix('[') {
+                let (name, rest) = header
+                    .split_once(']')
+                    .ok_or_else(|| error("unclosed table header".to_string()))?;
+                expect_end(rest).map_err(error)?;
+                section = name.trim().to_string();
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error(format!("expected key = value, got {line}")))?;
+            let (value, rest) = parse_value(value).map_err(error)?;
+            expect_end(rest).map_err(error)?;
+            config.set(&section, key.trim(), value).map_err(error)?;
+        }
+        Ok(config)
+    }
+
+    fn set(&mut self, section: &str, key: &str, value: Value) -> Result<(), String> {
+        let unknown = || match section {
+            "" => format!("unknown setting {key}"),
+            _ => format!("unknown setting {key} in [{section}]"),
+        };
+        match (section, key) {
+            ("", "recent_roms") => {
+                self.recent_roms = match value {
+                    Value::Array(items) => items
+                        .into_iter()
+                        .map(|item| item.string(key))
+                        .collect::<Result<_, _>>()?,
+                    _ => return Err(format!("{key} should be an array of strings")),
+                }
+            }
+            ("video", "scale") => self.video.scale = value.integer(key, 1..=8)?,
+            ("video", "fullscreen") => self.video.fullscreen = value.boolean(key)?,
+            ("video", "filter") => {
+                let filter = value.string(key)?;
+                NtscPreset::parse(&filter)?;
+                self.video.filter = filter;
+            }
+            ("video", "scaler") => {
+                let scaler = value.string(key)?;
+                Scaler::parse(&scaler, 1)?;
+                self.video.scaler = scaler;
+            }
+            ("video", "scanlines") => self.video.scanlines = value.integer(key, 0..=100)?,
+            ("video", "aspect_8_7") => self.video.aspect_8_7 = value.boolean(key)?,
+            ("video", "palette") => self.video.palette = value.string(key)?,
+            ("video", "screenshot_indices") => {
+                self.video.screenshot_indices = value.boolean(key)?
+            }
+            ("video", "record_format") => self.video.record_format = value.string(key)?,
+            ("audio", "sample_rate") => {
+                self.audio.sample_rate = value.integer(key, 8_000..=192_000)?
+            }
+            ("audio", "latency_ms") => self.audio.latency_ms = value.integer(key, 1..=1000)?,
+            ("input", "four_score") => self.input.four_score = value.boolean(key)?,
+            ("input", "analog_threshold") => {
+                self.input.analog_threshold = value.integer(key, 1..=100)?
+            }
+            ("input", "turbo_rate") => self.input.turbo_rate = value.integer(key, 1..=30)?,
+            ("paths", "saves") => self.paths.saves = value.string(key)?,
+            ("paths", "states") => self.paths.states = value.string(key)?,
+            ("paths", "screenshots") => self.paths.screenshots = value.string(key)?,
+            ("paths", "recordings") => self.paths.recordings = value.string(key)?,
+            ("ntsc", "hue") => self.ntsc.hue = value.float(key, -180.0..=180.0)?,
+            ("ntsc", "saturation") => self.ntsc.saturation = value.float(key, 0.0..=4.0)?,
+            ("ntsc", "contrast") => self.ntsc.contrast = value.float(key, 0.0..=4.0)?,
+            ("ntsc", "brightness") => self.ntsc.brightness = value.float(key, -1.0..=1.0)?,
+            ("ntsc", "gamma") => self.ntsc.gamma = value.float(key, 0.1..=4.0)?,
+            ("overscan", "ntsc") => self.overscan.ntsc = value.overscan(key)?,
+            ("overscan", "pal") => self.overscan.pal = value.overscan(key)?,
+            ("overscan.games", _) => {
+                let game = key.trim_matches('"').to_string();
+                let overscan = value.overscan(key)?;
+                self.overscan.games.retain(|(name, _)| *name != game);
+                self.overscan.games.push((game, overscan));
+            }
+            ("hotkeys", _) => {
+                let &(_, hotkey) = HOTKEY_NAMES
+                    .iter()
+                    .find(|(name, _)| *name == key)
+                    .ok_or_else(unknown)?;
+                let binding = self.hotkeys.iter_mut().find(|(h, _)| *h == hotkey).unwrap();
+                binding.1 = value.string(key)?;
+            }
+            _ => {
+                let (player, table) = section
+                    .strip_prefix("player")
+                    .map(|rest| rest.split_once('.').unwrap_or((rest, "")))
+                    .ok_or_else(unknown)?;
+                let player = player
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|player| (1..=PLAYERS).contains(player))
+                    .ok_or(format!(
+                        "unknown player in [{section}], expected 1 to {PLAYERS}"
+                    ))?;
+                let player = &mut self.players[player - 1];
+                let bindings = match (table, key) {
+                    ("", "device") => {
+                        player.device = value.string(key)?;
+                        return Ok(());
+                    }
+                    ("keys", _) => &mut player.keys,
+                    ("gamepad", _) => &mut player.gamepad,
+                    _ => return Err(unknown()),
+                };
+                let &(_, button) = BUTTON_NAMES
+                    .iter()
+                    .find(|(name, _)| *name == key)
+                    .ok_or_else(unknown)?;
+                let binding = bindings.iter_mut().find(|(b, _)| *b == button).unwrap();
+                binding.1 = value.string(key)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut out = String::new();
+        out += "# nes-emulator settings. Command line flags take precedence over these.\n";
+        out += "# Delete this file to get the defaults back.\n\n";
+        let recent: Vec<String> = self.recent_roms.iter().map(|rom| quote(rom)).collect();
+        out += &format!("recent_roms = [{}]\n", recent.join(", "));
+
+        out += "\n[video]\n";
+        out += &format!("scale = {}\n", self.video.scale);
+        out += &format!("fullscreen = {}\n", self.video.fullscreen);
+        out += &format!("filter = {}\n", quote(&self.video.filter));
+        out += &format!("scaler = {}\n", quote(&self.video.scaler));
+        out += &format!("scanlines = {}\n", self.video.scanlines);
+        out += &format!("aspect_8_7 = {}\n", self.video.aspect_8_7);
+        out += &format!("palette = {}\n", quote(&self.video.palette));
+        out += &format!("screenshot_indices = {}\n", self.video.screenshot_indices);
+        out += &format!("record_format = {}\n", quote(&self.video.record_format));
+
+        out += "\n[audio]\n";
+        out += &format!("sample_rate = {}\n", self.audio.sample_rate);
+        out += &format!("latency_ms = {}\n", self.audio.latency_ms);
+
+        out += "\n[input]\n";
+        out += &format!("four_score = {}\n", self.input.four_score);
+        out += &format!("analog_threshold = {}\n", self.input.analog_threshold);
+        out += &format!("turbo_rate = {}\n", self.input.turbo_rate);
+
+        out += "\n[paths]\n# empty keeps files next to the ROM\n";
+        out += &format!("saves = {}\n", quote(&self.paths.saves));
+        out += &format!("states = {}\n", quote(&self.paths.states));
+        out += &format!("screenshots = {}\n", quote(&self.paths.screenshots));
+        out += &format!("recordings = {}\n", quote(&self.paths.recordings));
+
+        out += "\n# used by palette = \"ntsc\"\n[ntsc]\n";
+        out += &format!("hue = {:?}\n", self.ntsc.hue);
+        out += &format!("saturation = {:?}\n", self.ntsc.saturation);
+        out += &format!("contrast = {:?}\n", self.ntsc.contrast);
+        out += &format!("brightness = {:?}\n", self.ntsc.brightness);
+        out += &format!("gamma = {:?}\n", self.ntsc.gamma);
+
+        out += "\n# pixels hidden at the [top, bottom, left, right]\n[overscan]\n";
+        out += &format!("ntsc = {:?}\n", self.overscan.ntsc.edges());
+        out += &format!("pal = {:?}\n", self.overscan.pal.edges());
+        out += "\n# by ROM file name without the extension\n[overscan.games]\n";
+        for (game, overscan) in &self.overscan.games {
+            out += &format!("{} = {:?}\n", quote(game), overscan.edges());
+        }
+
+        out += "\n# key names: https://wiki.libsdl.org/SDL2/SDL_Keycode\n[hotkeys]\n";
+        for (name, hotkey) in HOTKEY_NAMES {
+            let (_, key) = self.hotkeys.iter().find(|(h, _)| *h == hotkey).unwrap();
+            out += &format!("{name} = {}\n", quote(key));
+        }
+
+        for (i, player) in self.players.iter().enumerate() {
+            out += &format!("\n[player{}]\n", i + 1);
+            out += &format!("device = {}\n", quote(&player.device));
+            for (table, bindings) in [("keys", &player.keys), ("gamepad", &player.gamepad)] {
+                out += &format!("\n[player{}.{table}]\n", i + 1);
+                for (name, button) in BUTTON_NAMES {
+                    let (_, binding) = bindings.iter().find(|(b, _)| *b == button).unwrap();
+                    out += &format!("{name} = {}\n", quote(binding));
+                }
+            }
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn string(self, key: &str) -> Result<String, String> {
+        match self {
+            Value::String(s) => Ok(s),
+            _ => Err(format!("{key} should be a string")),
+        }
+    }
+
+    fn boolean(self, key: &str) -> Result<bool, String> {
+        match self {
+            Value::Boolean(b) => Ok(b),
+            _ => Err(format!("{key} should be true or false")),
+        }
+    }
+
+    fn integer(self, key: &str, range: std::ops::RangeInclusive<u32>) -> Result<u32, String> {
+        match self {
+            Value::Integer(n) => u32::try_from(n)
+                .ok()
+                .filter(|n| range.contains(n))
+                .ok_or_else(|| {
+                    format!(
+                        "{key} should be a number from {} to {}",
+                        range.start(),
+                        range.end()
+                    )
+                }),
+            _ => Err(format!(
+                "{key} should be a number from {} to {}",
+                range.start(),
+                range.end()
+            )),
+        }
+    }
+
+    fn overscan(self, key: &str) -> Result<Overscan, String> {
+        let error = || format!("{key} should be [top, bottom, left, right], each 0 to {MAX_EDGE}");
+        let Value::Array(items) = self else {
+            return Err(error());
+        };
+        let edges: Vec<usize> = items
+            .into_iter()
+            .map(|item| item.integer(key, 0..=MAX_EDGE as u32).map(|n| n as usize))
+            .collect::<Result<_, _>>()
+            .map_err(|_| error())?;
+        let edges: [usize; 4] = edges.try_into().map_err(|_| error())?;
+        Ok(Overscan::from_edges(edges))
+    }
+
+    fn float(self, key: &str, range: std::ops::RangeInclusive<f64>) -> Result<f64, String> {
+        let number = match self {
+            Value::Float(n) => Some(n),
+            Value::Integer(n) => Some(n as f64),
+            _ => None,
+        };
+        number.filter(|n| range.contains(n)).ok_or_else(|| {
+            format!(
+                "{key} should be a number from {} to {}",
+                range.start(),
+                range.end()
+            )
+        })
+    }
+}
+
+/**
+ * Parses one value from the front of `text`, returning it and whatever follows.
+ */
+fn parse_value(text: &str) -> Result<(Value, &str), String> {
+    let text = text.trim_start();
+    if let Some(rest) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::String(value), &rest[i + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    other => return Err(format!("unsupported escape \\{}", other.unwrap_or(' '))),
+                },
+                c => value.push(c),
+            }
+        }
+        return Err("unterminated string".to_string());
+    }
+    if let Some(rest) = text.strip_prefix('\'') {
+        let (value, rest) = rest
+            .split_once('\'')
+            .ok_or("unterminated string".to_string())?;
+        return Ok((Value::String(value.to_string()), rest));
+    }
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut items = vec![];
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(items), after));
+            }
+            let (item, after) = parse_value(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return Err("expected , or ] in array".to_string());
+            }
+        }
+    }
+
+    let end = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || "+-_.".contains(c)))
+        .unwrap_or(text.len());
+    let (word, rest) = text.split_at(end);
+    let value = match word {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        "" => return Err("missing value".to_string()),
+        _ if word.contains('.') => Value::Float(
+            word.replace('_', "")
+                .parse()
+                .map_err(|_| format!("invalid value {word}"))?,
+        ),
+        _ => Value::Integer(
+            word.replace('_', "")
+                .parse()
+                .map_err(|_| format!("invalid value {word}"))?,
+        ),
+    };
+    Ok((value, rest))
+}
+
+fn expect_end(rest: &str) -> Result<(), String> {
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(())
+    } else {
+        Err(format!("unexpected {rest}"))
+    }
+}
+
+fn quote(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{escaped}\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_defaults_round_trip() {
+        let mut config = Config::default();
+        config.add_recent_rom("C:\\roms\\smb.nes");
+        config.add_recent_rom("zelda \"us\".nes");
+        config.add_recent_rom("C:\\roms\\smb.nes");
+        config.overscan.games.push(("Kirby's Adventure".to_string(), Overscan::default()));
+        assert_eq!(config.recent_roms[0], "C:\\roms\\smb.nes");
+        assert_eq!(Config::parse(&config.to_toml()), Ok(config));
+    }
+
+    #[test]
+    fn test_parse_overrides() {
+        let config = Config::parse(
+            "recent_roms = ['a.nes', \"b.nes\"] # newest first\n\
+             [video]\nscale = 2\nfullscreen = true\n\
+             [player2.keys]\na = \"K\"\n\
+             [hotkeys]\npause = \"F1\"\n\
+             [ntsc]\nhue = -7.5\ngamma = 2\n\
+             [overscan.games]\n\"Mario Bros.\" = [0, 8, 8, 8]\n",
+        )
+        .unwrap();
+        assert_eq!(config.recent_roms, vec!["a.nes", "b.nes"]);
+        assert_eq!((config.video.scale, config.video.fullscreen), (2, true));
+        assert!(config.players[1]
+            .keys
+            .contains(&(Button::Pad(JoypadButton::BUTTON_A), "K".to_string())));
+        assert!(config.hotkeys.contains(&(Hotkey::Pause, "F1".to_string())));
+        assert_eq!(config.audio, Config::default().audio);
+        assert_eq!((config.ntsc.hue, config.ntsc.gamma), (-7.5, 2.0));
+        let overscan = &config.overscan;
+        assert_eq!(overscan.for_game("Mario Bros.", Region::Ntsc).edges(), [0, 8, 8, 8]);
+        assert_eq!(overscan.for_game("Tetris", Region::Ntsc).top, 8);
+        assert!(overscan.for_game("Tetris", Region::Pal).is_none());
+        assert!(overscan.for_game("Tetris", Region::Dendy).is_none());
+
+        assert_eq!(
+            Config::parse("[video]\nscale = 0"),
+            Err("line 2: scale should be a number from 1 to 8".to_string())
+        );
+        assert!(Config::parse("[player5.keys]\na = \"K\"").is_err());
+        assert!(Config::parse("[player4]\ndevice = \"8BitDo\"").is_ok());
+        assert!(Config::parse("[video]\nzoom = 2").is_err());
+        assert!(Config::parse("[video]\nfilter = \"crt\"").is_err());
+        assert!(Config::parse("[audio]\nlatency_ms = \"50\"").is_err());
+    }
+}
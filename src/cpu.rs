@@ -16,7 +16,7 @@ bitflags! {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[allow(non_camel_case_types)]
 pub enum AddressingMode {
     Immediate,
@@ -36,6 +36,74 @@ fn has_crossed_page(base_addr: u16, new_addr: u16) -> bool {
     base_addr >> 8 != new_addr >> 8
 }
 
+/**
+ * The operand address for `mode`, whose bytes start at `addr`, and whether indexing
+ * crossed a page.
+ */
+fn resolve_address(
+    mode: &AddressingMode,
+    addr: u16,
+    register_x: u8,
+    register_y: u8,
+    mut read: impl FnMut(u16) -> u8,
+) -> (u16, bool) {
+    let mut read_u16 = |addr: u16| u16::from_le_bytes([read(addr), read(addr.wrapping_add(1))]);
+    match mode {
+        AddressingMode::Immediate => (addr, false),
+        AddressingMode::ZeroPage => (read(addr) as u16, false),
+        AddressingMode::ZeroPage_X => {
+            let base_addr = read(addr);
+            (base_addr.wrapping_add(register_x) as u16, false)
+        }
+        AddressingMode::ZeroPage_Y => {
+            let base_addr = read(addr);
+            (base_addr.wrapping_add(register_y) as u16, false)
+        }
+        AddressingMode::Absolute => (read_u16(addr), false),
+        AddressingMode::Absolute_X => {
+            let base_addr = read_u16(addr);
+            let new_addr = base_addr.wrapping_add(register_x as u16);
+            (new_addr, has_crossed_page(base_addr, new_addr))
+        }
+        AddressingMode::Absolute_Y => {
+            let base_addr = read_u16(addr);
+            let new_addr = base_addr.wrapping_add(register_y as u16);
+            (new_addr, has_crossed_page(base_addr, new_addr))
+        }
+        AddressingMode::Indirect => {
+            let addr = read_u16(addr);
+            let lo = read(addr);
+            let hi = if addr & 0x00FF == 0x00FF {
+                // if im at the page boundary, stay on the same page (ignore)
+                read(addr & 0xFF00)
+            } else {
+                read(addr.wrapping_add(1))
+            };
+            (u16::from_le_bytes([lo, hi]), false)
+        }
+        AddressingMode::Indirect_X => {
+            let base_addr: u8 = read(addr);
+            let x_addr = base_addr.wrapping_add(register_x);
+            (u16::from_le_bytes([
+                read(x_addr as u16),
+                read(x_addr.wrapping_add(1) as u16),
+            ]), false)
+        }
+        AddressingMode::Indirect_Y => {
+            let base_addr = read(addr);
+            let preoffset_addr = u16::from_le_bytes([
+                read(base_addr as u16),
+                read(base_addr.wrapping_add(1) as u16),
+            ]);
+            let new_addr = preoffset_addr.wrapping_add(register_y as u16);
+            (new_addr, has_crossed_page(preoffset_addr, new_addr))
+        }
+        AddressingMode::Implied => {
+            panic!("Go to sleep. Why you tryna find a new address bruv.")
+        }
+    }
+}
+
 pub struct CPU {
     pub register_a: u8,
     pub register_x: u8,
@@ -67,6 +135,35 @@ pub trait Mem {
     }
 }
 
+/**
+ * Debug access to memory: `peek` returns what `mem_read` would, and `poke` changes
+ * memory, neither with side effects. No register latches or clears, no code/data
+ * logging. Everything on the bus (and mappers and the APU as they arrive)
+ * implements it next to `Mem`.
+ */
+pub trait Peek {
+    fn peek(&self, addr: u16) -> u8;
+
+    /**
+     * Fails where there is nothing to change without side effects, like a latch.
+     */
+    fn poke(&mut self, addr: u16, data: u8) -> Result<(), String>;
+
+    fn peek_u16(&self, pos: u16) -> u16 {
+        u16::from_le_bytes([self.peek(pos), self.peek(pos.wrapping_add(1))])
+    }
+}
+
+impl Peek for CPU {
+    fn peek(&self, addr: u16) -> u8 {
+        self.bus.peek(addr)
+    }
+
+    fn poke(&mut self, addr: u16, data: u8) -> Result<(), String> {
+        self.bus.poke(addr, data)
+    }
+}
+
 impl Mem for CPU {
     fn mem_read(&mut self, addr: u16) -> u8 {
         self.bus.mem_read(addr)
@@ -116,60 +213,16 @@ impl CPU {
      * to find the value we need as an operand for our command.
      */
     pub fn get_absolute_address(&mut self, mode: &AddressingMode, addr: u16) -> (u16, bool) {
-        match mode {
-            AddressingMode::Immediate => (addr, false),
-            AddressingMode::ZeroPage => (self.mem_read(addr) as u16, false),
-            AddressingMode::ZeroPage_X => {
-                let base_addr = self.mem_read(addr);
-                (base_addr.wrapping_add(self.register_x) as u16, false)
-            }
-            AddressingMode::ZeroPage_Y => {
-                let base_addr = self.mem_read(addr);
-                (base_addr.wrapping_add(self.register_y) as u16, false)
-            }
-            AddressingMode::Absolute => (self.mem_read_u16(addr), false),
-            AddressingMode::Absolute_X => {
-                let base_addr = self.mem_read_u16(addr);
-                let new_addr = base_addr.wrapping_add(self.register_x as u16) as u16;
-                (new_addr, has_crossed_page(base_addr, new_addr))
-            }
-            AddressingMode::Absolute_Y => {
-                let base_addr = self.mem_read_u16(addr);
-                let new_addr = base_addr.wrapping_add(self.register_y as u16) as u16;
-                (new_addr, has_crossed_page(base_addr, new_addr))
-            }
-            AddressingMode::Indirect => {
-                let addr = self.mem_read_u16(addr);
-                let lo = self.mem_read(addr);
-                let hi = if addr & 0x00FF == 0x00FF {
-                    // if im at the page boundary, stay on the same page (ignore)
-                    self.mem_read(addr & 0xFF00)
-                } else {
-                    self.mem_read(addr.wrapping_add(1))
-                };
-                (u16::from_le_bytes([lo, hi]), false)
-            }
-            AddressingMode::Indirect_X => {
-                let base_addr: u8 = self.mem_read(addr);
-                let x_addr = base_addr.wrapping_add(self.register_x);
-                (u16::from_le_bytes([
-                    self.mem_read(x_addr as u16),
-                    self.mem_read(x_addr.wrapping_add(1) as u16),
-                ]), false)
-            }
-            AddressingMode::Indirect_Y => {
-                let base_addr = self.mem_read(addr);
-                let preoffset_addr = u16::from_le_bytes([
-                    self.mem_read(base_addr as u16),
-                    self.mem_read(base_addr.wrapping_add(1) as u16),
-                ]);
-                let new_addr = preoffset_addr.wrapping_add(self.register_y as u16);
-                (new_addr, has_crossed_page(preoffset_addr, new_addr))
-            }
-            AddressingMode::Implied => {
-                panic!("Go to sleep. Why you tryna find a new address bruv.")
-            }
-        }
+        let (x, y) = (self.register_x, self.register_y);
+        resolve_address(mode, addr, x, y, |addr| self.mem_read(addr))
+    }
+
+    /**
+     * `get_absolute_address` for debuggers: pointers are read with `peek`.
+     */
+    pub fn peek_absolute_address(&self, mode: &AddressingMode, addr: u16) -> u16 {
+        let (x, y) = (self.register_x, self.register_y);
+        resolve_address(mode, addr, x, y, |addr| self.bus.peek(addr)).0
     }
 
     pub fn get_operand_address(&mut self, mode: &AddressingMode) -> (u16, bool) {
@@ -238,456 +291,474 @@ impl CPU {
         F: FnMut(&mut CPU),
     {
         loop {
-            if let Some(_nmi) = self.bus.check_nmi() {
-                self.interrupt_nmi();
+            self.poll_interrupts();
+            callback(self);
+            if !self.step() {
+                return;
             }
+        }
+    }
 
-            callback(self);
-            let opcode = self.mem_read(self.program_counter);
-            let opcode_details =
-                get_opcode_details(&opcode).expect(&format!("Opcode {opcode} is not recognised."));
-            let mode: &AddressingMode = &(opcode_details.mode);
-
-            self.program_counter += 1 as u16;
-            let program_counter_before_exec = self.program_counter;
-            match opcode {
-                0x00 => {
-                    return;
-                }
-                0x69 | 0x65 | 0x75 | 0x6D | 0x7D | 0x79 | 0x61 | 0x71 => {
-                    self.adc(mode);
-                }
-                0x29 | 0x25 | 0x35 | 0x2D | 0x3D | 0x39 | 0x21 | 0x31 => {
-                    self.and(mode);
-                }
-                0x0A => {
-                    self.asl_accumulator();
-                }
-                0x06 | 0x16 | 0x0E | 0x1E => {
-                    self.asl(mode);
-                }
-                // BPL
-                0x10 => {
-                    self.branch(!self.status.contains(StatusFlags::NEGATIVE));
-                }
-                // BVC
-                0x50 => {
-                    self.branch(!self.status.contains(StatusFlags::OVERFLOW));
-                }
-                // BVS
-                0x70 => {
-                    self.branch(self.status.contains(StatusFlags::OVERFLOW));
-                }
-                //BCC
-                0x90 => {
-                    self.branch(!self.status.contains(StatusFlags::CARRY));
-                }
-                //BCS
-                0xB0 => {
-                    self.branch(self.status.contains(StatusFlags::CARRY));
-                }
-                //BNE
-                0xD0 => {
-                    self.branch(!self.status.contains(StatusFlags::ZERO));
-                }
-                //BEQ
-                0xF0 => {
-                    self.branch(self.status.contains(StatusFlags::ZERO));
-                }
-                // BMI
-                0x30 => {
-                    self.branch(self.status.contains(StatusFlags::NEGATIVE));
-                }
-                // CLC
-                0x18 => {
-                    self.status.remove(StatusFlags::CARRY);
-                }
-                // CLV
-                0xB8 => {
-                    self.status.remove(StatusFlags::OVERFLOW);
-                }
-                // CLD
-                0xD8 => {
-                    self.status.remove(StatusFlags::DECIMAL);
-                }
-                // CLI
-                0x58 => {
-                    self.status.remove(StatusFlags::INTERRUPT_DISABLE);
-                }
-                0x24 | 0x2C => {
-                    self.bit(mode);
-                }
-                // CMP
-                0xC9 | 0xC5 | 0xD5 | 0xCD | 0xDD | 0xD9 | 0xC1 | 0xD1 => {
-                    self.compare(self.register_a, mode);
-                }
-                // CPX
-                0xE0 | 0xE4 | 0xEC => {
-                    self.compare(self.register_x, mode);
-                }
-                // CPY
-                0xC0 | 0xC4 | 0xCC => {
-                    self.compare(self.register_y, mode);
-                }
-                // DEC
-                0xC6 | 0xD6 | 0xCE | 0xDE => {
-                    self.dec(mode);
-                }
-                0xCA => {
-                    self.dex();
-                }
-                0x88 => {
-                    self.dey();
-                }
-                0x49 | 0x45 | 0x55 | 0x4D | 0x5D | 0x59 | 0x41 | 0x51 => {
-                    self.eor(mode);
-                }
-                0xE6 | 0xF6 | 0xEE | 0xFE => {
-                    self.inc(mode);
-                }
-                0xE8 => {
-                    self.inx();
-                }
-                0xC8 => {
-                    self.iny();
-                }
-                0x4C => {
-                    self.program_counter = self.mem_read_u16(self.program_counter);
-                }
-                0x6C => {
-                    let addr = self.mem_read_u16(self.program_counter);
-                    let indirect_ref = if addr & 0x00FF == 0x00FF {
-                        let lo = self.mem_read(addr);
-                        let hi = self.mem_read(addr & 0xFF00);
-                        (hi as u16) << 8 | (lo as u16)
-                    } else {
-                        self.mem_read_u16(addr)
-                    };
-
-                    self.program_counter = indirect_ref;
-                }
-                0x20 => {
-                    self.jsr(mode);
-                }
-                0xA2 | 0xA6 | 0xB6 | 0xAE | 0xBE => {
-                    self.ldx(mode);
-                }
-                0xA0 | 0xA4 | 0xB4 | 0xAC | 0xBC => {
-                    self.ldy(mode);
-                }
-                0xA9 | 0xA5 | 0xB5 | 0xAD | 0xBD | 0xB9 | 0xA1 | 0xB1 => {
-                    self.lda(mode);
-                }
-                0x4A => {
-                    self.lsr_accumulator();
-                }
-                0x46 | 0x56 | 0x4E | 0x5E => {
-                    self.lsr(mode);
-                }
-                0xEA => {} // NOP
-                0x09 | 0x05 | 0x15 | 0x0D | 0x1D | 0x19 | 0x01 | 0x11 => {
-                    self.ora(mode);
-                }
-                0x48 => {
-                    self.pha();
-                }
-                0x08 => {
-                    self.php();
-                }
-                0x68 => {
-                    self.pla();
-                }
-                0x28 => {
-                    self.plp();
-                }
-                0x2A => {
-                    self.rol_accumulator();
-                }
-                0x26 | 0x36 | 0x2E | 0x3E => {
-                    self.rol(mode);
-                }
-                0x6A => {
-                    self.ror_accumulator();
-                }
-                0x66 | 0x76 | 0x6E | 0x7E => {
-                    self.ror(mode);
-                }
-                0x40 => {
-                    self.rti();
-                }
-                0x60 => {
-                    self.rts();
-                }
-                0xE9 | 0xE5 | 0xF5 | 0xED | 0xFD | 0xF9 | 0xE1 | 0xF1 => {
-                    self.sbc(mode);
-                }
-                0x38 => {
-                    self.sec();
-                }
-                0xF8 => {
-                    self.sed();
-                }
-                0x78 => {
-                    self.sei();
-                }
-                0x85 | 0x95 | 0x8D | 0x9D | 0x99 | 0x81 | 0x91 => {
-                    self.sta(mode);
-                }
-                0x86 | 0x96 | 0x8E => {
-                    self.stx(mode);
-                }
-                0x84 | 0x94 | 0x8C => {
-                    self.sty(mode);
-                }
-                0xAA => {
-                    self.tax();
-                }
-                0xA8 => {
-                    self.tay();
-                }
-                0xBA => {
-                    self.tsx();
-                }
-                0x8A => {
-                    self.txa();
-                }
-                0x9A => {
-                    self.txs();
-                }
-                0x98 => {
-                    self.tya();
-                }
-                /* DCP */
-                0xc7 | 0xd7 | 0xCF | 0xdF | 0xdb | 0xd3 | 0xc3 => {
-                    let (addr, _) = self.get_operand_address(mode);
-                    let mut data = self.mem_read(addr);
-                    data = data.wrapping_sub(1);
-                    self.mem_write(addr, data);
-                    // self._update_zero_and_negative_flags(data);
-                    if data <= self.register_a {
-                        self.status.insert(StatusFlags::CARRY);
-                    }
-
-                    self.update_zero_and_negative_flags(self.register_a.wrapping_sub(data));
-                }
+    /**
+     * Services a pending NMI, if any, before the next instruction is fetched.
+     */
+    pub fn poll_interrupts(&mut self) {
+        if let Some(_nmi) = self.bus.check_nmi() {
+            self.interrupt_nmi();
+        }
+    }
 
-                /* RLA */
-                0x27 | 0x37 | 0x2F | 0x3F | 0x3b | 0x33 | 0x23 => {
-                    let data = self.rol(mode);
-                    self.and_with_register_a(data);
-                }
+    /**
+     * Fetches, decodes and executes a single instruction.
+     * Returns false when BRK is hit, which is how programs signal that they are done.
+     */
+    pub fn step(&mut self) -> bool {
+        let opcode = self.bus.fetch_opcode(self.program_counter);
+        let Some(opcode_details) = get_opcode_details(&opcode) else {
+            unreachable!("opcode {opcode:02x} is missing from OP_CODES_MAP");
+        };
+        let mode: &AddressingMode = &(opcode_details.mode);
+
+        self.program_counter += 1 as u16;
+        let program_counter_before_exec = self.program_counter;
+        match opcode {
+            0x00 => {
+                return false;
+            }
+            0x69 | 0x65 | 0x75 | 0x6D | 0x7D | 0x79 | 0x61 | 0x71 => {
+                self.adc(mode);
+            }
+            0x29 | 0x25 | 0x35 | 0x2D | 0x3D | 0x39 | 0x21 | 0x31 => {
+                self.and(mode);
+            }
+            0x0A => {
+                self.asl_accumulator();
+            }
+            0x06 | 0x16 | 0x0E | 0x1E => {
+                self.asl(mode);
+            }
+            // BPL
+            0x10 => {
+                self.branch(!self.status.contains(StatusFlags::NEGATIVE));
+            }
+            // BVC
+            0x50 => {
+                self.branch(!self.status.contains(StatusFlags::OVERFLOW));
+            }
+            // BVS
+            0x70 => {
+                self.branch(self.status.contains(StatusFlags::OVERFLOW));
+            }
+            //BCC
+            0x90 => {
+                self.branch(!self.status.contains(StatusFlags::CARRY));
+            }
+            //BCS
+            0xB0 => {
+                self.branch(self.status.contains(StatusFlags::CARRY));
+            }
+            //BNE
+            0xD0 => {
+                self.branch(!self.status.contains(StatusFlags::ZERO));
+            }
+            //BEQ
+            0xF0 => {
+                self.branch(self.status.contains(StatusFlags::ZERO));
+            }
+            // BMI
+            0x30 => {
+                self.branch(self.status.contains(StatusFlags::NEGATIVE));
+            }
+            // CLC
+            0x18 => {
+                self.status.remove(StatusFlags::CARRY);
+            }
+            // CLV
+            0xB8 => {
+                self.status.remove(StatusFlags::OVERFLOW);
+            }
+            // CLD
+            0xD8 => {
+                self.status.remove(StatusFlags::DECIMAL);
+            }
+            // CLI
+            0x58 => {
+                self.status.remove(StatusFlags::INTERRUPT_DISABLE);
+            }
+            0x24 | 0x2C => {
+                self.bit(mode);
+            }
+            // CMP
+            0xC9 | 0xC5 | 0xD5 | 0xCD | 0xDD | 0xD9 | 0xC1 | 0xD1 => {
+                self.compare(self.register_a, mode);
+            }
+            // CPX
+            0xE0 | 0xE4 | 0xEC => {
+                self.compare(self.register_x, mode);
+            }
+            // CPY
+            0xC0 | 0xC4 | 0xCC => {
+                self.compare(self.register_y, mode);
+            }
+            // DEC
+            0xC6 | 0xD6 | 0xCE | 0xDE => {
+                self.dec(mode);
+            }
+            0xCA => {
+                self.dex();
+            }
+            0x88 => {
+                self.dey();
+            }
+            0x49 | 0x45 | 0x55 | 0x4D | 0x5D | 0x59 | 0x41 | 0x51 => {
+                self.eor(mode);
+            }
+            0xE6 | 0xF6 | 0xEE | 0xFE => {
+                self.inc(mode);
+            }
+            0xE8 => {
+                self.inx();
+            }
+            0xC8 => {
+                self.iny();
+            }
+            0x4C => {
+                self.program_counter = self.mem_read_u16(self.program_counter);
+            }
+            0x6C => {
+                let addr = self.mem_read_u16(self.program_counter);
+                let indirect_ref = if addr & 0x00FF == 0x00FF {
+                    let lo = self.mem_read(addr);
+                    let hi = self.mem_read(addr & 0xFF00);
+                    (hi as u16) << 8 | (lo as u16)
+                } else {
+                    self.mem_read_u16(addr)
+                };
 
-                /* SLO */ //todo tests
-                0x07 | 0x17 | 0x0F | 0x1f | 0x1b | 0x03 | 0x13 => {
-                    let data = self.asl(mode);
-                    self.or_with_register_a(data);
-                }
+                self.bus.log_indirect_jump(indirect_ref);
+                self.program_counter = indirect_ref;
+            }
+            0x20 => {
+                self.jsr(mode);
+            }
+            0xA2 | 0xA6 | 0xB6 | 0xAE | 0xBE => {
+                self.ldx(mode);
+            }
+            0xA0 | 0xA4 | 0xB4 | 0xAC | 0xBC => {
+                self.ldy(mode);
+            }
+            0xA9 | 0xA5 | 0xB5 | 0xAD | 0xBD | 0xB9 | 0xA1 | 0xB1 => {
+                self.lda(mode);
+            }
+            0x4A => {
+                self.lsr_accumulator();
+            }
+            0x46 | 0x56 | 0x4E | 0x5E => {
+                self.lsr(mode);
+            }
+            0xEA => {} // NOP
+            0x09 | 0x05 | 0x15 | 0x0D | 0x1D | 0x19 | 0x01 | 0x11 => {
+                self.ora(mode);
+            }
+            0x48 => {
+                self.pha();
+            }
+            0x08 => {
+                self.php();
+            }
+            0x68 => {
+                self.pla();
+            }
+            0x28 => {
+                self.plp();
+            }
+            0x2A => {
+                self.rol_accumulator();
+            }
+            0x26 | 0x36 | 0x2E | 0x3E => {
+                self.rol(mode);
+            }
+            0x6A => {
+                self.ror_accumulator();
+            }
+            0x66 | 0x76 | 0x6E | 0x7E => {
+                self.ror(mode);
+            }
+            0x40 => {
+                self.rti();
+            }
+            0x60 => {
+                self.rts();
+            }
+            0xE9 | 0xE5 | 0xF5 | 0xED | 0xFD | 0xF9 | 0xE1 | 0xF1 => {
+                self.sbc(mode);
+            }
+            0x38 => {
+                self.sec();
+            }
+            0xF8 => {
+                self.sed();
+            }
+            0x78 => {
+                self.sei();
+            }
+            0x85 | 0x95 | 0x8D | 0x9D | 0x99 | 0x81 | 0x91 => {
+                self.sta(mode);
+            }
+            0x86 | 0x96 | 0x8E => {
+                self.stx(mode);
+            }
+            0x84 | 0x94 | 0x8C => {
+                self.sty(mode);
+            }
+            0xAA => {
+                self.tax();
+            }
+            0xA8 => {
+                self.tay();
+            }
+            0xBA => {
+                self.tsx();
+            }
+            0x8A => {
+                self.txa();
+            }
+            0x9A => {
+                self.txs();
+            }
+            0x98 => {
+                self.tya();
+            }
+            /* DCP */
+            0xc7 | 0xd7 | 0xCF | 0xdF | 0xdb | 0xd3 | 0xc3 => {
+                let (addr, _) = self.get_operand_address(mode);
+                let mut data = self.mem_read(addr);
+                data = data.wrapping_sub(1);
+                self.mem_write(addr, data);
+                // self._update_zero_and_negative_flags(data);
+                if data <= self.register_a {
+                    self.status.insert(StatusFlags::CARRY);
+                }
+
+                self.update_zero_and_negative_flags(self.register_a.wrapping_sub(data));
+            }
 
-                /* SRE */ //todo tests
-                0x47 | 0x57 | 0x4F | 0x5f | 0x5b | 0x43 | 0x53 => {
-                    let data = self.lsr(mode);
-                    self.xor_with_register_a(data);
-                }
+            /* RLA */
+            0x27 | 0x37 | 0x2F | 0x3F | 0x3b | 0x33 | 0x23 => {
+                let data = self.rol(mode);
+                self.and_with_register_a(data);
+            }
 
-                /* SKB */
-                0x80 | 0x82 | 0x89 | 0xc2 | 0xe2 => {
-                    /* 2 byte NOP (immediate ) */
-                    // todo: might be worth doing the read
-                }
+            /* SLO */ //todo tests
+            0x07 | 0x17 | 0x0F | 0x1f | 0x1b | 0x03 | 0x13 => {
+                let data = self.asl(mode);
+                self.or_with_register_a(data);
+            }
 
-                /* AXS */
-                0xCB => {
-                    let (addr, _) = self.get_operand_address(mode);
-                    let data = self.mem_read(addr);
-                    let x_and_a = self.register_x & self.register_a;
-                    let result = x_and_a.wrapping_sub(data);
+            /* SRE */ //todo tests
+            0x47 | 0x57 | 0x4F | 0x5f | 0x5b | 0x43 | 0x53 => {
+                let data = self.lsr(mode);
+                self.xor_with_register_a(data);
+            }
 
-                    if data <= x_and_a {
-                        self.status.insert(StatusFlags::CARRY);
-                    }
-                    self.update_zero_and_negative_flags(result);
+            /* SKB */
+            0x80 | 0x82 | 0x89 | 0xc2 | 0xe2 => {
+                /* 2 byte NOP (immediate ) */
+                // todo: might be worth doing the read
+            }
 
-                    self.register_x = result;
-                }
+            /* AXS */
+            0xCB => {
+                let (addr, _) = self.get_operand_address(mode);
+                let data = self.mem_read(addr);
+                let x_and_a = self.register_x & self.register_a;
+                let result = x_and_a.wrapping_sub(data);
 
-                /* ARR */
-                0x6B => {
-                    let (addr, _) = self.get_operand_address(mode);
-                    let data = self.mem_read(addr);
-                    self.and_with_register_a(data);
-                    self.ror_accumulator();
-                    //todo: registers
-                    let result = self.register_a;
-                    let bit_5 = (result >> 5) & 1;
-                    let bit_6 = (result >> 6) & 1;
-
-                    if bit_6 == 1 {
-                        self.status.insert(StatusFlags::CARRY)
-                    } else {
-                        self.status.remove(StatusFlags::CARRY)
-                    }
-
-                    if bit_5 ^ bit_6 == 1 {
-                        self.status.insert(StatusFlags::OVERFLOW);
-                    } else {
-                        self.status.remove(StatusFlags::OVERFLOW);
-                    }
-
-                    self.update_zero_and_negative_flags(result);
+                if data <= x_and_a {
+                    self.status.insert(StatusFlags::CARRY);
                 }
+                self.update_zero_and_negative_flags(result);
 
-                /* unofficial SBC */
-                0xeb => {
-                    let (addr, _) = self.get_operand_address(mode);
-                    let data = self.mem_read(addr);
-                    self.sub_from_register_a(data);
-                }
+                self.register_x = result;
+            }
 
-                /* ANC */
-                0x0b | 0x2b => {
-                    let (addr, _) = self.get_operand_address(mode);
-                    let data = self.mem_read(addr);
-                    self.and_with_register_a(data);
-                    if self.status.contains(StatusFlags::NEGATIVE) {
-                        self.status.insert(StatusFlags::CARRY);
-                    } else {
-                        self.status.remove(StatusFlags::CARRY);
-                    }
+            /* ARR */
+            0x6B => {
+                let (addr, _) = self.get_operand_address(mode);
+                let data = self.mem_read(addr);
+                self.and_with_register_a(data);
+                self.ror_accumulator();
+                //todo: registers
+                let result = self.register_a;
+                let bit_5 = (result >> 5) & 1;
+                let bit_6 = (result >> 6) & 1;
+
+                if bit_6 == 1 {
+                    self.status.insert(StatusFlags::CARRY)
+                } else {
+                    self.status.remove(StatusFlags::CARRY)
                 }
 
-                /* ALR */
-                0x4b => {
-                    let (addr, _) = self.get_operand_address(mode);
-                    let data = self.mem_read(addr);
-                    self.and_with_register_a(data);
-                    self.lsr_accumulator();
+                if bit_5 ^ bit_6 == 1 {
+                    self.status.insert(StatusFlags::OVERFLOW);
+                } else {
+                    self.status.remove(StatusFlags::OVERFLOW);
                 }
 
-                //todo: test for everything bellow
+                self.update_zero_and_negative_flags(result);
+            }
 
-                /* NOP read */
-                0x04 | 0x44 | 0x64 | 0x14 | 0x34 | 0x54 | 0x74 | 0xd4 | 0xf4 | 0x0c | 0x1c
-                | 0x3c | 0x5c | 0x7c | 0xdc | 0xfc => {
-                    let (addr, has_crossed_page) = self.get_operand_address(mode);
-                    let _ = self.mem_read(addr);
-                    if has_crossed_page {
-                        self.bus.tick(1);
-                    }
-                }
+            /* unofficial SBC */
+            0xeb => {
+                let (addr, _) = self.get_operand_address(mode);
+                let data = self.mem_read(addr);
+                self.sub_from_register_a(data);
+            }
 
-                /* RRA */
-                0x67 | 0x77 | 0x6f | 0x7f | 0x7b | 0x63 | 0x73 => {
-                    let data = self.ror(mode);
-                    self.add_to_register_a(data);
+            /* ANC */
+            0x0b | 0x2b => {
+                let (addr, _) = self.get_operand_address(mode);
+                let data = self.mem_read(addr);
+                self.and_with_register_a(data);
+                if self.status.contains(StatusFlags::NEGATIVE) {
+                    self.status.insert(StatusFlags::CARRY);
+                } else {
+                    self.status.remove(StatusFlags::CARRY);
                 }
+            }
+
+            /* ALR */
+            0x4b => {
+                let (addr, _) = self.get_operand_address(mode);
+                let data = self.mem_read(addr);
+                self.and_with_register_a(data);
+                self.lsr_accumulator();
+            }
+
+            //todo: test for everything bellow
 
-                /* ISB */
-                0xe7 | 0xf7 | 0xef | 0xff | 0xfb | 0xe3 | 0xf3 => {
-                    let data = self.inc(mode);
-                    self.sub_from_register_a(data);
+            /* NOP read */
+            0x04 | 0x44 | 0x64 | 0x14 | 0x34 | 0x54 | 0x74 | 0xd4 | 0xf4 | 0x0c | 0x1c
+            | 0x3c | 0x5c | 0x7c | 0xdc | 0xfc => {
+                let (addr, has_crossed_page) = self.get_operand_address(mode);
+                let _ = self.mem_read(addr);
+                if has_crossed_page {
+                    self.bus.tick(1);
                 }
+            }
 
-                /* NOPs */
-                0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xb2 | 0xd2
-                | 0xf2 => { /* do nothing */ }
+            /* RRA */
+            0x67 | 0x77 | 0x6f | 0x7f | 0x7b | 0x63 | 0x73 => {
+                let data = self.ror(mode);
+                self.add_to_register_a(data);
+            }
 
-                0x1a | 0x3a | 0x5a | 0x7a | 0xda | 0xfa => { /* do nothing */ }
+            /* ISB */
+            0xe7 | 0xf7 | 0xef | 0xff | 0xfb | 0xe3 | 0xf3 => {
+                let data = self.inc(mode);
+                self.sub_from_register_a(data);
+            }
 
-                /* LAX */
-                0xa7 | 0xb7 | 0xaf | 0xbf | 0xa3 | 0xb3 => {
-                    let (addr, _) = self.get_operand_address(mode);
-                    let data = self.mem_read(addr);
-                    self.set_register_a(data);
-                    self.register_x = self.register_a;
-                }
+            /* NOPs */
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xb2 | 0xd2
+            | 0xf2 => { /* do nothing */ }
 
-                /* SAX */
-                0x87 | 0x97 | 0x8f | 0x83 => {
-                    let data = self.register_a & self.register_x;
-                    let (addr, _) = self.get_operand_address(mode);
-                    self.mem_write(addr, data);
-                }
+            0x1a | 0x3a | 0x5a | 0x7a | 0xda | 0xfa => { /* do nothing */ }
 
-                /* LXA */
-                0xab => {
-                    self.lda(mode);
-                    self.tax();
-                }
+            /* LAX */
+            0xa7 | 0xb7 | 0xaf | 0xbf | 0xa3 | 0xb3 => {
+                let (addr, _) = self.get_operand_address(mode);
+                let data = self.mem_read(addr);
+                self.set_register_a(data);
+                self.register_x = self.register_a;
+            }
 
-                /* XAA */
-                0x8b => {
-                    self.register_a = self.register_x;
-                    self.update_zero_and_negative_flags(self.register_a);
-                    let (addr, _) = self.get_operand_address(mode);
-                    let data = self.mem_read(addr);
-                    self.and_with_register_a(data);
-                }
+            /* SAX */
+            0x87 | 0x97 | 0x8f | 0x83 => {
+                let data = self.register_a & self.register_x;
+                let (addr, _) = self.get_operand_address(mode);
+                self.mem_write(addr, data);
+            }
 
-                /* LAS */
-                0xbb => {
-                    let (addr, _) = self.get_operand_address(mode);
-                    let mut data = self.mem_read(addr);
-                    data = data & self.stack_ptr;
-                    self.register_a = data;
-                    self.register_x = data;
-                    self.stack_ptr = data;
-                    self.update_zero_and_negative_flags(data);
-                }
+            /* LXA */
+            0xab => {
+                self.lda(mode);
+                self.tax();
+            }
 
-                /* TAS */
-                0x9b => {
-                    let data = self.register_a & self.register_x;
-                    self.stack_ptr = data;
-                    let mem_address =
-                        self.mem_read_u16(self.program_counter) + self.register_y as u16;
+            /* XAA */
+            0x8b => {
+                self.register_a = self.register_x;
+                self.update_zero_and_negative_flags(self.register_a);
+                let (addr, _) = self.get_operand_address(mode);
+                let data = self.mem_read(addr);
+                self.and_with_register_a(data);
+            }
 
-                    let data = ((mem_address >> 8) as u8 + 1) & self.stack_ptr;
-                    self.mem_write(mem_address, data)
-                }
+            /* LAS */
+            0xbb => {
+                let (addr, _) = self.get_operand_address(mode);
+                let mut data = self.mem_read(addr);
+                data = data & self.stack_ptr;
+                self.register_a = data;
+                self.register_x = data;
+                self.stack_ptr = data;
+                self.update_zero_and_negative_flags(data);
+            }
 
-                /* AHX  Indirect Y */
-                0x93 => {
-                    let pos: u8 = self.mem_read(self.program_counter);
-                    let mem_address = self.mem_read_u16(pos as u16) + self.register_y as u16;
-                    let data = self.register_a & self.register_x & (mem_address >> 8) as u8;
-                    self.mem_write(mem_address, data)
-                }
+            /* TAS */
+            0x9b => {
+                let data = self.register_a & self.register_x;
+                self.stack_ptr = data;
+                let mem_address =
+                    self.mem_read_u16(self.program_counter) + self.register_y as u16;
 
-                /* AHX Absolute Y*/
-                0x9f => {
-                    let mem_address =
-                        self.mem_read_u16(self.program_counter) + self.register_y as u16;
+                let data = ((mem_address >> 8) as u8 + 1) & self.stack_ptr;
+                self.mem_write(mem_address, data)
+            }
 
-                    let data = self.register_a & self.register_x & (mem_address >> 8) as u8;
-                    self.mem_write(mem_address, data)
-                }
+            /* AHX  Indirect Y */
+            0x93 => {
+                let pos: u8 = self.mem_read(self.program_counter);
+                let mem_address = self.mem_read_u16(pos as u16) + self.register_y as u16;
+                let data = self.register_a & self.register_x & (mem_address >> 8) as u8;
+                self.mem_write(mem_address, data)
+            }
 
-                /* SHX */
-                0x9e => {
-                    let mem_address =
-                        self.mem_read_u16(self.program_counter) + self.register_y as u16;
+            /* AHX Absolute Y*/
+            0x9f => {
+                let mem_address =
+                    self.mem_read_u16(self.program_counter) + self.register_y as u16;
 
-                    // todo if cross page boundry {
-                    //     mem_address &= (self.x as u16) << 8;
-                    // }
-                    let data = self.register_x & ((mem_address >> 8) as u8 + 1);
-                    self.mem_write(mem_address, data)
-                }
+                let data = self.register_a & self.register_x & (mem_address >> 8) as u8;
+                self.mem_write(mem_address, data)
+            }
 
-                /* SHY */
-                0x9c => {
-                    let mem_address =
-                        self.mem_read_u16(self.program_counter) + self.register_x as u16;
-                    let data = self.register_y & ((mem_address >> 8) as u8 + 1);
-                    self.mem_write(mem_address, data)
-                }
-                _ => todo!(),
+            /* SHX */
+            0x9e => {
+                let mem_address =
+                    self.mem_read_u16(self.program_counter) + self.register_y as u16;
+
+                // todo if cross page boundry {
+                //     mem_address &= (self.x as u16) << 8;
+                // }
+                let data = self.register_x & ((mem_address >> 8) as u8 + 1);
+                self.mem_write(mem_address, data)
             }
-            self.bus.tick(opcode_details.cycles);
-            if !self.has_jumped_or_branched(program_counter_before_exec) {
-                self.program_counter += opcode_details.additional_bytes as u16;
+
+            /* SHY */
+            0x9c => {
+                let mem_address =
+                    self.mem_read_u16(self.program_counter) + self.register_x as u16;
+                let data = self.register_y & ((mem_address >> 8) as u8 + 1);
+                self.mem_write(mem_address, data)
             }
         }
+        self.bus.tick(opcode_details.cycles);
+        if !self.has_jumped_or_branched(program_counter_before_exec) {
+            self.program_counter += opcode_details.additional_bytes as u16;
+        }
+        true
     }
 
     fn set_accumulator(&mut self, value: u8) {
@@ -1129,6 +1200,17 @@ mod test {
     use super::*;
     use crate::rom::test;
 
+    #[test]
+    fn test_every_opcode_is_known() {
+        for opcode in 0..=0xFF {
+            assert!(get_opcode_details(&opcode).is_some(), "opcode {opcode:02x}");
+            let mut cpu = CPU::new(Bus::new(test::test_rom()));
+            cpu.load(vec![opcode, 0x00, 0x00]);
+            cpu.program_counter = 0x0600;
+            cpu.step();
+        }
+    }
+
     #[test]
     fn test_0xa9_lda_immediate_load_data() {
         let mut cpu = CPU::new(Bus::new(test::test_rom()));
@@ -0,0 +1,411 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::{
+    cpu::{AddressingMode, Peek},
+    opcodes::{get_opcode_details, OpCode},
+};
+
+/**
+ * Anything that can name an address, e.g. a label map or a loaded symbol file.
+ */
+pub trait Symbols {
+    fn label(&self, addr: u16) -> Option<&str>;
+}
+
+impl Symbols for () {
+    fn label(&self, _addr: u16) -> Option<&str> {
+        None
+    }
+}
+
+impl Symbols for HashMap<u16, String> {
+    fn label(&self, addr: u16) -> Option<&str> {
+        self.get(&addr).map(String::as_str)
+    }
+}
+
+/**
+ * Operands with their raw values. Branch offsets are already resolved
+ * to the absolute address they land on.
+ */
+#[derive(Debug, PartialEq)]
+pub enum Operand {
+    None,
+    Accumulator,
+    Immediate(u8),
+    ZeroPage(u8),
+    ZeroPageX(u8),
+    ZeroPageY(u8),
+    Absolute(u16),
+    AbsoluteX(u16),
+    AbsoluteY(u16),
+    Indirect(u16),
+    IndirectX(u8),
+    IndirectY(u8),
+    Relative(u16),
+}
+
+impl Operand {
+    /**
+     * The address this operand refers to before any indexing, if any.
+     */
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Operand::ZeroPage(addr)
+            | Operand::ZeroPageX(addr)
+            | Operand::ZeroPageY(addr)
+            | Operand::IndirectX(addr)
+            | Operand::IndirectY(addr) => Some(addr as u16),
+            Operand::Absolute(addr)
+            | Operand::AbsoluteX(addr)
+            | Operand::AbsoluteY(addr)
+            | Operand::Indirect(addr)
+            | Operand::Relative(addr) => Some(addr),
+            Operand::None | Operand::Accumulator | Operand::Immediate(_) => None,
+        }
+    }
+
+    pub fn format(&self, symbols: &dyn Symbols) -> String {
+        let zp = |addr: u8| match symbols.label(addr as u16) {
+            Some(label) => label.to_string(),
+            None => format!("${:02X}", addr),
+        };
+        let abs = |addr: u16| match symbols.label(addr) {
+            Some(label) => label.to_string(),
+            None => format!("${:04X}", addr),
+        };
+        match *self {
+            Operand::None => String::new(),
+            Operand::Accumulator => "A".to_string(),
+            Operand::Immediate(value) => format!("#${:02X}", value),
+            Operand::ZeroPage(addr) => zp(addr),
+            Operand::ZeroPageX(addr) => format!("{},X", zp(addr)),
+            Operand::ZeroPageY(addr) => format!("{},Y", zp(addr)),
+            Operand::Absolute(addr) | Operand::Relative(addr) => abs(addr),
+            Operand::AbsoluteX(addr) => format!("{},X", abs(addr)),
+            Operand::AbsoluteY(addr) => format!("{},Y", abs(addr)),
+            Operand::Indirect(addr) => format!("({})", abs(addr)),
+            Operand::IndirectX(addr) => format!("({},X)", zp(addr)),
+            Operand::IndirectY(addr) => format!("({}),Y", zp(addr)),
+        }
+    }
+}
+
+pub struct Instruction {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    /// None when the bytes do not form a complete, known instruction.
+    pub opcode: Option<&'static OpCode>,
+    pub operand: Operand,
+}
+
+impl Instruction {
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_unofficial(&self) -> bool {
+        self.opcode.is_some_and(|op| op.mnemonic.starts_with('*'))
+    }
+
+    /**
+     * Whether this instruction transfers control to its operand (JMP, JSR, branches).
+     */
+    pub fn is_control_flow(&self) -> bool {
+        self.opcode.is_some_and(|op| {
+            matches!(op.code, 0x4c | 0x20) || matches!(self.operand, Operand::Relative(_))
+        })
+    }
+
+    pub fn hex_bytes(&self) -> String {
+        self.bytes
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /**
+     * e.g. "LDA #$05", "JSR UpdatePlayer", ".byte $FF"
+     */
+    pub fn format(&self, symbols: &dyn Symbols) -> String {
+        match self.opcode {
+            Some(op) => format!("{} {}", op.mnemonic, self.operand.format(symbols))
+                .trim_end()
+                .to_string(),
+            None => format!(".byte {}", byte_list(&self.bytes)),
+        }
+    }
+}
+
+/**
+ * Decodes the instruction at the start of `bytes`, which live at `addr`.
+ * Unknown opcodes and instructions cut short by the end of the slice come back
+ * as a single data byte. None if `bytes` is empty.
+ */
+pub fn decode(bytes: &[u8], addr: u16) -> Option<Instruction> {
+    let data_byte = || Instruction {
+        addr,
+        bytes: bytes[..1].to_vec(),
+        opcode: None,
+        operand: Operand::None,
+    };
+
+    let op = match get_opcode_details(bytes.first()?) {
+        Some(op) => op,
+        None => return Some(data_byte()),
+    };
+    let len = 1 + op.additional_bytes as usize;
+    if bytes.len() < len {
+        return Some(data_byte());
+    }
+
+    let byte = bytes.get(1).copied().unwrap_or(0);
+    let word = u16::from_le_bytes([byte, bytes.get(2).copied().unwrap_or(0)]);
+    let operand = match (&op.mode, op.additional_bytes) {
+        (AddressingMode::Immediate, _) => Operand::Immediate(byte),
+        (AddressingMode::ZeroPage, _) => Operand::ZeroPage(byte),
+        (AddressingMode::ZeroPage_X, _) => Operand::ZeroPageX(byte),
+        (AddressingMode::ZeroPage_Y, _) => Operand::ZeroPageY(byte),
+        (AddressingMode::Absolute, _) => Operand::Absolute(word),
+        (AddressingMode::Absolute_X, _) => Operand::AbsoluteX(word),
+        (AddressingMode::Absolute_Y, _) => Operand::AbsoluteY(word),
+        (AddressingMode::Indirect, _) => Operand::Indirect(word),
+        (AddressingMode::Indirect_X, _) => Operand::IndirectX(byte),
+        (AddressingMode::Indirect_Y, _) => Operand::IndirectY(byte),
+        // the opcode table files jumps, branches and accumulator shifts under Implied
+        (AddressingMode::Implied, 0) => match op.code {
+            0x0a | 0x4a | 0x2a | 0x6a => Operand::Accumulator,
+            _ => Operand::None,
+        },
+        (AddressingMode::Implied, 1) => {
+            Operand::Relative(addr.wrapping_add(2).wrapping_add(byte as i8 as u16))
+        }
+        (AddressingMode::Implied, _) => match op.code {
+            0x6c => Operand::Indirect(word),
+            _ => Operand::Absolute(word),
+        },
+    };
+
+    Some(Instruction {
+        addr,
+        bytes: bytes[..len].to_vec(),
+        opcode: Some(op),
+        operand,
+    })
+}
+
+/**
+ * Reads the instruction at `addr` out of memory, without side effects.
+ */
+pub fn decode_at<M: Peek>(mem: &M, addr: u16) -> Instruction {
+    let opcode = mem.peek(addr);
+    let len = get_opcode_details(&opcode).map_or(1, |op| 1 + op.additional_bytes as usize);
+    let bytes: Vec<u8> = (0..len)
+        .map(|i| mem.peek(addr.wrapping_add(i as u16)))
+        .collect();
+    decode(&bytes, addr).expect("the opcode is always read")
+}
+
+/**
+ * Linear sweep over `bytes`, which are mapped starting at `origin`.
+ */
+pub fn disassemble(bytes: &[u8], origin: u16) -> Vec<Instruction> {
+    let mut instructions = vec![];
+    let mut offset = 0;
+    while let Some(instruction) = decode(&bytes[offset..], origin.wrapping_add(offset as u16)) {
+        offset += instruction.len();
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+/**
+ * Produces a listing that assembles back to the same bytes:
+ *  - jump and branch targets inside the range get labels (from `symbols` when available)
+ *    where an instruction starts; other addresses inside the range stay numeric, since
+ *    there is no line to put their label on
+ *  - unofficial opcodes are emitted as `.byte` with the mnemonic in a comment, since
+ *    most assemblers do not accept them
+ *  - absolute operands below $100 are forced with `a:` so they do not shrink to zero page
+ */
+pub fn listing(bytes: &[u8], origin: u16, symbols: &dyn Symbols) -> String {
+    let instructions = disassemble(bytes, origin);
+    let end = origin as usize + bytes.len();
+    let in_range = |addr: u16| (origin as usize..end).contains(&(addr as usize));
+
+    let starts: HashSet<u16> = instructions.iter().map(|i| i.addr).collect();
+    let targets: BTreeSet<u16> = instructions
+        .iter()
+        .filter(|i| i.is_control_flow())
+        .filter_map(|i| i.operand.target())
+        .filter(|addr| starts.contains(addr))
+        .collect();
+    let mut labels: HashMap<u16, String> = targets
+        .iter()
+        .map(|&addr| (addr, format!("L{:04X}", addr)))
+        .collect();
+    for i in &instructions {
+        if let Some(label) = symbols.label(i.addr) {
+            labels.insert(i.addr, label.to_string());
+        }
+    }
+    let names = Labels {
+        local: &labels,
+        symbols,
+        in_range: &in_range,
+    };
+
+    let mut out = format!(".org ${:04X}\n", origin);
+    for i in &instructions {
+        if let Some(label) = labels.get(&i.addr) {
+            out.push_str(&format!("{}:\n", label));
+        }
+        let line = match i.opcode {
+            Some(op) if i.is_unofficial() => format!(
+                ".byte {} ; {} {}",
+                byte_list(&i.bytes),
+                op.mnemonic,
+                i.operand.format(&names)
+            ),
+            Some(op) => {
+                let operand = match i.operand {
+                    Operand::Absolute(addr)
+                    | Operand::AbsoluteX(addr)
+                    | Operand::AbsoluteY(addr)
+                        if addr < 0x100 =>
+                    {
+                        format!("a:{}", i.operand.format(&names))
+                    }
+                    _ => i.operand.format(&names),
+                };
+                format!("{} {}", op.mnemonic, operand)
+            }
+            None => format!(".byte {}", byte_list(&i.bytes)),
+        };
+        out.push_str(&format!(
+            "    {:32}; {:04X}  {}\n",
+            line.trim_end(),
+            i.addr,
+            i.hex_bytes()
+        ));
+    }
+    out
+}
+
+/**
+ * Inside the listing only the labels it defines are used; external symbols name
+ * addresses outside it.
+ */
+struct Labels<'a> {
+    local: &'a HashMap<u16, String>,
+    symbols: &'a dyn Symbols,
+    in_range: &'a dyn Fn(u16) -> bool,
+}
+
+impl Symbols for Labels<'_> {
+    fn label(&self, addr: u16) -> Option<&str> {
+        if (self.in_range)(addr) {
+            return self.local.get(&addr).map(String::as_str);
+        }
+        self.symbols.label(addr)
+    }
+}
+
+fn byte_list(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("${:02X}", b))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn test_decode_modes() {
+        assert_eq!(decode(&[0xa9, 0x05], 0).unwrap().format(&()), "LDA #$05");
+        assert_eq!(decode(&[0x0a], 0).unwrap().format(&()), "ASL A");
+        assert_eq!(decode(&[0xb6, 0x10], 0).unwrap().format(&()), "LDX $10,Y");
+        assert_eq!(decode(&[0x91, 0x33], 0).unwrap().format(&()), "STA ($33),Y");
+        assert_eq!(
+            decode(&[0x6c, 0xff, 0x02], 0).unwrap().format(&()),
+            "JMP ($02FF)"
+        );
+        assert_eq!(
+            decode(&[0x20, 0xa4, 0xc3], 0).unwrap().format(&()),
+            "JSR $C3A4"
+        );
+        assert_eq!(decode(&[0xea], 0).unwrap().format(&()), "NOP");
+    }
+
+    #[test]
+    fn test_branch_targets_are_resolved() {
+        let forward = decode(&[0xd0, 0x04], 0xc000).unwrap();
+        assert_eq!(forward.operand, Operand::Relative(0xc006));
+        let backward = decode(&[0x10, 0xfe], 0xc000).unwrap();
+        assert_eq!(backward.format(&()), "BPL $C000");
+    }
+
+    #[test]
+    fn test_symbol_substitution() {
+        let mut symbols = HashMap::new();
+        symbols.insert(0xc3a4, "UpdatePlayer".to_string());
+        symbols.insert(0x0010, "player_x".to_string());
+        assert_eq!(
+            decode(&[0x20, 0xa4, 0xc3], 0).unwrap().format(&symbols),
+            "JSR UpdatePlayer"
+        );
+        assert_eq!(
+            decode(&[0xb5, 0x10], 0).unwrap().format(&symbols),
+            "LDA player_x,X"
+        );
+    }
+
+    #[test]
+    fn test_truncated_instruction_is_data() {
+        assert!(decode(&[], 0x8000).is_none());
+        let instructions = disassemble(&[0xea, 0xad, 0x00], 0x8000);
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(instructions[1].format(&()), ".byte $AD");
+    }
+
+    #[test]
+    fn test_listing() {
+        // loop: DEX; BNE loop; LAX $10; LDA $0010; RTS
+        let bytes = [0xca, 0xd0, 0xfd, 0xa7, 0x10, 0xad, 0x10, 0x00, 0x60];
+        let listing = listing(&bytes, 0x8000, &());
+        let lines: Vec<&str> = listing
+            .lines()
+            .map(|l| l.split(';').next().unwrap().trim())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ".org $8000",
+                "L8000:",
+                "DEX",
+                "BNE L8000",
+                ".byte $A7, $10",
+                "LDA a:$0010",
+                "RTS",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_listing_branch_into_an_instruction() {
+        // BNE $8003, into the operand of LDA #$00; BEQ $8000; RTS
+        let bytes = [0xd0, 0x01, 0xa9, 0x00, 0xf0, 0xfa, 0x60];
+        let mut symbols = HashMap::new();
+        symbols.insert(0x8003, "hidden".to_string());
+        let listing = listing(&bytes, 0x8000, &symbols);
+        assert!(listing.contains("BNE $8003"));
+        assert!(listing.contains("L8000:"));
+        assert!(!listing.contains("hidden"));
+        assert_eq!(assemble(&listing).unwrap().to_binary(), bytes);
+    }
+}
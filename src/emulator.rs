@@ -0,0 +1,597 @@
+use std::{path::Path, time::Duration};
+
+use crate::{
+    bus::Bus,
+    cpu::CPU,
+    frame::Frame,
+    frontend::{AudioSink, Command, DebugViews, InputSource, VideoSink},
+    gdb::{Debugger, SIGTRAP},
+    joypad::{JoypadButton, Pads, PLAYERS},
+    memview::MemoryConsole,
+    movie::MoviePlayback,
+    overscan::Overscan,
+    pacer::FramePacer,
+    palette::Rgb,
+    recorder::{Recorder, Recordings},
+    region::Region,
+    rom::Rom,
+    screenshot::Screenshots,
+    speed::SpeedControl,
+    trace::Tracer,
+};
+
+pub const AUDIO_SAMPLE_RATE: u32 = 44_100;
+/// how often a debugger is listened to while it has the program halted, without a pacer
+const HALTED_POLL: Duration = Duration::from_millis(10);
+
+/**
+ * The console plus the frame it last drew. Frontends drive it a frame at a time
+ * through `run`, or call `run_frame` themselves.
+ */
+pub struct Emulator {
+    pub cpu: CPU,
+    pub frame: Frame,
+    pub tracer: Option<Tracer>,
+    /// `None` runs as fast as possible
+    pub pacer: Option<FramePacer>,
+    pub speed: SpeedControl,
+    /// where the screenshot hotkey saves; `None` ignores it
+    pub screenshots: Option<Screenshots>,
+    /// PPU viewer windows
+    pub views: Option<Box<dyn DebugViews>>,
+    /// hex viewer in the terminal
+    pub memory: Option<MemoryConsole>,
+    /// GDB, which can halt the program at breakpoints or when interrupted
+    pub debugger: Option<Debugger>,
+    /// edges cropped off frames before they reach the `VideoSink`
+    pub overscan: Overscan,
+    /// the recording in progress
+    pub recorder: Option<Recorder>,
+    /// where the record hotkey saves; `None` ignores it
+    pub recordings: Option<Recordings>,
+    /// `run` returns once this many frames have been emulated
+    pub frame_limit: Option<u64>,
+    /// turbo presses per second
+    pub turbo_rate: u32,
+    /// buttons played back in place of the live ones, a frame at a time
+    pub movie: Option<MoviePlayback>,
+    frames: u64,
+    region: Region,
+    pads: Pads,
+    turbo: Pads,
+    audio: Vec<f32>,
+    sample_rate: u32,
+    /// fraction of a sample carried over between frames
+    sample_clock: f64,
+}
+
+impl Emulator {
+    pub fn new(rom: Rom, region: Region) -> Self {
+        let mut bus = Bus::new(rom);
+        bus.set_region(region);
+        let mut cpu = CPU::new(bus);
+        cpu.reset();
+        Emulator {
+            cpu,
+            frame: Frame::new(),
+            tracer: None,
+            pacer: None,
+            speed: SpeedControl::new(),
+            screenshots: None,
+            views: None,
+            memory: None,
+            debugger: None,
+            overscan: Overscan::default(),
+            recorder: None,
+            recordings: None,
+            frame_limit: None,
+            turbo_rate: 15,
+            movie: None,
+            frames: 0,
+            region,
+            pads: [JoypadButton::empty(); PLAYERS],
+            turbo: [JoypadButton::empty(); PLAYERS],
+            audio: vec![],
+            sample_rate: AUDIO_SAMPLE_RATE,
+            sample_clock: 0.0,
+        }
+    }
+
+    /**
+     * Runs the CPU until the PPU enters vblank and draws the finished picture into
+     * `frame`. Returns false if the program stopped on BRK. An attached debugger
+     * can halt it part way through, and the rest of the frame runs once it continues.
+     */
+    pub fn run_frame(&mut self) -> Result<bool, String> {
+        let start_cycles = self.cpu.bus.cycles();
+        let mut running = true;
+        let mut halted = false;
+        loop {
+            self.cpu.poll_interrupts();
+            if let Some(tracer) = &mut self.tracer {
+                tracer.trace(&self.cpu)?;
+            }
+            if !self.cpu.step() {
+                match &mut self.debugger {
+                    // the debugger gets to look at the program where it stopped
+                    Some(debugger) if debugger.is_attached() => {
+                        debugger.stop(SIGTRAP);
+                        halted = true;
+                    }
+                    _ => running = false,
+                }
+                break;
+            }
+            // before the frame ends, or a breakpoint on the next frame's first
+            // instruction would never be seen
+            if let Some(debugger) = &mut self.debugger {
+                if debugger.check_breakpoint(&self.cpu) {
+                    halted = true;
+                    break;
+                }
+            }
+            if self.cpu.bus.take_frame() {
+                break;
+            }
+        }
+        self.cpu.bus.render(&mut self.frame);
+        if !halted {
+            self.frames += 1;
+        }
+
+        // There is no APU yet: a frame's worth of silence keeps audio sinks in step
+        self.sample_clock +=
+            (self.cpu.bus.cycles() - start_cycles) as f64 * self.sample_rate as f64 / self.region.cpu_clock_hz();
+        let samples = self.sample_clock as usize;
+        self.sample_clock -= samples as f64;
+        self.audio.clear();
+        self.audio.resize(samples, 0.0);
+        Ok(running)
+    }
+
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        self.sample_rate = rate;
+    }
+
+    pub fn start_recording(&mut self, path: &Path, palette: &[Rgb]) -> Result<(), String> {
+        self.stop_recording()?;
+        self.recorder = Some(Recorder::create(
+            path,
+            palette,
+            self.region.frame_rate_ratio(),
+            self.sample_rate,
+        )?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) -> Result<(), String> {
+        if let Some(recorder) = self.recorder.take() {
+            println!("Saved {}", recorder.finish()?.display());
+        }
+        Ok(())
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    /**
+     * Samples produced by the last `run_frame`.
+     */
+    pub fn audio(&self) -> &[f32] {
+        &self.audio
+    }
+
+    pub fn run(
+        &mut self,
+        video: &mut dyn VideoSink,
+        audio: &mut dyn AudioSink,
+        input: &mut dyn InputSource,
+    ) -> Result<(), String> {
+        loop {
+            if self.frame_limit.is_some_and(|limit| self.frame_count() >= limit) {
+                return self.finish();
+            }
+            for command in input.poll(&mut self.pads) {
+                match command {
+                    Command::Quit => return self.finish(),
+                    Command::ToggleTrace => {
+                        if let Some(tracer) = &mut self.tracer {
+                            tracer.flush()?;
+                            tracer.set_enabled(!tracer.is_enabled());
+                        }
+                    }
+                    Command::TogglePause => self.speed.toggle_pause(),
+                    Command::FrameAdvance => self.speed.advance_frame(),
+                    Command::FastForward(on) => self.speed.set_fast_forward(on),
+                    Command::ToggleSlowMotion => self.speed.toggle_slow_motion(),
+                    Command::Screenshot => {
+                        if let Some(screenshots) = &self.screenshots {
+                            let path = screenshots.take(&self.frame)?;
+                            println!("Saved {}", path.display());
+                        }
+                    }
+                    Command::ToggleRecording => {
+                        if self.recorder.is_some() {
+                            self.stop_recording()?;
+                        } else if let Some(recordings) = &self.recordings {
+                            let recorder = recordings
+                                .start(self.region.frame_rate_ratio(), self.sample_rate)?;
+                            println!("Recording");
+                            self.recorder = Some(recorder);
+                        }
+                    }
+                    Command::ToggleMemoryViewer => {
+                        if let Some(memory) = &mut self.memory {
+                            memory.toggle();
+                        }
+                    }
+                    Command::ToggleCheats => {
+                        let enabled = !self.cpu.bus.cheats_enabled();
+                        self.cpu.bus.set_cheats_enabled(enabled);
+                        println!("Cheats {}", if enabled { "on" } else { "off" });
+                    }
+                    Command::CloseWindow(id) => {
+                        let viewer = self.views.as_mut().is_some_and(|views| views.close_window(id));
+                        if !viewer {
+                            return self.finish();
+                        }
+                    }
+                    _ => self.view_command(command)?,
+                }
+            }
+            self.turbo = input.turbo();
+
+            if let Some(debugger) = &mut self.debugger {
+                debugger.poll(&mut self.cpu)?;
+                if debugger.is_killed() {
+                    return self.finish();
+                }
+            }
+            let halted = self.is_halted();
+
+            // skipped frames are emulated but never presented
+            let frames = if halted { 0 } else { self.speed.frames_to_run() };
+            let frames = match self.frame_limit {
+                Some(limit) => frames.min(limit.saturating_sub(self.frame_count()) as u32),
+                None => frames,
+            };
+            for _ in 0..frames {
+                self.press_buttons();
+                let running = self.run_frame()?;
+                if let Some(memory) = &mut self.memory {
+                    memory.frame(&self.cpu.bus);
+                }
+                if let Some(recorder) = &mut self.recorder {
+                    recorder.record(&self.frame, &self.audio)?;
+                }
+                if self.speed.audible() {
+                    audio.queue(self.audio())?;
+                }
+                if !running {
+                    self.present(video)?;
+                    return self.finish();
+                }
+                if self.is_halted() {
+                    break;
+                }
+            }
+            if frames > 0 {
+                self.present(video)?;
+            }
+            if let Some(views) = &mut self.views {
+                views.update(self.cpu.bus.ppu())?;
+            }
+            if let Some(memory) = &mut self.memory {
+                memory.update(&mut self.cpu.bus)?;
+            }
+            if let Some(pacer) = &mut self.pacer {
+                pacer.set_speed(self.speed.pacing());
+                // nothing is queued while muted or halted, so the audio clock can't pace us
+                pacer.wait((self.speed.audible() && !halted).then_some(&*audio));
+            } else if halted {
+                std::thread::sleep(HALTED_POLL);
+            }
+        }
+    }
+
+    /**
+     * Hands the held buttons to the controllers, with turbo buttons down for the first
+     * half of every turbo period. Periods are counted in emulated frames, so the game
+     * sees the same rate in fast-forward and slow motion. A movie overrides both while
+     * it lasts.
+     */
+    fn press_buttons(&mut self) {
+        if let Some(frame) = self.movie.as_mut().and_then(MoviePlayback::next_frame) {
+            for (player, buttons) in frame.into_iter().enumerate() {
+                self.cpu.bus.set_buttons(player, buttons);
+            }
+            return;
+        }
+        let presses = 2.0 * self.turbo_rate.max(1) as f64;
+        let half_period = (self.region.frame_rate() / presses).round().max(1.0) as u64;
+        let turbo = (self.frame_count() / half_period).is_multiple_of(2);
+        for player in 0..PLAYERS {
+            let mut buttons = self.pads[player];
+            if turbo {
+                buttons |= self.turbo[player];
+            }
+            self.cpu.bus.set_buttons(player, buttons);
+        }
+    }
+
+    fn is_halted(&self) -> bool {
+        self.debugger.as_ref().is_some_and(Debugger::is_halted)
+    }
+
+    fn view_command(&mut self, command: Command) -> Result<(), String> {
+        let Some(views) = &mut self.views else {
+            return Ok(());
+        };
+        match command {
+            Command::ToggleViewer(viewer) => views.toggle(viewer)?,
+            Command::NextViewerPalette => views.next_palette(),
+            Command::ExportViewers => {
+                for path in views.export()? {
+                    println!("Saved {}", path.display());
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn present(&self, video: &mut dyn VideoSink) -> Result<(), String> {
+        if self.overscan.is_none() {
+            video.present(&self.frame)
+        } else {
+            video.present(&self.overscan.crop(&self.frame))
+        }
+    }
+
+    /**
+     * Flushes the trace and closes any recording when `run` returns.
+     */
+    fn finish(&mut self) -> Result<(), String> {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.flush()?;
+        }
+        self.stop_recording()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::cpu::Mem;
+    use crate::frontend::headless::{MemoryAudio, MemoryVideo, Null, ScriptedInput};
+    use crate::rom::HEADER_SIZE;
+
+    const E: JoypadButton = JoypadButton::empty();
+
+    const PROGRAM: &str = "
+  .org $8000
+reset:
+  lda #$3F       ; palette: backdrop $21, colour 1 $16
+  sta $2006
+  lda #$00
+  sta $2006
+  lda #$21
+  sta $2007
+  lda #$16
+  sta $2007
+  lda #$20       ; top left tile of the first nametable is tile 1
+  sta $2006
+  lda #$00
+  sta $2006
+  lda #$01
+  sta $2007
+  lda #$00
+  sta $2005
+  sta $2005
+  lda #%00001010 ; background, including the leftmost 8 pixels
+  sta $2001
+  lda #$80
+  sta $2000
+loop:
+  jmp loop
+nmi:             ; count frames with A held in $10
+  lda #$01
+  sta $4016
+  lda #$00
+  sta $4016
+  lda $4016
+  and #$01
+  clc
+  adc $10
+  sta $10
+  rti
+";
+
+    fn test_emulator_in(region: Region) -> Emulator {
+        let mut image = assemble(PROGRAM).unwrap().to_ines().unwrap();
+        // top row of tile 1 uses colour 1
+        let chr = HEADER_SIZE + 0x8000;
+        image[chr + 16] = 0xFF;
+        Emulator::new(Rom::new(&image).unwrap(), region)
+    }
+
+    fn test_emulator() -> Emulator {
+        test_emulator_in(Region::Ntsc)
+    }
+
+    #[test]
+    fn test_pal_frames_are_longer() {
+        let mut emulator = test_emulator_in(Region::Pal);
+        for _ in 0..3 {
+            emulator.run_frame().unwrap();
+        }
+        // 312 scanlines of 341 dots at 3.2 dots per cycle, 44100Hz / 50.007Hz
+        assert!((881..=883).contains(&emulator.audio().len()));
+    }
+
+    #[test]
+    fn test_frames_reach_the_sinks() {
+        let mut emulator = test_emulator();
+        let mut video = MemoryVideo::default();
+        let mut audio = MemoryAudio::default();
+        let mut input = ScriptedInput::new(vec![
+            [JoypadButton::BUTTON_A, E, E, E],
+            [E; 4],
+            [JoypadButton::BUTTON_A, E, E, E],
+        ]);
+        emulator.run(&mut video, &mut audio, &mut input).unwrap();
+
+        assert_eq!(video.frames, 3);
+        let frame = video.last_frame.unwrap();
+        assert_eq!(frame.pixel(0, 0), 0x16);
+        assert_eq!(frame.pixel(7, 0), 0x16);
+        assert_eq!(frame.pixel(8, 0), 0x21);
+        assert_eq!(frame.pixel(0, 1), 0x21);
+        // 44100Hz / 60.1Hz
+        assert!((733..=735).contains(&emulator.audio().len()));
+        assert_eq!(emulator.cpu.bus.ppu_position().0, 241);
+        assert!(audio.samples.len() > 2 * 733);
+    }
+
+    #[test]
+    fn test_joypad_is_read_in_nmi() {
+        let mut emulator = test_emulator();
+        let mut input = ScriptedInput::new(vec![
+            [JoypadButton::BUTTON_A, E, E, E],
+            [JoypadButton::BUTTON_A, E, E, E],
+            [E; 4],
+        ]);
+        let mut video = MemoryVideo::default();
+        let mut audio = MemoryAudio::default();
+        emulator.run(&mut video, &mut audio, &mut input).unwrap();
+        // each frame ends as vblank starts, so its NMI runs with the next frame's input
+        assert_eq!(emulator.cpu.bus.mem_read(0x10), 1);
+    }
+
+    #[test]
+    fn test_fast_forward_skips_frames() {
+        let mut emulator = test_emulator();
+        emulator.speed.set_fast_forward(true);
+        let mut video = MemoryVideo::default();
+        let mut audio = MemoryAudio::default();
+        emulator
+            .run(&mut video, &mut audio, &mut ScriptedInput::idle(2))
+            .unwrap();
+        assert_eq!(video.frames, 2);
+        // 4 frames per presented one, the first cut short by starting at reset
+        assert!((7 * 29_780..8 * 29_780).contains(&emulator.cpu.bus.cycles()));
+        assert!(audio.samples.is_empty());
+
+        emulator.speed.set_fast_forward(false);
+        emulator.speed.pause();
+        emulator
+            .run(&mut video, &mut audio, &mut ScriptedInput::idle(2))
+            .unwrap();
+        assert_eq!(video.frames, 2);
+    }
+
+    #[test]
+    fn test_overscan_is_cropped() {
+        let mut emulator = test_emulator();
+        emulator.overscan = Overscan::for_region(Region::Ntsc);
+        let mut video = MemoryVideo::default();
+        emulator
+            .run(&mut video, &mut Null, &mut ScriptedInput::idle(2))
+            .unwrap();
+        let frame = video.last_frame.unwrap();
+        assert_eq!((frame.width, frame.height), (256, 224));
+        // the first line of tile 1 is hidden
+        assert_eq!(frame.pixel(0, 0), 0x21);
+        assert_eq!(emulator.frame.pixel(0, 0), 0x16);
+    }
+
+    #[test]
+    fn test_frame_limit() {
+        let mut emulator = test_emulator();
+        emulator.frame_limit = Some(6);
+        emulator.speed.set_fast_forward(true);
+        let mut video = MemoryVideo::default();
+        emulator
+            .run(&mut video, &mut Null, &mut Null)
+            .unwrap();
+        assert_eq!(emulator.frame_count(), 6);
+        assert_eq!(video.frames, 2);
+    }
+
+    /// sends one batch of commands per poll, then quits
+    struct Commands(Vec<Vec<Command>>);
+
+    impl InputSource for Commands {
+        fn poll(&mut self, _pads: &mut Pads) -> Vec<Command> {
+            match self.0.is_empty() {
+                true => vec![Command::Quit],
+                false => self.0.remove(0),
+            }
+        }
+    }
+
+    fn play_movie(commands: Vec<Vec<Command>>) -> (u8, u64) {
+        let a = [JoypadButton::BUTTON_A, E, E, E];
+        let mut emulator = test_emulator();
+        emulator.movie = Some(MoviePlayback::new(vec![a, a, [E; 4], a, [E; 4], a, a, a]));
+        emulator.frame_limit = Some(9);
+        emulator
+            .run(&mut MemoryVideo::default(), &mut Null, &mut Commands(commands))
+            .unwrap();
+        (emulator.cpu.bus.mem_read(0x10), emulator.frame_count())
+    }
+
+    #[test]
+    fn test_movie_plays_one_frame_per_emulated_frame() {
+        let played = play_movie(vec![vec![]; 9]);
+        // each frame's NMI reads the next frame's buttons
+        assert_eq!(played, (5, 9));
+        let fast_forward = vec![vec![Command::FastForward(true)], vec![], vec![]];
+        assert_eq!(play_movie(fast_forward), played);
+        let mut paused = vec![vec![]; 14];
+        paused[2] = vec![Command::TogglePause];
+        paused[6] = vec![Command::TogglePause];
+        assert_eq!(play_movie(paused), played);
+    }
+
+    struct TurboA;
+
+    impl InputSource for TurboA {
+        fn poll(&mut self, _pads: &mut Pads) -> Vec<Command> {
+            vec![]
+        }
+
+        fn turbo(&self) -> Pads {
+            [JoypadButton::BUTTON_A, E, E, E]
+        }
+    }
+
+    fn turbo_presses(fast_forward: bool) -> u8 {
+        let mut emulator = test_emulator();
+        emulator.turbo_rate = 15;
+        emulator.frame_limit = Some(24);
+        emulator.speed.set_fast_forward(fast_forward);
+        emulator
+            .run(&mut MemoryVideo::default(), &mut Null, &mut TurboA)
+            .unwrap();
+        emulator.cpu.bus.mem_read(0x10)
+    }
+
+    #[test]
+    fn test_turbo_is_timed_in_emulated_frames() {
+        // 15 presses a second holds A for 2 frames out of every 4
+        let presses = turbo_presses(false);
+        assert!((11..=13).contains(&presses));
+        assert_eq!(turbo_presses(true), presses);
+
+        // a zero rate must not divide by zero
+        let mut emulator = test_emulator();
+        emulator.turbo_rate = 0;
+        emulator.frame_limit = Some(4);
+        emulator
+            .run(&mut MemoryVideo::default(), &mut Null, &mut TurboA)
+            .unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+use crate::palette::{Rgb, EMPHASIS_PALETTE_SIZE, PALETTE_SIZE};
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+
+/// how much the channels an emphasis bit does not cover are darkened by, for palettes
+/// without emphasis colours
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+/**
+ * One picture's worth of NES palette indices ($00-$3F), row by row.
+ * Sinks turn it into RGB with `to_rgb`, so the same frame can be shown with any palette.
+ */
+#[derive(Clone, PartialEq)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    /// PPUMASK's colour emphasis as red, green and blue in bits 0-2
+    pub emphasis: u8,
+}
+
+impl Frame {
+    pub fn new() -> Self {
+        Frame::with_size(WIDTH, HEIGHT)
+    }
+
+    pub fn with_size(width: usize, height: usize) -> Self {
+        Frame {
+            width,
+            height,
+            pixels: vec![0; width * height],
+            emphasis: 0,
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, colour: u8) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = colour;
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    /**
+     * RGB24, 3 bytes per pixel. Palettes with all 512 colours supply their own
+     * emphasised ones; otherwise each emphasis bit darkens the other two channels.
+     */
+    pub fn to_rgb(&self, palette: &[Rgb]) -> Vec<u8> {
+        let emphasis = self.emphasis as usize & 0b111;
+        let (palette, scale) = if palette.len() >= EMPHASIS_PALETTE_SIZE {
+            let start = emphasis * PALETTE_SIZE;
+            (&palette[start..start + PALETTE_SIZE], [1.0; 3])
+        } else {
+            let scale = |channel: usize| match emphasis & !(1 << channel) {
+                0 => 1.0,
+                _ => EMPHASIS_ATTENUATION,
+            };
+            (palette, [scale(0), scale(1), scale(2)])
+        };
+        let mut rgb = Vec::with_capacity(self.pixels.len() * 3);
+        for &index in &self.pixels {
+            let (r, g, b) = palette[index as usize % palette.len()];
+            if scale == [1.0; 3] {
+                rgb.extend([r, g, b]);
+            } else {
+                rgb.extend([
+                    (r as f32 * scale[0]) as u8,
+                    (g as f32 * scale[1]) as u8,
+                    (b as f32 * scale[2]) as u8,
+                ]);
+            }
+        }
+        rgb
+    }
+}
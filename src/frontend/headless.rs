@@ -0,0 +1,97 @@
+use super::{AudioSink, Command, InputSource, VideoSink};
+#[cfg(test)]
+use crate::joypad::{JoypadButton, PLAYERS};
+use crate::{frame::Frame, joypad::Pads};
+
+/// Discards frames and samples, and never presses a button.
+pub struct Null;
+
+impl VideoSink for Null {
+    fn present(&mut self, _frame: &Frame) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl AudioSink for Null {
+    fn queue(&mut self, _samples: &[f32]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl InputSource for Null {
+    fn poll(&mut self, _pads: &mut Pads) -> Vec<Command> {
+        vec![]
+    }
+}
+
+// the rest are for tests, which check what the run loop hands out and feed it input
+
+/// Keeps the most recent frame and counts how many were presented.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryVideo {
+    pub last_frame: Option<Frame>,
+    pub frames: usize,
+}
+
+#[cfg(test)]
+impl VideoSink for MemoryVideo {
+    fn present(&mut self, frame: &Frame) -> Result<(), String> {
+        self.last_frame = Some(frame.clone());
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+/// Collects every sample.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryAudio {
+    pub samples: Vec<f32>,
+}
+
+#[cfg(test)]
+impl AudioSink for MemoryAudio {
+    fn queue(&mut self, samples: &[f32]) -> Result<(), String> {
+        self.samples.extend_from_slice(samples);
+        Ok(())
+    }
+}
+
+/**
+ * Plays back controller state frame by frame, then asks to quit when the script
+ * runs out. An empty script with a frame count just runs that many frames.
+ */
+#[cfg(test)]
+pub struct ScriptedInput {
+    frames: Vec<Pads>,
+    position: usize,
+}
+
+#[cfg(test)]
+impl ScriptedInput {
+    pub fn new(frames: Vec<Pads>) -> Self {
+        ScriptedInput {
+            frames,
+            position: 0,
+        }
+    }
+
+    pub fn idle(frames: usize) -> Self {
+        ScriptedInput::new(vec![[JoypadButton::empty(); PLAYERS]; frames])
+    }
+}
+
+#[cfg(test)]
+impl InputSource for ScriptedInput {
+    fn poll(&mut self, pads: &mut Pads) -> Vec<Command> {
+        match self.frames.get(self.position) {
+            Some(frame) => {
+                *pads = *frame;
+                self.position += 1;
+                vec![]
+            }
+            None => vec![Command::Quit],
+        }
+    }
+}
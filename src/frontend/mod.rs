@@ -0,0 +1,94 @@
+pub mod headless;
+pub mod sdl;
+
+use std::path::PathBuf;
+
+use crate::{
+    frame::Frame,
+    joypad::{JoypadButton, Pads, PLAYERS},
+    ppu::{viewer::Viewer, PPU},
+};
+
+/**
+ * The core talks to the outside world through these three traits, once per frame:
+ * it asks the `InputSource` for buttons and hotkeys, runs the frame, then hands the
+ * picture to the `VideoSink` and that frame's samples to the `AudioSink`.
+ */
+pub trait VideoSink {
+    fn present(&mut self, frame: &Frame) -> Result<(), String>;
+}
+
+pub trait AudioSink {
+    /**
+     * Mono samples in -1.0..=1.0 at the emulator's sample rate (`emulator::AUDIO_SAMPLE_RATE` unless set).
+     */
+    fn queue(&mut self, samples: &[f32]) -> Result<(), String>;
+
+    /**
+     * Samples waiting to be played, if the sink knows. Used to sync to audio.
+     */
+    fn queued(&self) -> Option<usize> {
+        None
+    }
+}
+
+pub trait InputSource {
+    /**
+     * Updates the buttons held on each controller and returns any hotkeys pressed
+     * since the last poll.
+     */
+    fn poll(&mut self, pads: &mut Pads) -> Vec<Command>;
+
+    /**
+     * Buttons held as turbo buttons as of the last poll. The emulator presses and
+     * releases them at its `turbo_rate`.
+     */
+    fn turbo(&self) -> Pads {
+        [JoypadButton::empty(); PLAYERS]
+    }
+}
+
+/**
+ * Requests from the user that are about the emulator rather than the game.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Quit,
+    ToggleTrace,
+    TogglePause,
+    FrameAdvance,
+    /// held down rather than toggled
+    FastForward(bool),
+    ToggleSlowMotion,
+    Screenshot,
+    ToggleRecording,
+    ToggleViewer(Viewer),
+    NextViewerPalette,
+    ExportViewers,
+    ToggleMemoryViewer,
+    ToggleCheats,
+    /// the user closed a window; the main one means quit
+    CloseWindow(u32),
+}
+
+/**
+ * Debug windows showing the PPU's state, refreshed every time round the run loop
+ * whether or not the emulator is paused.
+ */
+pub trait DebugViews {
+    fn toggle(&mut self, viewer: Viewer) -> Result<(), String>;
+
+    /**
+     * Returns false if the window is not one of the viewers.
+     */
+    fn close_window(&mut self, window_id: u32) -> bool;
+
+    fn next_palette(&mut self);
+
+    fn update(&mut self, ppu: &PPU) -> Result<(), String>;
+
+    /**
+     * Saves what each open viewer shows, returning the files written.
+     */
+    fn export(&self) -> Result<Vec<PathBuf>, String>;
+}
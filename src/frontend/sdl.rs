@@ -0,0 +1,598 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use sdl2::{
+    audio::{AudioQueue, AudioSpecDesired},
+    controller::{self, GameController},
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+    pixels::PixelFormatEnum,
+    render::{Canvas, Texture, TextureCreator},
+    video::{Window, WindowContext},
+    AudioSubsystem, EventPump, GameControllerSubsystem, VideoSubsystem,
+};
+
+use super::{AudioSink, Command, DebugViews, InputSource, VideoSink};
+use crate::{
+    config::{Button, Config, Hotkey, BUTTON_NAMES},
+    frame::Frame,
+    joypad::{JoypadButton, Pads, PLAYERS},
+    palette::Rgb,
+    ppu::{
+        viewer::{Sprite, Viewer},
+        PPU,
+    },
+    screenshot::encode_png,
+    video::{Image, VideoPipeline},
+};
+
+/**
+ * Streams frames through the `VideoPipeline` into a texture stretched over the
+ * whole window. The texture is recreated whenever the picture's size changes.
+ */
+pub struct SdlVideo<'a> {
+    canvas: Canvas<Window>,
+    creator: &'a TextureCreator<WindowContext>,
+    texture: Option<(Texture<'a>, usize, usize)>,
+    pipeline: VideoPipeline,
+}
+
+impl<'a> SdlVideo<'a> {
+    pub fn new(
+        canvas: Canvas<Window>,
+        creator: &'a TextureCreator<WindowContext>,
+        pipeline: VideoPipeline,
+    ) -> Self {
+        SdlVideo {
+            canvas,
+            creator,
+            texture: None,
+            pipeline,
+        }
+    }
+}
+
+impl VideoSink for SdlVideo<'_> {
+    fn present(&mut self, frame: &Frame) -> Result<(), String> {
+        let image = self.pipeline.process(frame);
+        let reuse =
+            matches!(&self.texture, Some((_, w, h)) if (*w, *h) == (image.width, image.height));
+        if !reuse {
+            let texture = self
+                .creator
+                .create_texture_streaming(
+                    PixelFormatEnum::RGB24,
+                    image.width as u32,
+                    image.height as u32,
+                )
+                .map_err(|e| e.to_string())?;
+            self.texture = Some((texture, image.width, image.height));
+        }
+        let (texture, _, _) = self.texture.as_mut().unwrap();
+        texture
+            .update(None, &image.rgb, image.width * 3)
+            .map_err(|e| e.to_string())?;
+        self.canvas.copy(texture, None, None)?;
+        self.canvas.present();
+        Ok(())
+    }
+}
+
+/**
+ * The PPU viewers, each in a window of its own. Exports go to
+ * `<dir>/<name>-<viewer>-001.png` and up, with the sprite list next to the OAM one.
+ */
+pub struct SdlViewers {
+    video: VideoSubsystem,
+    palette: Vec<Rgb>,
+    dir: PathBuf,
+    name: String,
+    windows: Vec<(Viewer, Canvas<Window>)>,
+    pattern_palette: u8,
+    /// what the windows showed last, for `export`
+    images: Vec<(Viewer, Image)>,
+    sprites: Vec<Sprite>,
+}
+
+impl SdlViewers {
+    pub fn new(video: VideoSubsystem, palette: Vec<Rgb>, dir: PathBuf, name: String) -> Self {
+        SdlViewers {
+            video,
+            palette,
+            dir,
+            name,
+            windows: vec![],
+            pattern_palette: 0,
+            images: vec![],
+            sprites: vec![],
+        }
+    }
+
+    fn title(&self, viewer: Viewer) -> String {
+        match viewer {
+            Viewer::PatternTables => format!("PPU patterns - palette {}", self.pattern_palette),
+            _ => format!("PPU {}", viewer.name()),
+        }
+    }
+}
+
+impl DebugViews for SdlViewers {
+    fn toggle(&mut self, viewer: Viewer) -> Result<(), String> {
+        if let Some(open) = self.windows.iter().position(|(v, _)| *v == viewer) {
+            self.windows.remove(open);
+            self.images.retain(|(v, _)| *v != viewer);
+            return Ok(());
+        }
+        let (width, height) = viewer.size();
+        // small pictures get bigger windows
+        let scale = (512 / width.max(height)).max(1) as u32;
+        let canvas = self
+            .video
+            .window(
+                &self.title(viewer),
+                width as u32 * scale,
+                height as u32 * scale,
+            )
+            .resizable()
+            .build()
+            .map_err(|e| e.to_string())?
+            .into_canvas()
+            .build()
+            .map_err(|e| e.to_string())?;
+        self.windows.push((viewer, canvas));
+        Ok(())
+    }
+
+    fn close_window(&mut self, window_id: u32) -> bool {
+        let Some(open) = self
+            .windows
+            .iter()
+            .position(|(_, canvas)| canvas.window().id() == window_id)
+        else {
+            return false;
+        };
+        let (viewer, _) = self.windows.remove(open);
+        self.images.retain(|(v, _)| *v != viewer);
+        true
+    }
+
+    fn next_palette(&mut self) {
+        self.pattern_palette = (self.pattern_palette + 1) % 8;
+        let title = self.title(Viewer::PatternTables);
+        for (viewer, canvas) in &mut self.windows {
+            if *viewer == Viewer::PatternTables {
+                let _ = canvas.window_mut().set_title(&title);
+            }
+        }
+    }
+
+    fn update(&mut self, ppu: &PPU) -> Result<(), String> {
+        self.images.clear();
+        for (viewer, canvas) in &mut self.windows {
+            let image = viewer.render(ppu, self.pattern_palette, &self.palette);
+            let creator = canvas.texture_creator();
+            let mut texture = creator
+                .create_texture_streaming(
+                    PixelFormatEnum::RGB24,
+                    image.width as u32,
+                    image.height as u32,
+                )
+                .map_err(|e| e.to_string())?;
+            texture
+                .update(None, &image.rgb, image.width * 3)
+                .map_err(|e| e.to_string())?;
+            canvas.copy(&texture, None, None)?;
+            canvas.present();
+            self.images.push((*viewer, image));
+        }
+        self.sprites = ppu.sprites();
+        Ok(())
+    }
+
+    fn export(&self) -> Result<Vec<PathBuf>, String> {
+        if !self.dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(&self.dir)
+                .map_err(|e| format!("Cannot create {}: {e}", self.dir.display()))?;
+        }
+        let file = |viewer: Viewer, n: usize, extension: &str| {
+            let name = format!("{}-{}-{n:03}.{extension}", self.name, viewer.name());
+            self.dir.join(name)
+        };
+        let n = (1..)
+            .find(|&n| {
+                self.images
+                    .iter()
+                    .all(|&(v, _)| !file(v, n, "png").exists())
+            })
+            .unwrap();
+        let mut saved = vec![];
+        for (viewer, image) in &self.images {
+            let path = file(*viewer, n, "png");
+            std::fs::write(&path, encode_png(image.width, image.height, &image.rgb))
+                .map_err(|e| format!("Cannot write {}: {e}", path.display()))?;
+            saved.push(path);
+            if *viewer == Viewer::Oam {
+                let path = file(Viewer::Oam, n, "txt");
+                let list: String = self.sprites.iter().map(|s| format!("{s}\n")).collect();
+                std::fs::write(&path, list)
+                    .map_err(|e| format!("Cannot write {}: {e}", path.display()))?;
+                saved.push(path);
+            }
+        }
+        Ok(saved)
+    }
+}
+
+pub struct SdlAudio {
+    queue: AudioQueue<f32>,
+}
+
+impl SdlAudio {
+    pub fn new(audio: &AudioSubsystem, sample_rate: u32) -> Result<Self, String> {
+        let spec = AudioSpecDesired {
+            freq: Some(sample_rate as i32),
+            channels: Some(1),
+            samples: Some(1024),
+        };
+        let queue = audio.open_queue::<f32, _>(None, &spec)?;
+        queue.resume();
+        Ok(SdlAudio { queue })
+    }
+}
+
+impl AudioSink for SdlAudio {
+    fn queue(&mut self, samples: &[f32]) -> Result<(), String> {
+        self.queue.queue_audio(samples)
+    }
+
+    fn queued(&self) -> Option<usize> {
+        Some(self.queue.size() as usize / std::mem::size_of::<f32>())
+    }
+}
+
+/// what one player is holding on one kind of device
+#[derive(Debug, Clone, Copy, Default)]
+struct Held {
+    buttons: JoypadButton,
+    turbo: JoypadButton,
+    /// d-pad directions from the left analog stick
+    stick: JoypadButton,
+}
+
+impl Held {
+    fn set(&mut self, button: Button, pressed: bool) {
+        let (held, button) = match button {
+            Button::Pad(button) => (&mut self.buttons, button),
+            Button::Turbo(button) => (&mut self.turbo, button),
+        };
+        held.set(button, pressed);
+    }
+}
+
+/// bindings for one player being collected by the rebinding prompt
+struct Rebinding {
+    player: usize,
+    step: usize,
+    keys: Vec<(Button, String)>,
+    gamepad: Vec<(Button, String)>,
+}
+
+/**
+ * Keyboard and SDL game controllers for up to four players, plus hotkeys, all
+ * bound as the config says. Controllers can come and go while running: each new
+ * one goes to the first free player whose `device` matches its name, or else to the
+ * first free player without a `device`.
+ */
+pub struct SdlInput {
+    event_pump: EventPump,
+    subsystem: GameControllerSubsystem,
+    config: Config,
+    /// where rebound controls get saved
+    config_path: Option<PathBuf>,
+    keymap: HashMap<Keycode, (usize, Button)>,
+    hotkeys: HashMap<Keycode, Hotkey>,
+    padmap: Vec<HashMap<controller::Button, Button>>,
+    /// open controllers by joystick instance id, with the player each one drives
+    controllers: HashMap<u32, (GameController, usize)>,
+    keyboard: [Held; PLAYERS],
+    gamepads: [Held; PLAYERS],
+    rebinding: Option<Rebinding>,
+}
+
+impl SdlInput {
+    pub fn new(
+        event_pump: EventPump,
+        subsystem: GameControllerSubsystem,
+        config: &Config,
+    ) -> Result<Self, String> {
+        let mut input = SdlInput {
+            event_pump,
+            subsystem,
+            config: config.clone(),
+            config_path: None,
+            keymap: HashMap::new(),
+            hotkeys: HashMap::new(),
+            padmap: vec![],
+            controllers: HashMap::new(),
+            keyboard: [Held::default(); PLAYERS],
+            gamepads: [Held::default(); PLAYERS],
+            rebinding: None,
+        };
+        input.bind()?;
+        Ok(input)
+    }
+
+    pub fn save_bindings_to(&mut self, path: PathBuf) {
+        self.config_path = Some(path);
+    }
+
+    /**
+     * Builds the lookup tables from the config. Unbound (empty) names are skipped.
+     */
+    fn bind(&mut self) -> Result<(), String> {
+        let key = |name: &str| {
+            Keycode::from_name(name).ok_or(format!("Unknown key {name:?} in the config"))
+        };
+        self.keymap.clear();
+        self.hotkeys.clear();
+        self.padmap.clear();
+        for (player, bindings) in self.config.players.iter().enumerate() {
+            for (button, name) in bindings.keys.iter().filter(|(_, name)| !name.is_empty()) {
+                self.keymap.insert(key(name)?, (player, *button));
+            }
+            let mut padmap = HashMap::new();
+            for (button, name) in bindings.gamepad.iter().filter(|(_, name)| !name.is_empty()) {
+                let pad_button = controller::Button::from_string(name)
+                    .ok_or(format!("Unknown controller button {name:?} in the config"))?;
+                padmap.insert(pad_button, *button);
+            }
+            self.padmap.push(padmap);
+        }
+        for (hotkey, name) in self
+            .config
+            .hotkeys
+            .iter()
+            .filter(|(_, name)| !name.is_empty())
+        {
+            self.hotkeys.insert(key(name)?, *hotkey);
+        }
+        Ok(())
+    }
+
+    fn connect(&mut self, index: u32) {
+        let controller = match self.subsystem.open(index) {
+            Ok(controller) => controller,
+            Err(e) => {
+                eprintln!("Cannot open controller {index}: {e}");
+                return;
+            }
+        };
+        let name = controller.name();
+        let taken: Vec<usize> = self.controllers.values().map(|&(_, p)| p).collect();
+        let players = &self.config.players;
+        let mut free = (0..PLAYERS).filter(|p| !taken.contains(p));
+        let player = free
+            .clone()
+            .find(|&p| {
+                let device = players[p].device.to_lowercase();
+                !device.is_empty() && name.to_lowercase().contains(&device)
+            })
+            .or_else(|| free.find(|&p| players[p].device.is_empty()));
+        match player {
+            Some(player) => {
+                println!("{name} connected as player {}", player + 1);
+                self.controllers
+                    .insert(controller.instance_id(), (controller, player));
+            }
+            None => println!("{name} connected, but no player is free to use it"),
+        }
+    }
+
+    fn disconnect(&mut self, instance_id: u32) {
+        if let Some((controller, player)) = self.controllers.remove(&instance_id) {
+            println!(
+                "{} disconnected from player {}",
+                controller.name(),
+                player + 1
+            );
+            self.gamepads[player] = Held::default();
+        }
+    }
+
+    fn stick(&mut self, player: usize, axis: controller::Axis, value: i16) {
+        let (negative, positive) = match axis {
+            controller::Axis::LeftX => (JoypadButton::LEFT, JoypadButton::RIGHT),
+            controller::Axis::LeftY => (JoypadButton::UP, JoypadButton::DOWN),
+            _ => return,
+        };
+        let threshold = i16::MAX as i32 * self.config.input.analog_threshold as i32 / 100;
+        let stick = &mut self.gamepads[player].stick;
+        stick.remove(negative | positive);
+        if (value as i32) < -threshold {
+            stick.insert(negative);
+        } else if value as i32 > threshold {
+            stick.insert(positive);
+        }
+    }
+
+    fn start_rebinding(&mut self, player: usize) {
+        let bindings = &self.config.players[player];
+        self.rebinding = Some(Rebinding {
+            player,
+            step: 0,
+            keys: bindings.keys.clone(),
+            gamepad: bindings.gamepad.clone(),
+        });
+        self.prompt();
+    }
+
+    fn prompt(&self) {
+        let Some(rebinding) = &self.rebinding else {
+            return;
+        };
+        let (name, _) = BUTTON_NAMES[rebinding.step];
+        let mut line = format!(
+            "Player {}: press a key or controller button for {name}",
+            rebinding.player + 1
+        );
+        if rebinding.step == 0 {
+            line += " (rebind again for the next player, quit to cancel)";
+        }
+        println!("{line}");
+    }
+
+    /**
+     * Keys go to the keyboard bindings and controller buttons to the controller
+     * bindings, whichever controller they come from.
+     */
+    fn rebind(&mut self, key: Option<Keycode>, pad_button: Option<controller::Button>) {
+        let Some(rebinding) = &mut self.rebinding else {
+            return;
+        };
+        let step = rebinding.step;
+        if let Some(key) = key {
+            match self.hotkeys.get(&key) {
+                Some(Hotkey::Quit) => {
+                    println!("Rebinding cancelled");
+                    self.rebinding = None;
+                    return;
+                }
+                Some(Hotkey::Rebind) if step == 0 => {
+                    let next = (rebinding.player + 1) % PLAYERS;
+                    self.start_rebinding(next);
+                    return;
+                }
+                _ => rebinding.keys[step].1 = key.name(),
+            }
+        }
+        if let Some(pad_button) = pad_button {
+            rebinding.gamepad[step].1 = pad_button.string();
+        }
+        rebinding.step += 1;
+        if rebinding.step < BUTTON_NAMES.len() {
+            self.prompt();
+            return;
+        }
+
+        let Rebinding {
+            player,
+            keys,
+            gamepad,
+            ..
+        } = self.rebinding.take().unwrap();
+        let bindings = &mut self.config.players[player];
+        bindings.keys = keys;
+        bindings.gamepad = gamepad;
+        self.keyboard[player] = Held::default();
+        if let Err(e) = self.bind() {
+            eprintln!("{e}");
+        }
+        match &self.config_path {
+            Some(path) => match self.config.save(path) {
+                Ok(()) => println!("Player {} controls saved", player + 1),
+                Err(e) => eprintln!("{e}"),
+            },
+            None => println!("Player {} controls changed", player + 1),
+        }
+    }
+}
+
+impl InputSource for SdlInput {
+    fn poll(&mut self, pads: &mut Pads) -> Vec<Command> {
+        let mut commands = vec![];
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in events {
+            match event {
+                Event::Quit { .. } => commands.push(Command::Quit),
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Close,
+                    ..
+                } => commands.push(Command::CloseWindow(window_id)),
+                Event::ControllerDeviceAdded { which, .. } => self.connect(which),
+                Event::ControllerDeviceRemoved { which, .. } => self.disconnect(which),
+                Event::KeyDown {
+                    keycode: Some(key),
+                    repeat,
+                    ..
+                } => {
+                    if self.rebinding.is_some() {
+                        if !repeat {
+                            self.rebind(Some(key), None);
+                        }
+                        continue;
+                    }
+                    if let Some(&(player, button)) = self.keymap.get(&key) {
+                        self.keyboard[player].set(button, true);
+                    }
+                    let command = match self.hotkeys.get(&key) {
+                        Some(Hotkey::Quit) => Some(Command::Quit),
+                        // frame advance repeats while held, the others act once
+                        Some(Hotkey::FrameAdvance) => Some(Command::FrameAdvance),
+                        Some(_) if repeat => None,
+                        Some(Hotkey::ToggleTrace) => Some(Command::ToggleTrace),
+                        Some(Hotkey::Pause) => Some(Command::TogglePause),
+                        Some(Hotkey::FastForward) => Some(Command::FastForward(true)),
+                        Some(Hotkey::SlowMotion) => Some(Command::ToggleSlowMotion),
+                        Some(Hotkey::Screenshot) => Some(Command::Screenshot),
+                        Some(Hotkey::Record) => Some(Command::ToggleRecording),
+                        Some(Hotkey::Viewer(viewer)) => Some(Command::ToggleViewer(*viewer)),
+                        Some(Hotkey::ViewerPalette) => Some(Command::NextViewerPalette),
+                        Some(Hotkey::ExportViewers) => Some(Command::ExportViewers),
+                        Some(Hotkey::MemoryViewer) => Some(Command::ToggleMemoryViewer),
+                        Some(Hotkey::ToggleCheats) => Some(Command::ToggleCheats),
+                        Some(Hotkey::Rebind) => {
+                            self.start_rebinding(0);
+                            None
+                        }
+                        None => None,
+                    };
+                    commands.extend(command);
+                }
+                Event::KeyUp {
+                    keycode: Some(key), ..
+                } => {
+                    if let Some(&(player, button)) = self.keymap.get(&key) {
+                        self.keyboard[player].set(button, false);
+                    }
+                    if self.hotkeys.get(&key) == Some(&Hotkey::FastForward) {
+                        commands.push(Command::FastForward(false));
+                    }
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    if self.rebinding.is_some() {
+                        self.rebind(None, Some(button));
+                    } else if let Some(&(_, player)) = self.controllers.get(&which) {
+                        if let Some(&binding) = self.padmap[player].get(&button) {
+                            self.gamepads[player].set(binding, true);
+                        }
+                    }
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    if let Some(&(_, player)) = self.controllers.get(&which) {
+                        if let Some(&binding) = self.padmap[player].get(&button) {
+                            self.gamepads[player].set(binding, false);
+                        }
+                    }
+                }
+                Event::ControllerAxisMotion {
+                    which, axis, value, ..
+                } => {
+                    if let Some(&(_, player)) = self.controllers.get(&which) {
+                        self.stick(player, axis, value);
+                    }
+                }
+                _ => { /* do nothing */ }
+            }
+        }
+
+        for (player, pad) in pads.iter_mut().enumerate() {
+            let (keyboard, gamepad) = (self.keyboard[player], self.gamepads[player]);
+            *pad = keyboard.buttons | gamepad.buttons | gamepad.stick;
+        }
+        commands
+    }
+
+    fn turbo(&self) -> Pads {
+        std::array::from_fn(|player| self.keyboard[player].turbo | self.gamepads[player].turbo)
+    }
+}
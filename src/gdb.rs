@@ -0,0 +1,761 @@
+use std::{
+    collections::HashSet,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+};
+
+use crate::cpu::{Peek, StatusFlags, CPU};
+
+const INTERRUPT: u8 = 0x03;
+const SIGINT: &str = "S02";
+pub const SIGTRAP: &str = "S05";
+/// largest packet we accept or send, advertised in `qSupported`
+const PACKET_SIZE: usize = 0x1000;
+
+enum Action {
+    Reply(String),
+    Step,
+    Continue,
+    Detach,
+    Kill,
+}
+
+/**
+ * Minimal GDB Remote Serial Protocol stub for the 6502 core.
+ * https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+ *
+ * Register numbering used by `g`/`G`/`p`/`P`:
+ *  0: A, 1: X, 2: Y, 3: P, 4: SP (all 8 bits), 5: PC (16 bits, little endian)
+ *
+ * `m`/`M` cover the whole CPU address space through `Peek`, without side effects;
+ * writes to latched registers like $4016 fail with E01.
+ */
+pub struct GdbStub {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    breakpoints: HashSet<u16>,
+    /// the debugger has asked the program to continue
+    running: bool,
+    /// the debugger has asked for the program to be stopped for good
+    killed: bool,
+}
+
+impl GdbStub {
+    /**
+     * A session starts with the program halted, as the debugger expects.
+     */
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        Ok(GdbStub {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            breakpoints: HashSet::new(),
+            running: false,
+            killed: false,
+        })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /**
+     * Serves the requests that have arrived, without waiting for more. While the
+     * program runs, only an interrupt is looked for. Returns false once the debugger
+     * has detached or killed the session.
+     */
+    pub fn poll(&mut self, cpu: &mut CPU) -> io::Result<bool> {
+        loop {
+            if self.running {
+                if !self.poll_interrupt()? {
+                    return Ok(true);
+                }
+                self.stop(SIGINT)?;
+            }
+            if !self.packet_waiting()? {
+                return Ok(true);
+            }
+            let packet = match self.read_packet()? {
+                Some(packet) => packet,
+                None => return Err(io::Error::new(ErrorKind::UnexpectedEof, "GDB hung up")),
+            };
+            match self.handle(cpu, &packet) {
+                Action::Reply(reply) => self.send_packet(&reply)?,
+                Action::Step => {
+                    cpu.poll_interrupts();
+                    cpu.step();
+                    self.send_packet(SIGTRAP)?;
+                }
+                Action::Continue => self.running = true,
+                Action::Detach => {
+                    self.send_packet("OK")?;
+                    return Ok(false);
+                }
+                Action::Kill => {
+                    self.killed = true;
+                    self.send_packet("OK")?;
+                    return Ok(false);
+                }
+            }
+        }
+    }
+
+    /**
+     * Called after each instruction the program runs. Returns true if it halted on
+     * a breakpoint.
+     */
+    pub fn check_breakpoint(&mut self, cpu: &CPU) -> io::Result<bool> {
+        if self.running && self.breakpoints.contains(&cpu.program_counter) {
+            self.stop(SIGTRAP)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /**
+     * Halts the program and tells the debugger why, e.g. with `SIGTRAP`.
+     */
+    pub fn stop(&mut self, signal: &str) -> io::Result<()> {
+        self.running = false;
+        self.send_packet(signal)
+    }
+
+    fn handle(&mut self, cpu: &mut CPU, packet: &str) -> Action {
+        let (command, args) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => SIGTRAP.to_string(),
+            "g" => encode_hex(&read_registers(cpu)),
+            "G" => match decode_hex(args) {
+                Some(bytes) if bytes.len() == 7 => {
+                    write_registers(cpu, &bytes);
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "p" => match parse_hex(args).and_then(|reg| read_register(cpu, reg)) {
+                Some(bytes) => encode_hex(&bytes),
+                None => "E01".to_string(),
+            },
+            "P" => match args
+                .split_once('=')
+                .and_then(|(reg, value)| Some((parse_hex(reg)?, decode_hex(value)?)))
+            {
+                Some((reg, bytes)) if write_register(cpu, reg, &bytes) => "OK".to_string(),
+                _ => "E01".to_string(),
+            },
+            "m" => match parse_range(args) {
+                // a short read is allowed, and two hex digits per byte must fit a packet
+                Some((addr, len)) => encode_hex(&read_memory(cpu, addr, len.min(PACKET_SIZE / 2))),
+                None => "E01".to_string(),
+            },
+            "M" => match args
+                .split_once(':')
+                .and_then(|(range, data)| Some((parse_range(range)?, decode_hex(data)?)))
+            {
+                Some(((addr, len), bytes))
+                    if bytes.len() == len && write_memory(cpu, addr, &bytes) =>
+                {
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "Z" | "z" => match parse_breakpoint(args) {
+                Some(addr) => {
+                    if command == "Z" {
+                        self.breakpoints.insert(addr);
+                    } else {
+                        self.breakpoints.remove(&addr);
+                    }
+                    "OK".to_string()
+                }
+                // only software breakpoints (type 0) are supported
+                None => String::new(),
+            },
+            "s" | "c" => {
+                if let Some(addr) = parse_hex(args) {
+                    cpu.program_counter = addr as u16;
+                }
+                return if command == "s" {
+                    Action::Step
+                } else {
+                    Action::Continue
+                };
+            }
+            "H" => "OK".to_string(),
+            "D" => return Action::Detach,
+            "k" => return Action::Kill,
+            "q" if args.starts_with("Supported") => format!("PacketSize={PACKET_SIZE:x}"),
+            "q" if args == "Attached" => "1".to_string(),
+            "q" if args == "C" => "QC1".to_string(),
+            _ => String::new(),
+        };
+        Action::Reply(reply)
+    }
+
+    fn poll_interrupt(&mut self) -> io::Result<bool> {
+        if !self.reader.buffer().is_empty() {
+            let interrupted = self.reader.buffer()[0] == INTERRUPT;
+            if interrupted {
+                self.reader.consume(1);
+            }
+            return Ok(interrupted);
+        }
+
+        self.writer.set_nonblocking(true)?;
+        let mut byte = [0u8; 1];
+        let result = self.reader.get_mut().read(&mut byte);
+        self.writer.set_nonblocking(false)?;
+        match result {
+            Ok(1) => Ok(byte[0] == INTERRUPT),
+            Ok(_) => Err(io::Error::new(ErrorKind::UnexpectedEof, "GDB hung up")),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /**
+     * Whether the start of a packet has arrived, or the debugger hung up. Stray acks
+     * and interrupts received while halted are dropped.
+     */
+    fn packet_waiting(&mut self) -> io::Result<bool> {
+        loop {
+            let skip = self
+                .reader
+                .buffer()
+                .iter()
+                .take_while(|&&b| b != b'$')
+                .count();
+            self.reader.consume(skip);
+            if !self.reader.buffer().is_empty() {
+                return Ok(true);
+            }
+
+            self.writer.set_nonblocking(true)?;
+            let result = self.reader.fill_buf().map(|buf| buf.is_empty());
+            self.writer.set_nonblocking(false)?;
+            match result {
+                // read_packet finds out it hung up
+                Ok(true) => return Ok(true),
+                Ok(false) => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /**
+     * Reads the next `$<data>#<checksum>` packet, acknowledging it.
+     * Stray acks and interrupts received while halted are ignored.
+     * Returns None once the connection is closed.
+     */
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let mut skipped = Vec::new();
+            if self.reader.read_until(b'$', &mut skipped)? == 0 || skipped.last() != Some(&b'$') {
+                return Ok(None);
+            }
+
+            let mut data = Vec::new();
+            if self.reader.read_until(b'#', &mut data)? == 0 || data.pop() != Some(b'#') {
+                return Ok(None);
+            }
+            let mut checksum = [0u8; 2];
+            self.reader.read_exact(&mut checksum)?;
+
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok());
+            if expected == Some(packet_checksum(&data)) {
+                self.writer.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+            self.writer.write_all(b"-")?;
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        write!(
+            self.writer,
+            "${}#{:02x}",
+            data,
+            packet_checksum(data.as_bytes())
+        )?;
+        self.writer.flush()?;
+
+        // wait for the debugger to acknowledge, resending on a NAK
+        let mut ack = [0u8; 1];
+        loop {
+            self.reader.read_exact(&mut ack)?;
+            match ack[0] {
+                b'+' => return Ok(()),
+                b'-' => {
+                    write!(
+                        self.writer,
+                        "${}#{:02x}",
+                        data,
+                        packet_checksum(data.as_bytes())
+                    )?;
+                    self.writer.flush()?;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/**
+ * Lets debuggers attach to the running emulator, one at a time. Once one detaches
+ * or hangs up, the program carries on and another can connect.
+ */
+pub struct Debugger {
+    listener: TcpListener,
+    session: Option<GdbStub>,
+    killed: bool,
+}
+
+impl Debugger {
+    /**
+     * Blocks until a debugger connects to `addr`, e.g. "127.0.0.1:9001", so the
+     * program can be debugged from reset.
+     */
+    pub fn listen<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Debugger::accept(TcpListener::bind(addr)?)
+    }
+
+    pub fn accept(listener: TcpListener) -> io::Result<Self> {
+        println!("Waiting for GDB on {}", listener.local_addr()?);
+        let (stream, peer) = listener.accept()?;
+        println!("GDB connected from {peer}");
+        listener.set_nonblocking(true)?;
+        Ok(Debugger {
+            listener,
+            session: Some(GdbStub::new(stream)?),
+            killed: false,
+        })
+    }
+
+    /**
+     * Whether a debugger is attached and has the program halted.
+     */
+    pub fn is_halted(&self) -> bool {
+        self.session
+            .as_ref()
+            .is_some_and(|session| !session.is_running())
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.session.is_some()
+    }
+
+    /**
+     * Whether a debugger has killed the program, which ends emulation.
+     */
+    pub fn is_killed(&self) -> bool {
+        self.killed
+    }
+
+    /**
+     * Takes a new connection if there is no session, then serves the requests that
+     * have arrived. Called once a frame, and while halted.
+     */
+    pub fn poll(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        if self.session.is_none() {
+            match self.listener.accept() {
+                Ok((stream, peer)) => {
+                    println!("GDB connected from {peer}");
+                    let session =
+                        GdbStub::new(stream).map_err(|e| format!("GDB session failed: {e}"))?;
+                    self.session = Some(session);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(format!("Cannot accept a debugger: {e}")),
+            }
+        }
+        if let Some(session) = &mut self.session {
+            let attached = session.poll(cpu);
+            self.killed |= session.killed;
+            self.keep_session(attached);
+        }
+        Ok(())
+    }
+
+    /**
+     * Called after each instruction. Returns true if the program halted on a
+     * breakpoint.
+     */
+    pub fn check_breakpoint(&mut self, cpu: &CPU) -> bool {
+        let Some(session) = &mut self.session else {
+            return false;
+        };
+        let halted = session.check_breakpoint(cpu);
+        let stopped = matches!(halted, Ok(true));
+        self.keep_session(halted.map(|_| true));
+        stopped
+    }
+
+    /**
+     * Halts the program, e.g. when it hits BRK, and tells the debugger.
+     */
+    pub fn stop(&mut self, signal: &str) {
+        if let Some(session) = &mut self.session {
+            let stopped = session.stop(signal);
+            self.keep_session(stopped.map(|_| true));
+        }
+    }
+
+    /**
+     * A debugger that goes away only ends its session; the program keeps running.
+     */
+    fn keep_session(&mut self, attached: io::Result<bool>) {
+        match attached {
+            Ok(true) => return,
+            Ok(false) => println!("GDB detached"),
+            Err(e) => println!("GDB disconnected: {e}"),
+        }
+        self.session = None;
+    }
+}
+
+fn packet_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn read_registers(cpu: &CPU) -> Vec<u8> {
+    let [pc_lo, pc_hi] = cpu.program_counter.to_le_bytes();
+    vec![
+        cpu.register_a,
+        cpu.register_x,
+        cpu.register_y,
+        cpu.status.bits(),
+        cpu.stack_ptr,
+        pc_lo,
+        pc_hi,
+    ]
+}
+
+fn write_registers(cpu: &mut CPU, bytes: &[u8]) {
+    cpu.register_a = bytes[0];
+    cpu.register_x = bytes[1];
+    cpu.register_y = bytes[2];
+    cpu.status = StatusFlags::from_bits_truncate(bytes[3]);
+    cpu.stack_ptr = bytes[4];
+    cpu.program_counter = u16::from_le_bytes([bytes[5], bytes[6]]);
+}
+
+fn read_register(cpu: &CPU, reg: usize) -> Option<Vec<u8>> {
+    let registers = read_registers(cpu);
+    match reg {
+        0..=4 => Some(vec![registers[reg]]),
+        5 => Some(registers[5..7].to_vec()),
+        _ => None,
+    }
+}
+
+fn write_register(cpu: &mut CPU, reg: usize, bytes: &[u8]) -> bool {
+    match (reg, bytes) {
+        (0, [value]) => cpu.register_a = *value,
+        (1, [value]) => cpu.register_x = *value,
+        (2, [value]) => cpu.register_y = *value,
+        (3, [value]) => cpu.status = StatusFlags::from_bits_truncate(*value),
+        (4, [value]) => cpu.stack_ptr = *value,
+        (5, [lo, hi]) => cpu.program_counter = u16::from_le_bytes([*lo, *hi]),
+        _ => return false,
+    }
+    true
+}
+
+/**
+ * Registers read as they would for the program, but without the side effects.
+ */
+fn read_memory(cpu: &CPU, addr: u16, len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|offset| cpu.peek(addr.wrapping_add(offset as u16)))
+        .collect()
+}
+
+/**
+ * Writes go through `Peek::poke`, so RAM, PPU state and even PRG ROM can be
+ * patched, but registers that only a read or write can change are refused. An empty
+ * write is how GDB asks whether writes are supported at all.
+ */
+fn write_memory(cpu: &mut CPU, addr: u16, bytes: &[u8]) -> bool {
+    if addr as usize + bytes.len() > 0x10000 {
+        return false;
+    }
+    bytes
+        .iter()
+        .enumerate()
+        .all(|(offset, &byte)| cpu.poke(addr + offset as u16, byte).is_ok())
+}
+
+fn parse_hex(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 16).ok()
+}
+
+fn parse_range(s: &str) -> Option<(u16, usize)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((u16::try_from(parse_hex(addr)?).ok()?, parse_hex(len)?))
+}
+
+fn parse_breakpoint(s: &str) -> Option<u16> {
+    let mut parts = s.split(',');
+    if parts.next()? != "0" {
+        return None;
+    }
+    u16::try_from(parse_hex(parts.next()?)?).ok()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::bus::Bus;
+    use crate::cpu::Mem;
+    use crate::emulator::Emulator;
+    use crate::frontend::{headless::Null, Command, InputSource};
+    use crate::joypad::Pads;
+    use crate::region::Region;
+    use crate::rom::{test::test_rom, Rom};
+    use std::net::SocketAddr;
+    use std::thread::{self, JoinHandle};
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn connect(addr: SocketAddr) -> Self {
+            let stream = TcpStream::connect(addr).unwrap();
+            Client {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                writer: stream,
+            }
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            write!(
+                self.writer,
+                "${}#{:02x}",
+                data,
+                packet_checksum(data.as_bytes())
+            )
+            .unwrap();
+            let mut ack = [0u8; 1];
+            self.reader.read_exact(&mut ack).unwrap();
+            assert_eq!(ack[0], b'+');
+            self.reply()
+        }
+
+        fn reply(&mut self) -> String {
+            let mut packet = Vec::new();
+            self.reader.read_until(b'$', &mut Vec::new()).unwrap();
+            self.reader.read_until(b'#', &mut packet).unwrap();
+            packet.pop();
+            let mut checksum = [0u8; 2];
+            self.reader.read_exact(&mut checksum).unwrap();
+            self.writer.write_all(b"+").unwrap();
+            String::from_utf8(packet).unwrap()
+        }
+    }
+
+    fn start_session<F>(program: Vec<u8>, script: F) -> CPU
+    where
+        F: FnOnce(&mut Client) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || script(&mut Client::connect(addr)));
+
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.load(program);
+        cpu.program_counter = 0x0600;
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut stub = GdbStub::new(stream).unwrap();
+        // what the emulator does around each instruction
+        while stub.poll(&mut cpu).unwrap() {
+            if stub.is_running() {
+                cpu.poll_interrupts();
+                if cpu.step() {
+                    stub.check_breakpoint(&cpu).unwrap();
+                } else {
+                    stub.stop(SIGTRAP).unwrap();
+                }
+            }
+        }
+        client.join().unwrap();
+        cpu
+    }
+
+    // LDA #$05; INX; INX; STA $10; BRK
+    const PROGRAM: [u8; 7] = [0xa9, 0x05, 0xe8, 0xe8, 0x85, 0x10, 0x00];
+
+    #[test]
+    fn test_registers_and_memory() {
+        let mut cpu = start_session(PROGRAM.to_vec(), |client| {
+            assert_eq!(client.request("?"), "S05");
+            assert_eq!(client.request("g"), "00000024fd0006");
+            assert_eq!(client.request("m0600,3"), "a905e8");
+            assert_eq!(client.request("m0,ffffffff").len(), PACKET_SIZE);
+            assert_eq!(client.request("qSupported"), "PacketSize=1000");
+            assert_eq!(client.request("M0010,2:beef"), "OK");
+            assert_eq!(client.request("m0010,2"), "beef");
+            assert_eq!(client.request("M0,0:"), "OK");
+            assert_eq!(client.request("M8000,1:ea"), "OK");
+            assert_eq!(client.request("m8000,1"), "ea");
+            assert_eq!(client.request("M4016,1:00"), "E01");
+            assert_eq!(client.request("Mffff,2:0000"), "E01");
+            assert_eq!(client.request("P1=42"), "OK");
+            assert_eq!(client.request("p1"), "42");
+            assert_eq!(client.request("p5"), "0006");
+            assert_eq!(client.request("k"), "OK");
+        });
+        assert_eq!(cpu.register_x, 0x42);
+        assert_eq!(cpu.mem_read(0x10), 0xbe);
+    }
+
+    #[test]
+    fn test_breakpoint_and_step() {
+        let cpu = start_session(PROGRAM.to_vec(), |client| {
+            assert_eq!(client.request("Z0,604,1"), "OK");
+            assert_eq!(client.request("c"), "S05");
+            assert_eq!(client.request("p5"), "0406");
+            assert_eq!(client.request("p1"), "02");
+            assert_eq!(client.request("z0,604,1"), "OK");
+            assert_eq!(client.request("s"), "S05");
+            assert_eq!(client.request("p5"), "0606");
+            assert_eq!(client.request("D"), "OK");
+        });
+        assert_eq!(cpu.register_a, 0x05);
+        assert_eq!(cpu.program_counter, 0x0606);
+    }
+
+    #[test]
+    fn test_interrupt_while_running() {
+        // JMP $0600
+        start_session(vec![0x4c, 0x00, 0x06], |client| {
+            write!(client.writer, "$c#63").unwrap();
+            let mut ack = [0u8; 1];
+            client.reader.read_exact(&mut ack).unwrap();
+            client.writer.write_all(&[INTERRUPT]).unwrap();
+            assert_eq!(client.reply(), "S02");
+            assert_eq!(client.request("p5"), "0006");
+            assert_eq!(client.request("k"), "OK");
+        });
+    }
+
+    /// quits once the debugger's script is done
+    struct UntilFinished(JoinHandle<()>);
+
+    impl InputSource for UntilFinished {
+        fn poll(&mut self, _pads: &mut Pads) -> Vec<Command> {
+            if self.0.is_finished() {
+                vec![Command::Quit]
+            } else {
+                vec![]
+            }
+        }
+    }
+
+    #[test]
+    fn test_attaching_to_the_running_emulator() {
+        let image = assemble("  .org $8000\nreset:\n  inx\n  jmp reset\n")
+            .and_then(|program| program.to_ines())
+            .unwrap();
+        let mut emulator = Emulator::new(Rom::new(&image).unwrap(), Region::Ntsc);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let script = thread::spawn(move || {
+            let mut client = Client::connect(addr);
+            assert_eq!(client.request("?"), "S05");
+            assert_eq!(client.request("p5"), "0080");
+            assert_eq!(client.request("Z0,8001,1"), "OK");
+            assert_eq!(client.request("c"), "S05");
+            assert_eq!(client.request("p5"), "0180");
+            assert_eq!(client.request("z0,8001,1"), "OK");
+            assert_eq!(client.request("D"), "OK");
+            drop(client);
+
+            // the game carries on, and halts again for the next debugger
+            let mut client = Client::connect(addr);
+            assert_eq!(client.request("?"), "S05");
+            write!(client.writer, "$c#63").unwrap();
+            let mut ack = [0u8; 1];
+            client.reader.read_exact(&mut ack).unwrap();
+            client.writer.write_all(&[INTERRUPT]).unwrap();
+            assert_eq!(client.reply(), "S02");
+            assert_eq!(client.request("k"), "OK");
+        });
+        emulator.debugger = Some(Debugger::accept(listener).unwrap());
+        let mut input = UntilFinished(script);
+        emulator.run(&mut Null, &mut Null, &mut input).unwrap();
+        input.0.join().unwrap();
+        assert!(!emulator.debugger.unwrap().is_attached());
+    }
+
+    #[test]
+    fn test_kill_ends_emulation() {
+        let image = assemble("  .org $8000\nreset:\n  jmp reset\n")
+            .and_then(|program| program.to_ines())
+            .unwrap();
+        let mut emulator = Emulator::new(Rom::new(&image).unwrap(), Region::Ntsc);
+        emulator.frame_limit = Some(10_000);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let script = thread::spawn(move || {
+            let mut client = Client::connect(addr);
+            assert_eq!(client.request("k"), "OK");
+        });
+        emulator.debugger = Some(Debugger::accept(listener).unwrap());
+        // the input never quits, so only the kill can stop it early
+        emulator.run(&mut Null, &mut Null, &mut Null).unwrap();
+        script.join().unwrap();
+        assert_eq!(emulator.frame_count(), 0);
+        assert!(emulator.debugger.unwrap().is_killed());
+    }
+
+    #[test]
+    fn test_breakpoint_on_the_first_instruction_of_a_frame() {
+        // every instruction has its own address until after the first vblank
+        let source = format!("  .org $8000\nreset:\n{}  brk\n", "  nop\n".repeat(16_000));
+        let image = assemble(&source)
+            .and_then(|program| program.to_ines())
+            .unwrap();
+        let mut emulator = Emulator::new(Rom::new(&image).unwrap(), Region::Ntsc);
+        emulator.run_frame().unwrap();
+        let [lo, hi] = emulator.cpu.program_counter.to_le_bytes();
+        assert!(emulator.cpu.program_counter < 0x8000 + 16_000);
+
+        let mut emulator = Emulator::new(Rom::new(&image).unwrap(), Region::Ntsc);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let script = thread::spawn(move || {
+            let mut client = Client::connect(addr);
+            let pc = format!("{lo:02x}{hi:02x}");
+            assert_eq!(client.request(&format!("Z0,{hi:02x}{lo:02x},1")), "OK");
+            assert_eq!(client.request("c"), "S05");
+            assert_eq!(client.request("p5"), pc);
+            assert_eq!(client.request("D"), "OK");
+        });
+        emulator.debugger = Some(Debugger::accept(listener).unwrap());
+        let mut input = UntilFinished(script);
+        emulator.run(&mut Null, &mut Null, &mut input).unwrap();
+        input.0.join().unwrap();
+    }
+}
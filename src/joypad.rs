@@ -0,0 +1,144 @@
+use bitflags::bitflags;
+
+bitflags! {
+    // Right Left Down Up Start Select B A
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct JoypadButton: u8 {
+        const RIGHT    = 0b1000_0000;
+        const LEFT     = 0b0100_0000;
+        const DOWN     = 0b0010_0000;
+        const UP       = 0b0001_0000;
+        const START    = 0b0000_1000;
+        const SELECT   = 0b0000_0100;
+        const BUTTON_B = 0b0000_0010;
+        const BUTTON_A = 0b0000_0001;
+    }
+}
+
+pub const PLAYERS: usize = 4;
+
+/// buttons held by each player; 3 and 4 need a Four Score
+pub type Pads = [JoypadButton; PLAYERS];
+
+/// Four Score signatures, in read order: $4016 gives 0,0,0,1,0,0,0,0 and $4017
+/// 0,0,1,0,0,0,0,0 after both controllers' 16 buttons
+pub const FOUR_SCORE_SIGNATURES: [u8; 2] = [0b0000_1000, 0b0000_0100];
+
+/**
+ * Standard controller behind $4016/$4017. While strobe is high it keeps reporting A;
+ * once it drops, each read shifts out the next button, then 1s.
+ *
+ * With a Four Score attached the port carries a second controller (player 3 or 4),
+ * whose buttons follow the first's, then the adapter's 8 bit signature.
+ */
+pub struct Joypad {
+    strobe: bool,
+    button_index: u8,
+    button_status: JoypadButton,
+    four_score: Option<(JoypadButton, u8)>,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad {
+            strobe: false,
+            button_index: 0,
+            button_status: JoypadButton::empty(),
+            four_score: None,
+        }
+    }
+
+    pub fn attach_four_score(&mut self, signature: u8) {
+        self.four_score = Some((JoypadButton::empty(), signature));
+    }
+
+    pub fn detach_four_score(&mut self) {
+        self.four_score = None;
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 == 1;
+        if self.strobe {
+            self.button_index = 0;
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        let response = self.peek();
+        if !self.strobe && self.button_index < self.report().1 {
+            self.button_index += 1;
+        }
+        response
+    }
+
+    /**
+     * The bits shifted out in turn, and how many there are.
+     */
+    fn report(&self) -> (u32, u8) {
+        match self.four_score {
+            Some((second, signature)) => (
+                self.button_status.bits() as u32
+                    | (second.bits() as u32) << 8
+                    | (signature as u32) << 16,
+                24,
+            ),
+            None => (self.button_status.bits() as u32, 8),
+        }
+    }
+
+    /**
+     * The bit `read` would return, without moving on to the next button.
+     */
+    pub fn peek(&self) -> u8 {
+        let (bits, len) = self.report();
+        if self.button_index >= len {
+            return 1;
+        }
+        ((bits >> self.button_index) & 1) as u8
+    }
+
+    pub fn set_buttons(&mut self, buttons: JoypadButton) {
+        self.button_status = buttons;
+    }
+
+    /**
+     * Buttons of the controller plugged into the Four Score behind this one. Ignored
+     * without a Four Score.
+     */
+    pub fn set_second_buttons(&mut self, buttons: JoypadButton) {
+        if let Some((second, _)) = &mut self.four_score {
+            *second = buttons;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shift_out_buttons() {
+        let mut joypad = Joypad::new();
+        joypad.set_buttons(JoypadButton::BUTTON_A | JoypadButton::START | JoypadButton::RIGHT);
+        joypad.write(1);
+        assert_eq!(joypad.read(), 1);
+        assert_eq!(joypad.read(), 1);
+        joypad.write(0);
+        let reads: Vec<u8> = (0..10).map(|_| joypad.read()).collect();
+        assert_eq!(reads, vec![1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_four_score() {
+        let mut joypad = Joypad::new();
+        joypad.attach_four_score(FOUR_SCORE_SIGNATURES[0]);
+        joypad.set_buttons(JoypadButton::BUTTON_A);
+        joypad.set_second_buttons(JoypadButton::BUTTON_B);
+        joypad.write(1);
+        joypad.write(0);
+        let reads: Vec<u8> = (0..25).map(|_| joypad.read()).collect();
+        assert_eq!(reads[..8], [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(reads[8..16], [0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(reads[16..], [0, 0, 0, 1, 0, 0, 0, 0, 1]);
+    }
+}
@@ -1,132 +1,124 @@
-use crate::{cpu::{AddressingMode, Mem, CPU}, opcodes::get_opcode_details};
-
-pub fn log(cpu: &mut CPU) -> String {
-    let opcode = cpu.mem_read(cpu.program_counter);
-
-    let opcode_details = get_opcode_details(&opcode).unwrap();
-    let mut log: Vec<u8> = Vec::new();
-    log.push(opcode);
+use crate::{
+    cpu::{AddressingMode, Peek, CPU},
+    disasm::{decode_at, Operand, Symbols},
+};
+
+/**
+ * nestest.log style trace line. Operands are shown by label where `symbols` knows one.
+ * Memory is read with `peek`, so tracing never changes what the program sees.
+ */
+pub fn log(cpu: &CPU, symbols: &dyn Symbols) -> String {
+    let instruction = decode_at(cpu, cpu.program_counter);
+    let Some(opcode_details) = instruction.opcode else {
+        unreachable!("opcode {:02x} is missing from OP_CODES_MAP", instruction.bytes[0]);
+    };
 
     let (mem_addr, value) = match opcode_details.mode {
         AddressingMode::Immediate | AddressingMode::Implied => (0, 0),
         _ => {
-            let (addr, _) = cpu.get_absolute_address(&opcode_details.mode, cpu.program_counter + 1);
-            (addr, cpu.mem_read(addr))
+            let addr = cpu.peek_absolute_address(&opcode_details.mode, cpu.program_counter + 1);
+            (addr, cpu.peek(addr))
         }
     };
 
-    let tmp = match opcode_details.additional_bytes {
-        0 => match opcode_details.code {
-            0x0a | 0x4a | 0x2a | 0x6a => format!("A "),
-            _ => String::from(""),
-        },
-        1 => {
-            let address: u8 = cpu.mem_read(cpu.program_counter + 1);
-            log.push(address);
-
-            match opcode_details.mode {
-                AddressingMode::Immediate => format!("#${:02x}", address),
-                AddressingMode::ZeroPage => format!("${:02x} = {:02x}", mem_addr, value),
-                AddressingMode::ZeroPage_X => format!(
-                    "${:02x},X @ {:02x} = {:02x}",
-                    address, mem_addr, value
-                ),
-                AddressingMode::ZeroPage_Y => format!(
-                    "${:02x},Y @ {:02x} = {:02x}",
-                    address, mem_addr, value
-                ),
-                AddressingMode::Indirect_X => format!(
-                    "(${:02x},X) @ {:02x} = {:04x} = {:02x}",
-                    address,
-                    (address.wrapping_add(cpu.register_x)),
-                    mem_addr,
-                    value
-                ),
-                AddressingMode::Indirect_Y => format!(
-                    "(${:02x}),Y = {:04x} @ {:04x} = {:02x}",
-                    address,
-                    (mem_addr.wrapping_sub(cpu.register_y as u16)),
-                    mem_addr,
-                    value
-                ),
-                AddressingMode::Implied => {
-                    // assuming local jumps: BNE, BVS, etc....
-                    let address: usize =
-                        (cpu.program_counter as usize + 2).wrapping_add((address as i8) as usize);
-                    format!("${:04x}", address)
-                }
-
-                _ => panic!(
-                    "unexpected addressing mode {:?} has opcode_details-additional_bytes 2. code {:02x}",
-                    opcode_details.mode, opcode_details.code
-                ),
-            }
+    let operand = instruction.operand.format(symbols);
+    let tmp = match instruction.operand {
+        Operand::ZeroPage(_) | Operand::Absolute(_)
+            if opcode_details.mode != AddressingMode::Implied =>
+        {
+            format!("{} = {:02X}", operand, value)
         }
-        2 => {
-            let address_lo = cpu.mem_read(cpu.program_counter + 1);
-            let address_hi = cpu.mem_read(cpu.program_counter + 2);
-            log.push(address_lo);
-            log.push(address_hi);
-
-            let address = cpu.mem_read_u16(cpu.program_counter + 1);
-
-            match opcode_details.mode {
-                AddressingMode::Implied => {
-                    if opcode_details.code == 0x6c {
-                        //jmp indirect
-                        let jmp_addr = if address & 0x00FF == 0x00FF {
-                            let lo = cpu.mem_read(address);
-                            let hi = cpu.mem_read(address & 0xFF00);
-                            (hi as u16) << 8 | (lo as u16)
-                        } else {
-                            cpu.mem_read_u16(address)
-                        };
-
-                        // let jmp_addr = cpu.mem_read_u16(address);
-                        format!("(${:04x}) = {:04x}", address, jmp_addr)
-                    } else {
-                        format!("${:04x}", address)
-                    }
-                }
-                AddressingMode::Absolute => format!("${:04x} = {:02x}", mem_addr, value),
-                AddressingMode::Absolute_X => format!(
-                    "${:04x},X @ {:04x} = {:02x}",
-                    address, mem_addr, value
-                ),
-                AddressingMode::Absolute_Y => format!(
-                    "${:04x},Y @ {:04x} = {:02x}",
-                    address, mem_addr, value
-                ),
-                _ => panic!(
-                    "unexpected addressing mode {:?} has opcode_details-additional_bytes 2. code {:02x}",
-                    opcode_details.mode, opcode_details.code
-                ),
-            }
+        Operand::ZeroPageX(_) | Operand::ZeroPageY(_) => {
+            format!("{} @ {:02X} = {:02X}", operand, mem_addr, value)
         }
-        _ => String::from(""),
+        Operand::AbsoluteX(_) | Operand::AbsoluteY(_) => {
+            format!("{} @ {:04X} = {:02X}", operand, mem_addr, value)
+        }
+        Operand::IndirectX(address) => format!(
+            "{} @ {:02X} = {:04X} = {:02X}",
+            operand,
+            (address.wrapping_add(cpu.register_x)),
+            mem_addr,
+            value
+        ),
+        Operand::IndirectY(_) => format!(
+            "{} = {:04X} @ {:04X} = {:02X}",
+            operand,
+            (mem_addr.wrapping_sub(cpu.register_y as u16)),
+            mem_addr,
+            value
+        ),
+        Operand::Indirect(address) => {
+            //jmp indirect
+            let jmp_addr = if address & 0x00FF == 0x00FF {
+                let lo = cpu.peek(address);
+                let hi = cpu.peek(address & 0xFF00);
+                (hi as u16) << 8 | (lo as u16)
+            } else {
+                cpu.peek_u16(address)
+            };
+            format!("{} = {:04X}", operand, jmp_addr)
+        }
+        _ => operand,
     };
 
-    let hex_str = log
-        .iter()
-        .map(|z| format!("{:02x}", z))
-        .collect::<Vec<String>>()
-        .join(" ");
-    let asm_str = format!("{:04x}  {:8} {: >4} {}", cpu.program_counter, hex_str, opcode_details.mnemonic, tmp)
-        .trim()
-        .to_string();
+    let asm_str = format!(
+        "{:04X}  {:8} {: >4} {}",
+        cpu.program_counter,
+        instruction.hex_bytes(),
+        opcode_details.mnemonic,
+        tmp
+    )
+    .trim()
+    .to_string();
 
     format!(
-        "{:47} A:{:02x} X:{:02x} Y:{:02x} P:{:02x} SP:{:02x}",
+        "{:47} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
         asm_str, cpu.register_a, cpu.register_x, cpu.register_y, cpu.status, cpu.stack_ptr,
     )
-    .to_ascii_uppercase()
+}
+
+/**
+ * Mesen-style trace line: plain disassembly, flags as letters (upper case when set),
+ * then the PPU scanline/dot and the CPU cycle count before the instruction runs.
+ */
+pub fn log_mesen(cpu: &CPU, symbols: &dyn Symbols) -> String {
+    let instruction = decode_at(cpu, cpu.program_counter);
+    let flags: String = "NVUBDIZC"
+        .chars()
+        .enumerate()
+        .map(|(i, flag)| {
+            if cpu.status.bits() & (0x80 >> i) != 0 {
+                flag
+            } else {
+                flag.to_ascii_lowercase()
+            }
+        })
+        .collect();
+    let (scanline, dot) = cpu.bus.ppu_position();
+
+    format!(
+        "{:04X}  {:<30} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{} V:{:<3} H:{:<3} Cy:{}",
+        cpu.program_counter,
+        instruction.format(symbols),
+        cpu.register_a,
+        cpu.register_x,
+        cpu.register_y,
+        cpu.stack_ptr,
+        flags,
+        scanline,
+        dot,
+        cpu.bus.cycles()
+    )
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::bus::Bus;
+    use crate::cpu::Mem;
     use crate::rom::test::test_rom;
+    use std::collections::HashMap;
 
     #[test]
     fn test_format_trace() {
@@ -144,7 +136,7 @@ mod test {
         cpu.register_y = 3;
         let mut result: Vec<String> = vec![];
         cpu.run_with_callback(|cpu| {
-            result.push(log(cpu));
+            result.push(log(cpu, &()));
         });
         assert_eq!(
             "0064  A2 01     LDX #$01                        A:01 X:02 Y:03 P:24 SP:FD",
@@ -179,11 +171,51 @@ mod test {
         cpu.register_y = 0;
         let mut result: Vec<String> = vec![];
         cpu.run_with_callback(|cpu| {
-            result.push(log(cpu));
+            result.push(log(cpu, &()));
         });
         assert_eq!(
             "0064  11 33     ORA ($33),Y = 0400 @ 0400 = AA  A:00 X:00 Y:00 P:24 SP:FD",
             result[0]
         );
     }
+
+    #[test]
+    fn test_format_with_symbols() {
+        let mut bus = Bus::new(test_rom());
+        // JSR $0400
+        bus.mem_write(100, 0x20);
+        bus.mem_write(101, 0x00);
+        bus.mem_write(102, 0x04);
+
+        let mut symbols = HashMap::new();
+        symbols.insert(0x0400, "UpdatePlayer".to_string());
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x64;
+        assert_eq!(
+            "0064  20 00 04  JSR UpdatePlayer                A:00 X:00 Y:00 P:24 SP:FD",
+            log(&cpu, &symbols)
+        );
+    }
+
+    #[test]
+    fn test_logging_leaves_registers_alone() {
+        let mut bus = Bus::new(test_rom());
+        // LDA $2002
+        bus.mem_write(100, 0xad);
+        bus.mem_write(101, 0x02);
+        bus.mem_write(102, 0x20);
+        while !bus.take_frame() {
+            bus.tick(1);
+        }
+
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x64;
+        assert_eq!(
+            "0064  AD 02 20  LDA $2002 = 80                  A:00 X:00 Y:00 P:24 SP:FD",
+            log(&cpu, &())
+        );
+        // vblank is still set for the program to see
+        assert_eq!(cpu.mem_read(0x2002), 0x80);
+        assert_eq!(cpu.peek(0x2002), 0x00);
+    }
 }
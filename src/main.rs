@@ -1,15 +1,36 @@
+use assembler::assemble_file;
 use bus::Bus;
-use logger::log;
-use rom::insert_new_cartridge;
+use cdl::{CodeDataLogger, SharedLogger};
+use disasm::listing;
+use rom::load_rom;
 use rom::Rom;
-use cpu::Mem;
+use rom::PRG_ROM_BANK_SIZE;
+use symbols::SymbolTable;
+use trace::{TraceFormat, Tracer};
+use trace_diff::{diff, parse_trace, DiffResult};
 use cpu::CPU;
-use rand::Rng;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::pixels::PixelFormatEnum;
-use sdl2::EventPump;
+use emulator::Emulator;
+use frame::{HEIGHT, WIDTH};
+use frontend::headless::Null;
+use frontend::sdl::{SdlAudio, SdlInput, SdlVideo, SdlViewers};
+use gdb::Debugger;
+use movie::{load_fm2, MoviePlayback};
+use options::Options;
+use region::Region;
+use config::Config;
+use palette::{NtscParams, Rgb};
+use screenshot::Screenshots;
+use recorder::Recordings;
+use memview::MemoryConsole;
+use video::{
+    ntsc::{NtscFilter, NtscPreset},
+    scale::Scaler,
+    VideoPipeline,
+};
+use std::path::{Path, PathBuf};
+use pacer::{FramePacer, SyncMode};
+use sdl2::render::Canvas;
+use sdl2::video::Window;
 
 mod bus;
 mod cpu;
@@ -17,114 +38,556 @@ mod ppu;
 mod rom;
 mod opcodes;
 mod logger;
+mod gdb;
+mod disasm;
+mod assembler;
+mod cdl;
+mod symbols;
+mod trace;
+mod trace_diff;
+mod joypad;
+mod frame;
+mod palette;
+mod frontend;
+mod emulator;
+mod snake;
+mod region;
+mod pacer;
+mod speed;
+mod options;
+mod movie;
+mod config;
+mod screenshot;
+mod overscan;
+mod recorder;
+mod video;
+mod memview;
+mod ram_search;
+mod cheats;
+
+const GDB_ADDRESS: &str = "127.0.0.1:9001";
+
+/// a tool run as `nes-emulator <name> <args...>` instead of playing a ROM
+type Subcommand = fn(&[String]) -> Result<(), String>;
 
 fn main() {
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window("Snake game", (32.0 * 10.0) as u32, (32.0 * 10.0) as u32)
-        .position_centered()
-        .build()
-        .unwrap();
+    let args: Vec<String> = std::env::args().collect();
+    let subcommand: Option<Subcommand> = match args.get(1).map(String::as_str) {
+        Some("disasm") => Some(disassemble_rom),
+        Some("asm") => Some(assemble_program),
+        Some("cdl") => Some(log_code_data),
+        Some("trace") => Some(trace_rom),
+        Some("trace-diff") => Some(diff_traces),
+        Some("snake") => Some(play_snake),
+        Some("palette") => Some(save_palette),
+        _ => None,
+    };
+    if let Some(subcommand) = subcommand {
+        if let Err(e) = subcommand(&args[2..]) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
-    let mut event_pump = sdl_context.event_pump().unwrap();
-    canvas.set_scale(10.0, 10.0).unwrap();
+    if let Err(e) = play(&args[1..]) {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
 
-    let creator = canvas.texture_creator();
-    let mut texture = creator
-        .create_texture_target(PixelFormatEnum::RGB24, 32, 32)
-        .unwrap();
+struct Sdl {
+    context: sdl2::Sdl,
+    canvas: Canvas<Window>,
+}
 
-    let game_code = insert_new_cartridge("nestest").unwrap();
-    let rom = Rom::new(&game_code).unwrap();
-    let bus = Bus::new(rom);
-    let mut cpu = CPU::new(bus);
-    cpu.reset();
-    cpu.program_counter = 0xC000;
+fn open_window(title: &str, width: u32, height: u32, fullscreen: bool) -> Result<Sdl, String> {
+    let context = sdl2::init()?;
+    let mut window = context.video()?.window(title, width, height);
+    window.position_centered();
+    if fullscreen {
+        window.fullscreen_desktop();
+    }
+    let canvas = window
+        .build()
+        .map_err(|e| e.to_string())?
+        .into_canvas()
+        .build()
+        .map_err(|e| e.to_string())?;
+    Ok(Sdl { context, canvas })
+}
 
-    let mut screen_state = [0 as u8; 32 * 3 * 32];
-    let mut rng = rand::thread_rng();
+/**
+ * Plays a ROM in a window, or headless. See `options::USAGE` for the flags.
+ */
+fn play(args: &[String]) -> Result<(), String> {
+    let options = Options::parse(args)?;
+    let config_path = options
+        .config
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(config::default_path);
+    let mut config = match &config_path {
+        Some(path) => Config::load_or_create(path)?,
+        None => Config::default(),
+    };
+    if options.snake {
+        return play_snake_with(&config);
+    }
+    let path = options.rom.as_deref().ok_or(options::USAGE)?;
+    let rom = Rom::new(&load_rom(path)?)?;
+    let symbols = SymbolTable::load_for_rom(path, rom.prg_rom.len())?;
 
-    cpu.run_with_callback(move |cpu| {
-        println!("{}", log(cpu));
-        handle_user_input(cpu, &mut event_pump);
-        cpu.mem_write(0xfe, rng.gen_range(0..=16));
+    let palette = palette::load(&config.video.palette, &config.ntsc)?;
 
-        if read_screen_state(cpu, &mut screen_state) {
-            texture.update(None, &screen_state, 32 * 3).unwrap();
-            canvas.copy(&texture, None, None).unwrap();
-            canvas.present();
+    let code_data = options.cdl.then(|| code_data_logger(path, &rom));
+    let mut emulator = Emulator::new(rom, options.region);
+    if let Some((_, logger)) = &code_data {
+        emulator.cpu.bus.attach_cdl(logger.clone());
+    }
+    emulator.set_sample_rate(config.audio.sample_rate);
+    emulator.cpu.bus.set_four_score(config.input.four_score);
+    emulator.turbo_rate = config.input.turbo_rate;
+    let cheat_file = cheats::path_for_rom(path);
+    if cheat_file.exists() {
+        *emulator.cpu.bus.cheats_mut() = cheats::load_cheats(&cheat_file)?;
+        println!("{} cheats from {}", emulator.cpu.bus.cheats().len(), cheat_file.display());
+    }
+    if let Some(addr) = &options.start_pc {
+        emulator.cpu.program_counter = symbols.resolve(addr)?;
+    }
+    let mut tracer = match &options.trace {
+        Some(trace) => Tracer::to_file(trace, TraceFormat::Nestest)?,
+        None => {
+            // F8 toggles tracing to stdout
+            let mut tracer = Tracer::new(Box::new(std::io::stdout()), TraceFormat::Nestest);
+            tracer.set_enabled(false);
+            tracer
         }
+    };
+    tracer.set_symbols(Box::new(symbols));
+    emulator.tracer = Some(tracer);
+    emulator.frame_limit = options.frames;
+    let movie = options.movie.as_deref().map(load_fm2).transpose()?;
+    if let Some(file) = &options.record {
+        emulator.start_recording(Path::new(file), &palette)?;
+    }
 
-        std::thread::sleep(std::time::Duration::new(0, 50));
+    if options.debug {
+        let debugger = Debugger::listen(GDB_ADDRESS)
+            .map_err(|e| format!("Cannot listen for GDB on {GDB_ADDRESS}: {e}"))?;
+        emulator.debugger = Some(debugger);
+    }
+
+    if options.headless {
+        if emulator.frame_limit.is_none() {
+            emulator.frame_limit = movie.as_ref().map(|movie| movie.len() as u64);
+        }
+        emulator.movie = movie.map(MoviePlayback::new);
+        emulator.run(&mut Null, &mut Null, &mut Null)?;
+    } else {
+        emulator.movie = movie.map(MoviePlayback::new);
+        play_in_window(&mut emulator, &options, &mut config, config_path, &palette)?;
+    }
+
+    if let Some(file) = &options.screenshot {
+        screenshot::save_png(Path::new(file), &emulator.frame, &palette, 1)?;
+    }
+    if let Some(file) = &options.dump_frame {
+        screenshot::save_indices(Path::new(file), &emulator.frame)?;
+    }
+    if let Some((cdl_path, logger)) = &code_data {
+        save_code_data(cdl_path, logger)?;
+    }
+    Ok(())
+}
+
+fn play_in_window(
+    emulator: &mut Emulator,
+    options: &Options,
+    config: &mut Config,
+    config_path: Option<PathBuf>,
+    palette: &[Rgb],
+) -> Result<(), String> {
+    let path = options.rom.as_deref().unwrap_or_default();
+    if let Some(config_path) = &config_path {
+        let rom_path = std::fs::canonicalize(path).map_or(path.to_string(), |full| {
+            full.display().to_string()
+        });
+        config.add_recent_rom(&rom_path);
+        config.save(config_path)?;
+    }
+    let scale = options.scale.unwrap_or(config.video.scale);
+    let screenshot_dir = match config.paths.screenshots.as_str() {
+        "" => Path::new(path).parent().unwrap_or(Path::new("")).to_path_buf(),
+        dir => PathBuf::from(dir),
+    };
+    let name = Path::new(path)
+        .file_stem()
+        .map_or("nes".to_string(), |stem| stem.to_string_lossy().to_string());
+    emulator.overscan = config.overscan.for_game(&name, options.region);
+    let recording_dir = match config.paths.recordings.as_str() {
+        "" => screenshot_dir.clone(),
+        dir => PathBuf::from(dir),
+    };
+    emulator.recordings = Some(Recordings {
+        dir: recording_dir,
+        name: name.clone(),
+        format: config.video.record_format.clone(),
+        palette: palette.to_vec(),
     });
+    let viewer_files = (screenshot_dir.clone(), name.clone());
+    emulator.screenshots = Some(Screenshots {
+        dir: screenshot_dir,
+        name,
+        scale: scale as usize,
+        indices: config.video.screenshot_indices,
+        palette: palette.to_vec(),
+    });
+
+    let (width, height) = emulator.overscan.visible(WIDTH, HEIGHT);
+    let width = match config.video.aspect_8_7 {
+        true => width as u32 * 8 / 7,
+        false => width as u32,
+    };
+    let sdl = open_window(
+        &format!("NES - {path}"),
+        width * scale,
+        height as u32 * scale,
+        options.fullscreen || config.video.fullscreen,
+    )?;
+    let creator = sdl.canvas.texture_creator();
+    let mut pipeline = VideoPipeline::new(palette.to_vec());
+    pipeline.ntsc = NtscPreset::parse(&config.video.filter)?
+        .map(|preset| NtscFilter::new(preset, &config.ntsc, palette));
+    pipeline.scaler = Scaler::parse(&config.video.scaler, scale as usize)?;
+    pipeline.scanlines = config.video.scanlines;
+    pipeline.aspect_8_7 = config.video.aspect_8_7;
+    let mut video = SdlVideo::new(sdl.canvas, &creator, pipeline);
+    let mut audio = SdlAudio::new(&sdl.context.audio()?, config.audio.sample_rate)?;
+    let (viewer_dir, viewer_name) = viewer_files;
+    emulator.views = Some(Box::new(SdlViewers::new(
+        sdl.context.video()?,
+        palette.to_vec(),
+        viewer_dir,
+        viewer_name,
+    )));
+    emulator.memory = Some(MemoryConsole::new(Some(cheats::path_for_rom(path))));
+    let mut input = SdlInput::new(
+        sdl.context.event_pump()?,
+        sdl.context.game_controller()?,
+        config,
+    )?;
+    if let Some(config_path) = config_path {
+        input.save_bindings_to(config_path);
+    }
+    let mut pacer = FramePacer::new(options.region.frame_rate(), SyncMode::Audio);
+    pacer.set_audio_latency(
+        (config.audio.sample_rate as u64 * config.audio.latency_ms as u64 / 1000) as usize,
+    );
+    emulator.pacer = Some(pacer);
+    emulator.run(&mut video, &mut audio, &mut input)
 }
 
-fn handle_user_input(cpu: &mut CPU, event_pump: &mut EventPump) {
-    for event in event_pump.poll_iter() {
-        match event {
-            Event::Quit { .. }
-            | Event::KeyDown {
-                keycode: Some(Keycode::Escape),
-                ..
-            } => std::process::exit(0),
-            Event::KeyDown {
-                keycode: Some(Keycode::W),
-                ..
-            } => {
-                cpu.mem_write(0xff, 0x77);
-            }
-            Event::KeyDown {
-                keycode: Some(Keycode::S),
-                ..
-            } => {
-                cpu.mem_write(0xff, 0x73);
+/**
+ * `snake` plays snake.asm in a 32x32 window, steered with the arrow keys.
+ */
+fn play_snake(_args: &[String]) -> Result<(), String> {
+    match config::default_path() {
+        Some(path) => play_snake_with(&Config::load_or_create(&path)?),
+        None => play_snake_with(&Config::default()),
+    }
+}
+
+fn play_snake_with(config: &Config) -> Result<(), String> {
+    let sdl = open_window("Snake game", 32 * 10, 32 * 10, false)?;
+    let creator = sdl.canvas.texture_creator();
+    let mut video = SdlVideo::new(
+        sdl.canvas,
+        &creator,
+        VideoPipeline::new(palette::load(&config.video.palette, &config.ntsc)?),
+    );
+    let mut input = SdlInput::new(
+        sdl.context.event_pump()?,
+        sdl.context.game_controller()?,
+        config,
+    )?;
+    snake::run(&mut video, &mut input)
+}
+
+/**
+ * `disasm <rom.nes> [bank|symbol]` prints a 16KiB PRG bank as an assembler listing,
+ * using labels from any .dbg/.nl files next to the ROM. Defaults to the last bank,
+ * which most boards keep fixed at $C000.
+ */
+fn disassemble_rom(args: &[String]) -> Result<(), String> {
+    let path = args
+        .first()
+        .ok_or("usage: nes-emulator disasm <rom.nes> [bank|symbol]")?;
+    let rom = Rom::new(&load_rom(path)?)?;
+    let mut symbols = SymbolTable::load_for_rom(path, rom.prg_rom.len())?;
+    let banks = rom.prg_rom.len() / PRG_ROM_BANK_SIZE;
+    if banks == 0 {
+        return Err(format!("{path} has no PRG ROM"));
+    }
+    let bank = match args.get(1) {
+        Some(bank) => match bank.parse::<usize>() {
+            Ok(bank) => bank,
+            Err(_) => symbols
+                .lookup(bank)
+                .and_then(|symbol| symbol.bank)
+                .ok_or(format!("{bank} is neither a bank nor a PRG ROM symbol"))?,
+        },
+        None => banks - 1,
+    };
+    if bank >= banks {
+        return Err(format!("{path} only has {banks} PRG bank(s)"));
+    }
+
+    let origin = if bank == banks - 1 { 0xC000 } else { 0x8000 };
+    if origin == 0x8000 {
+        symbols.map_prg(bank, banks - 1);
+    }
+    let prg = &rom.prg_rom[bank * PRG_ROM_BANK_SIZE..(bank + 1) * PRG_ROM_BANK_SIZE];
+    print!("{}", listing(prg, origin, &symbols));
+    Ok(())
+}
+
+/**
+ * `asm <source.asm> <output>` writes an iNES image when the output ends in .nes,
+ * or a raw binary for `CPU::load` otherwise.
+ */
+fn assemble_program(args: &[String]) -> Result<(), String> {
+    let (source, output) = match args {
+        [source, output] => (source, output),
+        _ => return Err("usage: nes-emulator asm <source.asm> <output>".to_string()),
+    };
+    let program = assemble_file(source)?;
+    let bytes = if output.ends_with(".nes") {
+        program.to_ines()?
+    } else {
+        program.to_binary()
+    };
+    std::fs::write(output, bytes).map_err(|e| format!("Cannot write {output}: {e}"))
+}
+
+/**
+ * `cdl <rom.nes> [frames]` runs a ROM from its reset vector without input, drawing
+ * every frame, and writes the code/data log next to it in the FCEUX format. An
+ * existing log is added to. `--cdl` logs while playing instead.
+ */
+fn log_code_data(args: &[String]) -> Result<(), String> {
+    let path = args
+        .first()
+        .ok_or("usage: nes-emulator cdl <rom.nes> [frames]")?;
+    let frames = match args.get(1) {
+        Some(count) => count
+            .parse::<u64>()
+            .map_err(|e| format!("Invalid frame count {count}: {e}"))?,
+        None => 600,
+    };
+    let rom = Rom::new(&load_rom(path)?)?;
+    let (cdl_path, logger) = code_data_logger(path, &rom);
+    let mut emulator = Emulator::new(rom, Region::Ntsc);
+    emulator.cpu.bus.attach_cdl(logger.clone());
+    for _ in 0..frames {
+        if !emulator.run_frame()? {
+            break;
+        }
+    }
+    save_code_data(&cdl_path, &logger)
+}
+
+/**
+ * The logger for `rom_path`, picking up where its .cdl file left off.
+ */
+fn code_data_logger(rom_path: &str, rom: &Rom) -> (String, SharedLogger) {
+    let cdl_path = format!("{}.cdl", rom_path.strip_suffix(".nes").unwrap_or(rom_path));
+    let logger = CodeDataLogger::resume(&cdl_path, rom.prg_rom.len(), rom.chr_rom.len());
+    (cdl_path, logger.shared())
+}
+
+fn save_code_data(cdl_path: &str, logger: &SharedLogger) -> Result<(), String> {
+    let logger = logger.borrow();
+    let (code, data, unlogged) = logger.prg_coverage();
+    println!("PRG: {code} bytes of code, {data} bytes of data, {unlogged} bytes not reached");
+    let (drawn, read, unlogged) = logger.chr_coverage();
+    println!("CHR: {drawn} bytes drawn, {read} bytes read, {unlogged} bytes not used");
+    logger.save(cdl_path)
+}
+
+/**
+ * `trace <rom.nes> <output> [options]` runs a ROM without a window and writes an
+ * instruction trace. Addresses may be given as `$C000`, `0xC000` or a symbol name.
+ *   --format nestest|mesen|binary
+ *   --start-pc <addr>      instead of the reset vector (nestest.nes uses $C000)
+ *   --trigger <addr>       start tracing when the CPU gets there
+ *   --range <from>-<to>    only trace instructions in this PC range
+ *   --bank <n>             only trace code running from this PRG bank
+ *   --instructions <n>     stop after this many instructions (default 1000000)
+ */
+fn trace_rom(args: &[String]) -> Result<(), String> {
+    let usage = "usage: nes-emulator trace <rom.nes> <output> [--format nestest|mesen|binary] \
+        [--start-pc addr] [--trigger addr] [--range from-to] [--bank n] [--instructions n]";
+    let (path, output, options) = match args {
+        [path, output, options @ ..] => (path, output, options),
+        _ => return Err(usage.to_string()),
+    };
+    let bytes = std::fs::read(path).map_err(|e| format!("Cannot read {path}: {e}"))?;
+    let rom = Rom::new(&bytes)?;
+    let symbols = SymbolTable::load_for_rom(path, rom.prg_rom.len())?;
+
+    let mut format = TraceFormat::Nestest;
+    let mut start_pc = None;
+    let mut trigger = None;
+    let mut range = None;
+    let mut bank = None;
+    let mut instructions = 1_000_000;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let value = options
+            .next()
+            .ok_or(format!("{option} needs a value\n{usage}"))?;
+        match option.as_str() {
+            "--format" => format = TraceFormat::parse(value)?,
+            "--start-pc" => start_pc = Some(symbols.resolve(value)?),
+            "--trigger" => trigger = Some(symbols.resolve(value)?),
+            "--range" => {
+                let (from, to) = value
+                    .split_once('-')
+                    .ok_or(format!("Invalid range {value}, expected from-to"))?;
+                range = Some(symbols.resolve(from)?..=symbols.resolve(to)?);
             }
-            Event::KeyDown {
-                keycode: Some(Keycode::A),
-                ..
-            } => {
-                cpu.mem_write(0xff, 0x61);
+            "--bank" => {
+                bank = Some(
+                    value
+                        .parse::<usize>()
+                        .map_err(|e| format!("Invalid bank {value}: {e}"))?,
+                )
             }
-            Event::KeyDown {
-                keycode: Some(Keycode::D),
-                ..
-            } => {
-                cpu.mem_write(0xff, 0x64);
+            "--instructions" => {
+                instructions = value
+                    .parse::<usize>()
+                    .map_err(|e| format!("Invalid instruction count {value}: {e}"))?
             }
-            _ => { /* do nothing */ }
+            _ => return Err(format!("Unknown option {option}\n{usage}")),
         }
     }
+
+    let mut tracer = Tracer::to_file(output, format)?;
+    tracer.set_symbols(Box::new(symbols));
+    if let Some(addr) = trigger {
+        tracer.trigger_at(addr);
+    }
+    if let Some(range) = range {
+        tracer.filter_pc(range);
+    }
+    if let Some(bank) = bank {
+        tracer.filter_bank(bank);
+    }
+
+    let mut cpu = CPU::new(Bus::new(rom));
+    cpu.reset();
+    if let Some(pc) = start_pc {
+        cpu.program_counter = pc;
+    }
+    for _ in 0..instructions {
+        cpu.poll_interrupts();
+        tracer.trace(&cpu)?;
+        if !cpu.step() {
+            break;
+        }
+    }
+    tracer.flush()
 }
 
-fn color(byte: u8) -> Color {
-    match byte {
-        0 => sdl2::pixels::Color::BLACK,
-        1 => sdl2::pixels::Color::WHITE,
-        2 | 9 => sdl2::pixels::Color::GREY,
-        3 | 10 => sdl2::pixels::Color::RED,
-        4 | 11 => sdl2::pixels::Color::GREEN,
-        5 | 12 => sdl2::pixels::Color::BLUE,
-        6 | 13 => sdl2::pixels::Color::MAGENTA,
-        7 | 14 => sdl2::pixels::Color::YELLOW,
-        _ => sdl2::pixels::Color::CYAN,
+/**
+ * `palette <file.pal> [--hue <degrees>] [--saturation <n>] [--contrast <n>]
+ * [--brightness <n>] [--gamma <n>]` saves a generated NTSC palette with its
+ * emphasis colours. Settings not given come from `[ntsc]` in the config file.
+ */
+fn save_palette(args: &[String]) -> Result<(), String> {
+    let usage = "usage: nes-emulator palette <file.pal> [--hue <degrees>] [--saturation <n>] \
+                 [--contrast <n>] [--brightness <n>] [--gamma <n>]";
+    let (path, flags) = args.split_first().ok_or(usage)?;
+    let mut params = match config::default_path().filter(|path| path.exists()) {
+        Some(path) => Config::load_or_create(&path)?.ntsc,
+        None => NtscParams::default(),
+    };
+    for pair in flags.chunks(2) {
+        let [flag, value] = pair else {
+            return Err(usage.to_string());
+        };
+        let value: f64 = value
+            .parse()
+            .map_err(|_| format!("Invalid value {value} for {flag}"))?;
+        match flag.as_str() {
+            "--hue" => params.hue = value,
+            "--saturation" => params.saturation = value,
+            "--contrast" => params.contrast = value,
+            "--brightness" => params.brightness = value,
+            "--gamma" if value > 0.0 => params.gamma = value,
+            _ => return Err(usage.to_string()),
+        }
     }
+    palette::save_pal(Path::new(path), &palette::generate_ntsc(&params))?;
+    println!("Saved {path}");
+    Ok(())
 }
 
-fn read_screen_state(cpu: &mut CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
-    let mut frame_idx = 0;
-    let mut update = false;
-    for i in 0x0200..0x600 {
-        let color_idx = cpu.mem_read(i as u16);
-        let (b1, b2, b3) = color(color_idx).rgb();
-        if frame[frame_idx] != b1 || frame[frame_idx + 1] != b2 || frame[frame_idx + 2] != b3 {
-            frame[frame_idx] = b1;
-            frame[frame_idx + 1] = b2;
-            frame[frame_idx + 2] = b3;
-            update = true;
+/**
+ * `trace-diff <expected> <actual> [context lines]` reports the first instruction
+ * where two traces disagree, with the lines leading up to it.
+ */
+fn diff_traces(args: &[String]) -> Result<(), String> {
+    let usage = "usage: nes-emulator trace-diff <expected> <actual> [context lines]";
+    let (expected_path, actual_path) = match args {
+        [expected, actual] | [expected, actual, _] => (expected, actual),
+        _ => return Err(usage.to_string()),
+    };
+    let context = match args.get(2) {
+        Some(lines) => lines
+            .parse::<usize>()
+            .map_err(|e| format!("Invalid context {lines}: {e}"))?,
+        None => 5,
+    };
+    let read = |path: &String| {
+        let bytes = std::fs::read(path).map_err(|e| format!("Cannot read {path}: {e}"))?;
+        parse_trace(&bytes).map_err(|e| format!("{path}: {e}"))
+    };
+    let expected = read(expected_path)?;
+    let actual = read(actual_path)?;
+
+    match diff(&expected, &actual, context) {
+        DiffResult::Match(count) => {
+            println!("Traces match for all {count} instructions");
+            Ok(())
+        }
+        DiffResult::Truncated(count) => {
+            println!(
+                "Traces match for {count} instructions, then {} ends",
+                if expected.len() < actual.len() {
+                    expected_path
+                } else {
+                    actual_path
+                }
+            );
+            Ok(())
+        }
+        DiffResult::Diverged(divergence) => {
+            for line in &divergence.context {
+                println!("  {line}");
+            }
+            println!("- {}", divergence.expected);
+            println!("+ {}", divergence.actual);
+            for difference in &divergence.differences {
+                println!("    {difference}");
+            }
+            Err(format!(
+                "Traces diverge at line {} of {expected_path} / line {} of {actual_path}",
+                divergence.expected_index + 1,
+                divergence.actual_index + 1
+            ))
         }
-        frame_idx += 3;
     }
-    update
 }
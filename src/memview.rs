@@ -0,0 +1,684 @@
+use std::{
+    io::{BufRead, Write},
+    ops::Range,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
+};
+
+use crate::{
+    bus::Bus,
+    cheats::{save_cheats, Cheat},
+    cpu::Peek,
+    ram_search::{parse_value, Filter, RamSearch},
+};
+
+/// bytes per row
+const ROW: usize = 16;
+const ROWS: usize = 16;
+const CHANGED: &str = "\x1b[1;33m";
+const FOUND: &str = "\x1b[7m";
+const RESET: &str = "\x1b[0m";
+const CLEAR_SCREEN: &str = "\x1b[H\x1b[2J";
+const HELP: &str = "cpu ppu oam palette prg chr | goto <addr> | set <addr> <bytes> | \
+                    find <bytes or \"text\"> | next | + -";
+const SEARCH_HELP: &str = "search [8|16] [signed] | filter <= != > < [value], +N, -N, changed> | \
+                           freeze <addr> [value] | unfreeze [addr] | export <addr>";
+const CHEAT_HELP: &str = "cheat add <code> [name] | cheat <n> | cheat remove <n> | cheat on|off | \
+                          cheat save";
+/// search results listed under the dump
+const RESULTS: usize = 8;
+
+/**
+ * The memories the viewer can show. None of them is read through `mem_read`, so
+ * looking never changes the console's state.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemorySpace {
+    /// the 64KiB the CPU addresses
+    Cpu,
+    /// the 16KiB the PPU addresses
+    Ppu,
+    Oam,
+    /// the 32 bytes of palette RAM, without mirroring
+    Palette,
+    PrgRom,
+    ChrRom,
+}
+
+const SPACE_NAMES: [(&str, MemorySpace); 6] = [
+    ("cpu", MemorySpace::Cpu),
+    ("ppu", MemorySpace::Ppu),
+    ("oam", MemorySpace::Oam),
+    ("palette", MemorySpace::Palette),
+    ("prg", MemorySpace::PrgRom),
+    ("chr", MemorySpace::ChrRom),
+];
+
+impl MemorySpace {
+    pub fn parse(name: &str) -> Option<Self> {
+        SPACE_NAMES
+            .iter()
+            .find(|(space, _)| space.eq_ignore_ascii_case(name))
+            .map(|&(_, space)| space)
+    }
+
+    pub fn name(&self) -> &'static str {
+        SPACE_NAMES
+            .iter()
+            .find(|(_, space)| space == self)
+            .map_or("", |(name, _)| name)
+    }
+
+    pub fn len(&self, bus: &Bus) -> usize {
+        match self {
+            MemorySpace::Cpu => 0x10000,
+            MemorySpace::Ppu => 0x4000,
+            MemorySpace::Oam => 256,
+            MemorySpace::Palette => 32,
+            MemorySpace::PrgRom => bus.prg_rom().len(),
+            MemorySpace::ChrRom => bus.ppu().chr_rom.len(),
+        }
+    }
+
+    /**
+     * `addr` must be below `len`.
+     */
+    pub fn read(&self, bus: &Bus, addr: usize) -> u8 {
+        match self {
+            MemorySpace::Cpu => bus.peek(addr as u16),
+            MemorySpace::Ppu => bus.ppu().peek_vram(addr as u16),
+            MemorySpace::Oam => bus.ppu().oam()[addr],
+            MemorySpace::Palette => bus.ppu().palette_table[addr],
+            MemorySpace::PrgRom => bus.prg_rom()[addr],
+            MemorySpace::ChrRom => bus.ppu().chr_rom[addr],
+        }
+    }
+
+    /**
+     * `addr` must be below `len`. Registers that latch cannot be written.
+     */
+    pub fn write(&self, bus: &mut Bus, addr: usize, data: u8) -> Result<(), String> {
+        match self {
+            MemorySpace::Cpu => return bus.poke(addr as u16, data),
+            MemorySpace::Ppu => bus.ppu_mut().poke_vram(addr as u16, data),
+            MemorySpace::Oam => bus.ppu_mut().poke_oam(addr as u8, data),
+            MemorySpace::Palette => bus.ppu_mut().palette_table[addr] = data,
+            MemorySpace::PrgRom => bus.prg_rom_mut()[addr] = data,
+            MemorySpace::ChrRom => bus.ppu_mut().chr_rom[addr] = data,
+        }
+        Ok(())
+    }
+}
+
+/**
+ * A hex dump of one memory space, driven by typed commands (see `HELP`). Bytes
+ * that changed during the last frame are highlighted.
+ */
+pub struct MemoryViewer {
+    space: MemorySpace,
+    /// first address shown, a multiple of `ROW`
+    address: usize,
+    /// the bytes shown as of the last frame, and which of them that frame changed
+    previous: Vec<u8>,
+    changed: Vec<bool>,
+    /// what `find` last looked for, and where `next` carries on from
+    search: Vec<u8>,
+    found: Option<usize>,
+    /// the cheat search, whose view (8 or 16 bit) `freeze` and `export` follow
+    ram_search: Option<RamSearch>,
+    /// where `cheat save` writes the bus's cheats
+    cheat_file: Option<PathBuf>,
+    message: String,
+}
+
+impl MemoryViewer {
+    pub fn new(cheat_file: Option<PathBuf>) -> Self {
+        MemoryViewer {
+            space: MemorySpace::Cpu,
+            address: 0,
+            previous: vec![],
+            changed: vec![],
+            search: vec![],
+            found: None,
+            ram_search: None,
+            cheat_file,
+            message: String::new(),
+        }
+    }
+
+    /**
+     * Called once per emulated frame to find the bytes it changed.
+     */
+    pub fn frame(&mut self, bus: &Bus) {
+        let current: Vec<u8> = self
+            .visible(bus)
+            .map(|addr| self.space.read(bus, addr))
+            .collect();
+        self.changed = if current.len() == self.previous.len() {
+            current
+                .iter()
+                .zip(&self.previous)
+                .map(|(a, b)| a != b)
+                .collect()
+        } else {
+            vec![false; current.len()]
+        };
+        self.previous = current;
+    }
+
+    /**
+     * Runs one line typed by the user. An empty line shows the next page; errors
+     * end up in `message`.
+     */
+    pub fn command(&mut self, line: &str, bus: &mut Bus) {
+        self.message = match self.run_command(line.trim(), bus) {
+            Ok(message) => message,
+            Err(e) => e,
+        };
+    }
+
+    fn run_command(&mut self, line: &str, bus: &mut Bus) -> Result<String, String> {
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        let page = ROWS * ROW;
+        match command {
+            "" | "+" => self.show(self.space, self.address + page, bus),
+            "-" => self.show(self.space, self.address.saturating_sub(page), bus),
+            "goto" | "g" => {
+                let addr = parse_address(rest)?;
+                if addr >= self.space.len(bus) {
+                    return Err(format!(
+                        "${addr:X} is past the end of {}",
+                        self.space.name()
+                    ));
+                }
+                self.show(self.space, addr, bus);
+            }
+            "set" | "s" => {
+                let (addr, bytes) = rest.split_once(' ').ok_or("set <addr> <bytes>")?;
+                let (addr, bytes) = (parse_address(addr)?, parse_bytes(bytes)?);
+                if addr + bytes.len() > self.space.len(bus) {
+                    return Err(format!(
+                        "${addr:X} is past the end of {}",
+                        self.space.name()
+                    ));
+                }
+                for (i, &byte) in bytes.iter().enumerate() {
+                    self.space.write(bus, addr + i, byte)?;
+                }
+            }
+            "find" | "f" => {
+                self.search = match rest.strip_prefix('"') {
+                    Some(text) => text.trim_end_matches('"').as_bytes().to_vec(),
+                    None => parse_bytes(rest)?,
+                };
+                self.found = None;
+                return self.find_next(bus);
+            }
+            "next" | "n" => return self.find_next(bus),
+            "search" => {
+                let words: Vec<&str> = rest.split_whitespace().collect();
+                if let Some(word) = words.iter().find(|w| !["8", "16", "signed"].contains(w)) {
+                    return Err(format!("Unknown search view {word}: {SEARCH_HELP}"));
+                }
+                let search =
+                    RamSearch::start(bus, words.contains(&"16"), words.contains(&"signed"));
+                let message = format!("{} candidates ({})", search.len(), search.view());
+                self.ram_search = Some(search);
+                return Ok(message);
+            }
+            "filter" => {
+                let search = self.ram_search.as_mut().ok_or("Start a search first")?;
+                search.filter(bus, Filter::parse(rest)?);
+                return Ok(format!("{} candidates", search.len()));
+            }
+            "freeze" => {
+                let (addr, value) = rest.split_once(' ').unwrap_or((rest, ""));
+                let addr = parse_cpu_address(addr)?;
+                let bytes = match (value.trim(), &self.ram_search) {
+                    ("", _) => self.cheat_bytes(bus, addr),
+                    (value, Some(search)) => search.bytes(parse_value(value)?)?,
+                    (value, None) => {
+                        let value = parse_value(value)?;
+                        let byte = u8::try_from(value)
+                            .or_else(|_| i8::try_from(value).map(|byte| byte as u8))
+                            .map_err(|_| format!("{value} does not fit in a byte"))?;
+                        vec![byte]
+                    }
+                };
+                for (i, &byte) in bytes.iter().enumerate() {
+                    bus.freeze(addr.wrapping_add(i as u16), byte)?;
+                }
+            }
+            "unfreeze" => {
+                if rest.is_empty() {
+                    for (addr, _) in bus.frozen().to_vec() {
+                        bus.unfreeze(addr);
+                    }
+                } else {
+                    let addr = parse_cpu_address(rest)?;
+                    for i in 0..self.cheat_bytes(bus, addr).len() {
+                        bus.unfreeze(addr.wrapping_add(i as u16));
+                    }
+                }
+            }
+            "export" => {
+                let addr = parse_cpu_address(rest)?;
+                let codes: Vec<String> = (self.cheat_bytes(bus, addr).iter().enumerate())
+                    .map(|(i, byte)| format!("{:04X}:{byte:02X}", addr.wrapping_add(i as u16)))
+                    .collect();
+                return Ok(format!("Cheat {}", codes.join(" ")));
+            }
+            "cheat" => return self.cheat(rest, bus),
+            name => match MemorySpace::parse(name) {
+                Some(space) => self.show(space, 0, bus),
+                None => return Err(format!("Unknown command {name}: {HELP}")),
+            },
+        }
+        Ok(String::new())
+    }
+
+    /**
+     * `cheat add <code> [name]`, `cheat <n>` to turn one on or off, `cheat remove <n>`,
+     * `cheat on|off` for all of them and `cheat save`. Cheats are numbered from 1.
+     */
+    fn cheat(&mut self, line: &str, bus: &mut Bus) -> Result<String, String> {
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        let index = |text: &str, bus: &Bus| {
+            text.parse::<usize>()
+                .ok()
+                .filter(|&n| n >= 1 && n <= bus.cheats().len())
+                .map(|n| n - 1)
+                .ok_or(format!("No cheat {text}"))
+        };
+        match command {
+            "add" => {
+                let (code, name) = rest.split_once(' ').unwrap_or((rest, ""));
+                let cheat = Cheat::parse(code, name.trim())?;
+                let message = format!(
+                    "Cheat {} is ${:04X}={:02X}",
+                    cheat.code, cheat.addr, cheat.value
+                );
+                bus.cheats_mut().push(cheat);
+                Ok(message)
+            }
+            "remove" => {
+                let i = index(rest, bus)?;
+                bus.cheats_mut().remove(i);
+                Ok(String::new())
+            }
+            "on" | "off" => {
+                bus.set_cheats_enabled(command == "on");
+                Ok(String::new())
+            }
+            "save" => {
+                let path = self
+                    .cheat_file
+                    .as_ref()
+                    .ok_or("No cheat file for this game")?;
+                save_cheats(path, bus.cheats())?;
+                Ok(format!("Saved {}", path.display()))
+            }
+            n => {
+                let i = index(n, bus)?;
+                let cheat = &mut bus.cheats_mut()[i];
+                cheat.enabled = !cheat.enabled;
+                Ok(String::new())
+            }
+        }
+    }
+
+    /**
+     * What `addr` holds now, one byte or two depending on the search's view.
+     */
+    fn cheat_bytes(&self, bus: &Bus, addr: u16) -> Vec<u8> {
+        let len = match &self.ram_search {
+            Some(search) if search.is_word() => 2,
+            _ => 1,
+        };
+        (0..len).map(|i| bus.peek(addr.wrapping_add(i))).collect()
+    }
+
+    fn find_next(&mut self, bus: &Bus) -> Result<String, String> {
+        if self.search.is_empty() {
+            return Err("Nothing to find".to_string());
+        }
+        let len = self.space.len(bus);
+        let start = self.found.map_or(self.address, |found| found + 1);
+        let matches = |addr: usize| {
+            addr + self.search.len() <= len
+                && (self.search.iter().enumerate())
+                    .all(|(i, &byte)| self.space.read(bus, addr + i) == byte)
+        };
+        let found = (0..len)
+            .map(|i| (start + i) % len)
+            .find(|&addr| matches(addr))
+            .ok_or("Not found")?;
+        self.found = Some(found);
+        self.show(self.space, found, bus);
+        Ok(format!(
+            "Found at ${found:0width$X}",
+            width = self.digits(bus)
+        ))
+    }
+
+    fn show(&mut self, space: MemorySpace, addr: usize, bus: &Bus) {
+        if space != self.space {
+            self.found = None;
+        }
+        self.space = space;
+        let last_row = self.space.len(bus).saturating_sub(1) / ROW * ROW;
+        self.address = (addr / ROW * ROW).min(last_row);
+        self.previous.clear();
+        self.changed.clear();
+    }
+
+    fn visible(&self, bus: &Bus) -> Range<usize> {
+        let len = self.space.len(bus);
+        self.address.min(len)..(self.address + ROWS * ROW).min(len)
+    }
+
+    /// hex digits in an address
+    fn digits(&self, bus: &Bus) -> usize {
+        format!("{:X}", self.space.len(bus).max(0x10000) - 1).len()
+    }
+
+    /**
+     * The panel, with ANSI colours for changed bytes and the last match.
+     */
+    pub fn render(&self, bus: &Bus) -> String {
+        let visible = self.visible(bus);
+        let width = self.digits(bus);
+        let mut out = format!(
+            "{} ${:0width$X}-${:0width$X} of ${:X}\n",
+            self.space.name().to_uppercase(),
+            visible.start,
+            visible.end.saturating_sub(1),
+            self.space.len(bus)
+        );
+        let found = self
+            .found
+            .map_or(0..0, |found| found..found + self.search.len());
+        for row in visible.clone().step_by(ROW) {
+            let bytes: Vec<u8> = (row..(row + ROW).min(visible.end))
+                .map(|addr| self.space.read(bus, addr))
+                .collect();
+            out += &format!("${row:0width$X}:");
+            for (i, byte) in bytes.iter().enumerate() {
+                let addr = row + i;
+                let style = if found.contains(&addr) {
+                    FOUND
+                } else if self.changed.get(addr - visible.start) == Some(&true) {
+                    CHANGED
+                } else {
+                    ""
+                };
+                out += &match style {
+                    "" => format!(" {byte:02X}"),
+                    _ => format!(" {style}{byte:02X}{RESET}"),
+                };
+            }
+            let text: String = bytes
+                .iter()
+                .map(|&byte| match byte {
+                    0x20..=0x7E => byte as char,
+                    _ => '.',
+                })
+                .collect();
+            out += &format!("  {text}\n");
+        }
+        if let Some(search) = &self.ram_search {
+            out += &format!("Search ({}): {} candidates\n", search.view(), search.len());
+            for (addr, value, previous) in search.results(bus).into_iter().take(RESULTS) {
+                out += &format!("  ${addr:04X} = {value} (was {previous})\n");
+            }
+        }
+        if !bus.frozen().is_empty() {
+            let frozen: Vec<String> = (bus.frozen().iter())
+                .map(|(addr, data)| format!("${addr:04X}={data:02X}"))
+                .collect();
+            out += &format!("Frozen: {}\n", frozen.join(" "));
+        }
+        if !bus.cheats().is_empty() {
+            let off = if bus.cheats_enabled() {
+                ""
+            } else {
+                " (all off)"
+            };
+            out += &format!("Cheats{off}:\n");
+            for (i, cheat) in bus.cheats().iter().enumerate() {
+                let on = if cheat.enabled { 'x' } else { ' ' };
+                out += &format!("{:>2} [{on}] {} {}\n", i + 1, cheat.code, cheat.name);
+            }
+        }
+        out += HELP;
+        out += "\n";
+        out += SEARCH_HELP;
+        out += "\n";
+        out += CHEAT_HELP;
+        out += "\n";
+        if !self.message.is_empty() {
+            out += &self.message;
+            out += "\n";
+        }
+        out
+    }
+}
+
+/**
+ * A `MemoryViewer` in the terminal. Commands are typed on stdin, and the panel is
+ * redrawn whenever what it shows changes.
+ */
+pub struct MemoryConsole {
+    viewer: MemoryViewer,
+    /// stdin, a line at a time, once the console has been opened
+    lines: Option<Receiver<String>>,
+    enabled: bool,
+    drawn: String,
+}
+
+impl MemoryConsole {
+    pub fn new(cheat_file: Option<PathBuf>) -> Self {
+        MemoryConsole {
+            viewer: MemoryViewer::new(cheat_file),
+            lines: None,
+            enabled: false,
+            drawn: String::new(),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        self.drawn.clear();
+        if self.enabled && self.lines.is_none() {
+            let (sender, receiver) = mpsc::channel();
+            std::thread::spawn(move || {
+                for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            });
+            self.lines = Some(receiver);
+        }
+    }
+
+    pub fn frame(&mut self, bus: &Bus) {
+        if self.enabled {
+            self.viewer.frame(bus);
+        }
+    }
+
+    /**
+     * Runs the commands typed since the last call and redraws if needed.
+     */
+    pub fn update(&mut self, bus: &mut Bus) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if let Some(lines) = &self.lines {
+            for line in lines.try_iter() {
+                self.viewer.command(&line, bus);
+            }
+        }
+        let panel = self.viewer.render(bus);
+        if panel != self.drawn {
+            let mut stdout = std::io::stdout();
+            write!(stdout, "{CLEAR_SCREEN}{panel}")
+                .and_then(|_| stdout.flush())
+                .map_err(|e| format!("Cannot draw the memory viewer: {e}"))?;
+            self.drawn = panel;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * `$0300`, `0x300` or plain `300`, all hex.
+ */
+fn parse_address(text: &str) -> Result<usize, String> {
+    let hex = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+    usize::from_str_radix(hex, 16).map_err(|_| format!("Invalid address {text}"))
+}
+
+fn parse_cpu_address(text: &str) -> Result<u16, String> {
+    let addr = parse_address(text)?;
+    u16::try_from(addr).map_err(|_| format!("${addr:X} is past the end of cpu"))
+}
+
+/**
+ * Hex bytes, either spaced out or run together: `a9 00` or `a900`.
+ */
+fn parse_bytes(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text.split_whitespace().collect();
+    if digits.is_empty() || digits.len() & 1 == 1 {
+        return Err(format!("Invalid bytes {text}"));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| format!("Invalid bytes {text}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cpu::Mem, rom::test::test_rom};
+
+    #[test]
+    fn test_changes_edits_and_search() {
+        let mut bus = Bus::new(test_rom());
+        let mut viewer = MemoryViewer::new(None);
+        viewer.frame(&bus);
+        bus.mem_write(0x0012, 0x34);
+        viewer.frame(&bus);
+        let panel = viewer.render(&bus);
+        assert!(panel.starts_with("CPU $0000-$00FF"));
+        assert!(panel.contains(&format!("$0010: 00 00 {CHANGED}34{RESET} 00")));
+        // nothing changed during the next frame
+        viewer.frame(&bus);
+        assert!(viewer.render(&bus).contains("$0010: 00 00 34 00"));
+
+        viewer.command("set 0300 48 49", &mut bus);
+        assert_eq!(bus.mem_read(0x0300), 0x48);
+        viewer.command("find \"HI\"", &mut bus);
+        assert_eq!(viewer.message, "Found at $0300");
+        assert!(viewer
+            .render(&bus)
+            .contains(&format!("$0300: {FOUND}48{RESET} {FOUND}49{RESET}")));
+        // RAM is mirrored up to $1FFF
+        viewer.command("next", &mut bus);
+        assert_eq!(viewer.message, "Found at $0B00");
+
+        viewer.command("set 2005 01", &mut bus);
+        assert_eq!(
+            viewer.message,
+            "$2005 cannot be written without side effects"
+        );
+        viewer.command("set 4016 01", &mut bus);
+        assert_eq!(viewer.message, "$4016 is a register");
+        viewer.command("goto 10000", &mut bus);
+        assert!(viewer.message.contains("past the end"));
+    }
+
+    #[test]
+    fn test_peeking_leaves_registers_alone() {
+        let mut bus = Bus::new(test_rom());
+        let mut viewer = MemoryViewer::new(None);
+        bus.ppu_mut().poke_vram(0x3F10, 0x21);
+        assert_eq!(bus.ppu().palette_table[0], 0x21);
+
+        // point $2007 at the palette, then look at every space
+        bus.mem_write(0x2006, 0x3F);
+        bus.mem_write(0x2006, 0x00);
+        for space in ["cpu", "ppu", "oam", "palette", "prg", "chr"] {
+            viewer.command(space, &mut bus);
+            viewer.command("goto 2000", &mut bus);
+            viewer.render(&bus);
+        }
+        assert_eq!(bus.mem_read(0x2007), 0x21);
+
+        viewer.command("chr", &mut bus);
+        viewer.command("set 10 ff", &mut bus);
+        assert_eq!(bus.ppu().peek_vram(0x0010), 0xFF);
+        viewer.command("palette", &mut bus);
+        assert!(viewer
+            .render(&bus)
+            .starts_with("PALETTE $0000-$001F of $20"));
+    }
+
+    #[test]
+    fn test_cheat_search_commands() {
+        let mut bus = Bus::new(test_rom());
+        let mut viewer = MemoryViewer::new(None);
+        bus.mem_write(0x0305, 0x34);
+        bus.mem_write(0x0306, 0x12);
+        viewer.command("search 16", &mut bus);
+        assert_eq!(viewer.message, "10238 candidates (16 bit unsigned)");
+        viewer.command("filter = $1234", &mut bus);
+        assert_eq!(viewer.message, "1 candidates");
+        assert!(viewer.render(&bus).contains("  $0305 = 4660 (was 4660)"));
+
+        viewer.command("freeze 305 1000", &mut bus);
+        assert_eq!(bus.frozen(), &[(0x0305, 0xE8), (0x0306, 0x03)]);
+        viewer.command("export 305", &mut bus);
+        assert_eq!(viewer.message, "Cheat 0305:E8 0306:03");
+        viewer.command("unfreeze", &mut bus);
+        assert!(bus.frozen().is_empty());
+
+        viewer.command("filter ~", &mut bus);
+        assert!(viewer.message.starts_with("Unknown filter"));
+    }
+
+    #[test]
+    fn test_cheat_commands() {
+        let mut bus = Bus::new(test_rom());
+        let mut viewer = MemoryViewer::new(None);
+        viewer.command("cheat add sxiopo Infinite lives", &mut bus);
+        assert_eq!(viewer.message, "Cheat SXIOPO is $91D9=AD");
+        viewer.command("cheat add 0075:09", &mut bus);
+        assert!(viewer
+            .render(&bus)
+            .contains(" 1 [x] SXIOPO Infinite lives\n 2 [x] 0075:09"));
+
+        viewer.command("cheat 2", &mut bus);
+        assert!(!bus.cheats()[1].enabled);
+        viewer.command("cheat remove 1", &mut bus);
+        assert_eq!(bus.cheats().len(), 1);
+        viewer.command("cheat off", &mut bus);
+        assert!(viewer
+            .render(&bus)
+            .contains("Cheats (all off):\n 1 [ ] 0075:09"));
+
+        viewer.command("cheat 3", &mut bus);
+        assert_eq!(viewer.message, "No cheat 3");
+        viewer.command("cheat save", &mut bus);
+        assert_eq!(viewer.message, "No cheat file for this game");
+    }
+}
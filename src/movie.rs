@@ -0,0 +1,113 @@
+use crate::joypad::{JoypadButton, Pads, PLAYERS};
+
+/// FCEUX writes each gamepad as these letters, `.` or space when released
+const FM2_BUTTONS: [(char, JoypadButton); 8] = [
+    ('R', JoypadButton::RIGHT),
+    ('L', JoypadButton::LEFT),
+    ('D', JoypadButton::DOWN),
+    ('U', JoypadButton::UP),
+    ('T', JoypadButton::START),
+    ('S', JoypadButton::SELECT),
+    ('B', JoypadButton::BUTTON_B),
+    ('A', JoypadButton::BUTTON_A),
+];
+
+pub type Movie = Vec<Pads>;
+
+pub fn load_fm2(path: &str) -> Result<Movie, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {path}: {e}"))?;
+    parse_fm2(&text).map_err(|e| format!("{path}: {e}"))
+}
+
+/**
+ * FCEUX movie: header lines of `key value`, then one `|commands|port0|port1|port2|`
+ * line per frame, with two more gamepads before port2 when the movie used a Four
+ * Score. Only gamepads are read; resets in the command field are ignored.
+ * https://fceux.com/web/help/fm2.html
+ */
+pub fn parse_fm2(text: &str) -> Result<Movie, String> {
+    let mut frames = vec![];
+    let mut pads = 2;
+    for (n, line) in text.lines().enumerate() {
+        let Some(record) = line.strip_prefix('|') else {
+            if line.split_whitespace().eq(["fourscore", "1"]) {
+                pads = 4;
+            }
+            continue;
+        };
+        let mut fields = record.split('|').skip(1);
+        let mut frame = [JoypadButton::empty(); PLAYERS];
+        for pad in frame.iter_mut().take(pads) {
+            let field = fields.next().unwrap_or("");
+            if field.is_empty() {
+                continue;
+            }
+            if field.chars().count() != FM2_BUTTONS.len() {
+                return Err(format!("line {}: bad gamepad field {field:?}", n + 1));
+            }
+            for (c, (_, button)) in field.chars().zip(FM2_BUTTONS) {
+                if c != '.' && c != ' ' {
+                    pad.insert(button);
+                }
+            }
+        }
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+/**
+ * Replays a movie one emulated frame at a time, however often input is polled: the
+ * movie decides the buttons while it lasts, and the live input source takes the
+ * controllers back once it ends.
+ */
+pub struct MoviePlayback {
+    movie: Movie,
+    position: usize,
+}
+
+impl MoviePlayback {
+    pub fn new(movie: Movie) -> Self {
+        MoviePlayback { movie, position: 0 }
+    }
+
+    /**
+     * Buttons for the next emulated frame, or None once the movie is over.
+     */
+    pub fn next_frame(&mut self) -> Option<Pads> {
+        let frame = *self.movie.get(self.position)?;
+        self.position += 1;
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fm2_playback() {
+        let movie = parse_fm2(
+            "version 3\nport0 1\nport1 1\nport2 0\n\
+             |0|........|........||\n\
+             |0|...U...A|R.......||\n\
+             |1|    T   |        ||\n",
+        )
+        .unwrap();
+        assert_eq!(movie.len(), 3);
+        assert_eq!(
+            movie[1][..2],
+            [
+                JoypadButton::UP | JoypadButton::BUTTON_A,
+                JoypadButton::RIGHT
+            ]
+        );
+        assert_eq!(movie[2][0], JoypadButton::START);
+        assert!(parse_fm2("|0|UDLR|||").is_err());
+
+        let mut playback = MoviePlayback::new(movie);
+        let frames: Vec<Option<Pads>> = (0..4).map(|_| playback.next_frame()).collect();
+        assert_eq!(frames[2].unwrap()[0], JoypadButton::START);
+        assert_eq!(frames[3], None);
+    }
+}
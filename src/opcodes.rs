@@ -353,6 +353,10 @@ static OP_CODES_MAP: phf::Map<u8, OpCode> = phf_map! {
     0x83u8 => OpCode::new(0x83, "*SAX", 1, 6, AddressingMode::Indirect_X),
 };
 
-pub fn get_opcode_details(opcode: &u8) -> Option<&OpCode> {
+pub fn get_opcode_details(opcode: &u8) -> Option<&'static OpCode> {
     OP_CODES_MAP.get(opcode)
 }
+
+pub fn all_opcodes() -> impl Iterator<Item = &'static OpCode> {
+    OP_CODES_MAP.values()
+}
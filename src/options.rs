@@ -0,0 +1,204 @@
+use crate::region::Region;
+
+pub const USAGE: &str = "\
+usage: nes-emulator <rom> [options]
+       nes-emulator --snake
+  <rom>               iNES image with any extension, or a .asm source to assemble
+  --region <region>   console timing: ntsc (default), pal or dendy
+  --scale <1-8>       window size as a multiple of 256x240
+  --fullscreen
+  --headless          no window or sound, runs as fast as possible
+  --trace <file>      nestest-style instruction trace (F8 toggles it)
+  --movie <file>      play back controller input from an FCEUX .fm2 movie
+  --start-pc <addr>   start here instead of the reset vector, e.g. $C000 or a label
+  --frames <n>        quit after n frames
+  --screenshot <file> save the last frame as a PNG when quitting
+  --dump-frame <file> save the last frame's palette indices, one byte per pixel
+  --record <file>     record video and sound to .y4m or .rgb plus .wav, or to any
+                      format ffmpeg writes (.mp4, .mkv, ...)
+  --debug             wait for GDB on 127.0.0.1:9001; it can attach again later
+  --cdl               add to the ROM's code/data log (.cdl) while playing
+  --snake             play snake.asm instead of a cartridge
+  --config <file>     settings file instead of config.toml in the config directory
+scale, fullscreen and the key bindings default to the settings file, which is
+created on first run
+other commands: disasm, asm, cdl, trace, trace-diff, snake, palette";
+
+const MAX_SCALE: u32 = 8;
+
+/**
+ * Command line for playing a ROM. `--key value` and `--key=value` both work.
+ * Settings that can also come from the config file are left unset when not given.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub rom: Option<String>,
+    pub region: Region,
+    pub scale: Option<u32>,
+    pub fullscreen: bool,
+    pub headless: bool,
+    pub trace: Option<String>,
+    pub movie: Option<String>,
+    /// resolved against the ROM's symbols once it is loaded
+    pub start_pc: Option<String>,
+    pub frames: Option<u64>,
+    pub screenshot: Option<String>,
+    pub dump_frame: Option<String>,
+    pub record: Option<String>,
+    pub debug: bool,
+    /// log code, data and drawn tiles to the ROM's .cdl file
+    pub cdl: bool,
+    pub snake: bool,
+    pub config: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            rom: None,
+            region: Region::Ntsc,
+            scale: None,
+            fullscreen: false,
+            headless: false,
+            trace: None,
+            movie: None,
+            start_pc: None,
+            frames: None,
+            screenshot: None,
+            dump_frame: None,
+            record: None,
+            debug: false,
+            cdl: false,
+            snake: false,
+            config: None,
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                if let Some(rom) = &options.rom {
+                    return Err(format!("Got two ROMs, {rom} and {arg}\n{USAGE}"));
+                }
+                options.rom = Some(arg.clone());
+                continue;
+            }
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next().cloned())
+                    .ok_or(format!("{flag} needs a value\n{USAGE}"))
+            };
+            match flag {
+                "--region" => options.region = Region::parse(&value()?)?,
+                "--scale" => {
+                    let scale = value()?;
+                    options.scale = match scale.parse::<u32>() {
+                        Ok(scale @ 1..=MAX_SCALE) => Some(scale),
+                        _ => {
+                            return Err(format!("Invalid scale {scale}, expected 1 to {MAX_SCALE}"))
+                        }
+                    };
+                }
+                "--fullscreen" => options.fullscreen = true,
+                "--headless" => options.headless = true,
+                "--trace" => options.trace = Some(value()?),
+                "--movie" => options.movie = Some(value()?),
+                "--start-pc" => options.start_pc = Some(value()?),
+                "--frames" => {
+                    let frames = value()?;
+                    options.frames = Some(
+                        frames
+                            .parse::<u64>()
+                            .map_err(|e| format!("Invalid frame count {frames}: {e}"))?,
+                    );
+                }
+                "--screenshot" => options.screenshot = Some(value()?),
+                "--dump-frame" => options.dump_frame = Some(value()?),
+                "--record" => options.record = Some(value()?),
+                "--debug" => options.debug = true,
+                "--cdl" => options.cdl = true,
+                "--snake" => options.snake = true,
+                "--config" => options.config = Some(value()?),
+                "--help" => return Err(USAGE.to_string()),
+                _ => return Err(format!("Unknown option {flag}\n{USAGE}")),
+            }
+            if inline.is_some()
+                && matches!(
+                    flag,
+                    "--fullscreen" | "--headless" | "--debug" | "--cdl" | "--snake"
+                )
+            {
+                return Err(format!("{flag} does not take a value"));
+            }
+        }
+
+        if options.snake && options.rom.is_some() {
+            return Err("--snake plays snake.asm, it cannot be given a ROM".to_string());
+        }
+        if !options.snake && options.rom.is_none() {
+            return Err(USAGE.to_string());
+        }
+        if options.headless && options.fullscreen {
+            return Err(
+                "--fullscreen needs a window, it cannot be used with --headless".to_string(),
+            );
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        let args: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options =
+            parse("game.bin --region pal --scale=2 --headless --frames 600 --start-pc $C000")
+                .unwrap();
+        assert_eq!(
+            options,
+            Options {
+                rom: Some("game.bin".to_string()),
+                region: Region::Pal,
+                scale: Some(2),
+                headless: true,
+                frames: Some(600),
+                start_pc: Some("$C000".to_string()),
+                ..Options::default()
+            }
+        );
+        assert!(parse("--snake").unwrap().snake);
+    }
+
+    #[test]
+    fn test_bad_options() {
+        assert!(parse("").unwrap_err().starts_with("usage"));
+        assert!(parse("game.nes --scale 9")
+            .unwrap_err()
+            .contains("Invalid scale 9"));
+        assert!(parse("game.nes --frames")
+            .unwrap_err()
+            .contains("--frames needs a value"));
+        assert!(parse("game.nes --regoin pal")
+            .unwrap_err()
+            .contains("Unknown option --regoin"));
+        assert!(parse("a.nes b.nes").unwrap_err().contains("two ROMs"));
+        assert!(parse("--snake game.nes").is_err());
+        assert!(parse("game.nes --headless=yes").is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{frame::Frame, region::Region};
+
+/// the most any one edge may hide
+pub const MAX_EDGE: usize = 64;
+
+/**
+ * Pixels hidden at each edge of the picture before it is shown. NTSC TVs hid
+ * about 8 lines at the top and bottom, and games left garbage there; PAL sets
+ * showed all 240.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Overscan {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Overscan {
+    pub fn for_region(region: Region) -> Self {
+        match region {
+            Region::Ntsc => Overscan {
+                top: 8,
+                bottom: 8,
+                left: 0,
+                right: 0,
+            },
+            Region::Pal | Region::Dendy => Overscan::default(),
+        }
+    }
+
+    /**
+     * `[top, bottom, left, right]`, as the config file writes it.
+     */
+    pub fn from_edges([top, bottom, left, right]: [usize; 4]) -> Self {
+        Overscan {
+            top,
+            bottom,
+            left,
+            right,
+        }
+    }
+
+    pub fn edges(&self) -> [usize; 4] {
+        [self.top, self.bottom, self.left, self.right]
+    }
+
+    pub fn is_none(&self) -> bool {
+        *self == Overscan::default()
+    }
+
+    /**
+     * The size of a `width` x `height` picture once cropped.
+     */
+    pub fn visible(&self, width: usize, height: usize) -> (usize, usize) {
+        (
+            width.saturating_sub(self.left + self.right).max(1),
+            height.saturating_sub(self.top + self.bottom).max(1),
+        )
+    }
+
+    pub fn crop(&self, frame: &Frame) -> Frame {
+        let (width, height) = self.visible(frame.width, frame.height);
+        let mut cropped = Frame::with_size(width, height);
+        cropped.emphasis = frame.emphasis;
+        for y in 0..height.min(frame.height - self.top.min(frame.height)) {
+            let start = (self.top + y) * frame.width + self.left;
+            let row = &frame.pixels[start..(start + width).min(frame.pixels.len())];
+            cropped.pixels[y * width..y * width + row.len()].copy_from_slice(row);
+        }
+        cropped
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crop() {
+        let mut frame = Frame::with_size(4, 4);
+        for (i, pixel) in frame.pixels.iter_mut().enumerate() {
+            *pixel = i as u8;
+        }
+        frame.emphasis = 0b101;
+        let overscan = Overscan::from_edges([1, 2, 1, 0]);
+        let cropped = overscan.crop(&frame);
+        assert_eq!((cropped.width, cropped.height), (3, 1));
+        assert_eq!(cropped.pixels, [5, 6, 7]);
+        assert_eq!(cropped.emphasis, 0b101);
+
+        assert_eq!(
+            Overscan::for_region(Region::Ntsc).visible(256, 240),
+            (256, 224)
+        );
+        assert!(Overscan::for_region(Region::Pal).is_none());
+        assert!(Overscan::for_region(Region::Dendy).is_none());
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{emulator::AUDIO_SAMPLE_RATE, frontend::AudioSink};
+
+/// give up on catching up after falling this many frames behind
+const MAX_LAG_FRAMES: u32 = 4;
+/// sleep until this close to a deadline, then spin
+const SPIN_MARGIN: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncMode {
+    /// wall clock deadlines at the console's refresh rate
+    Timer,
+    /// keep the audio queue around `AUDIO_FRAMES` frames deep, falling back to the
+    /// timer when the sink can't say how full it is
+    Audio,
+}
+
+const AUDIO_FRAMES: f64 = 3.0;
+
+/**
+ * Where the pacer gets the time from, so tests don't depend on the wall clock.
+ */
+pub trait Clock {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+    /// returns at `deadline`, or as soon after it as the system allows
+    fn sleep_until(&self, deadline: Instant);
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+
+    fn sleep_until(&self, deadline: Instant) {
+        let now = Instant::now();
+        if deadline > now + SPIN_MARGIN {
+            thread::sleep(deadline - now - SPIN_MARGIN);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/**
+ * Holds the emulator to the console's refresh rate. Deadlines are absolute
+ * (start + n * period), so oversleeping one frame is paid back on the next instead of
+ * accumulating as drift.
+ */
+pub struct FramePacer {
+    period: Duration,
+    frame_rate: f64,
+    speed: f64,
+    deadline: Option<Instant>,
+    mode: SyncMode,
+    /// samples to keep queued in `SyncMode::Audio`
+    audio_target: usize,
+    clock: Box<dyn Clock>,
+}
+
+impl FramePacer {
+    pub fn new(frame_rate: f64, mode: SyncMode) -> Self {
+        FramePacer {
+            period: Duration::from_secs_f64(1.0 / frame_rate),
+            frame_rate,
+            speed: 1.0,
+            deadline: None,
+            mode,
+            audio_target: (AUDIO_FRAMES * AUDIO_SAMPLE_RATE as f64 / frame_rate) as usize,
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    pub fn set_audio_latency(&mut self, samples: usize) {
+        self.audio_target = samples;
+    }
+
+    /**
+     * Runs frames at `speed` times the console's rate, e.g. 0.25 for slow motion.
+     */
+    pub fn set_speed(&mut self, speed: f64) {
+        if speed != self.speed {
+            self.speed = speed;
+            self.period = Duration::from_secs_f64(1.0 / (self.frame_rate * speed));
+            self.reset();
+        }
+    }
+
+    /**
+     * Blocks until the next frame is due. Pass `None` for `audio` while nothing is
+     * being queued (paused, muted), so pacing falls back to the timer.
+     */
+    pub fn wait(&mut self, audio: Option<&dyn AudioSink>) {
+        if self.mode == SyncMode::Audio {
+            if let Some(audio) = audio.filter(|audio| audio.queued().is_some()) {
+                if self.wait_for_audio(audio) {
+                    self.reset();
+                    return;
+                }
+                // the device stopped playing (stalled, paused behind our back)
+            }
+        }
+        self.wait_for_deadline();
+    }
+
+    /**
+     * Waits for the audio queue to drain down to its target, but never past the
+     * frame's deadline. Returns false if it gave up.
+     */
+    fn wait_for_audio(&mut self, audio: &dyn AudioSink) -> bool {
+        let give_up = self.deadline.unwrap_or(self.clock.now() + self.period);
+        while audio.queued().unwrap_or(0) > self.audio_target {
+            if self.clock.now() >= give_up {
+                return false;
+            }
+            self.clock.sleep(SPIN_MARGIN);
+        }
+        true
+    }
+
+    fn wait_for_deadline(&mut self) {
+        let now = self.clock.now();
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => {
+                self.deadline = Some(now + self.period);
+                return;
+            }
+        };
+
+        if now > deadline + self.period * MAX_LAG_FRAMES {
+            // too far behind (breakpoint, window drag): start over rather than run flat out
+            self.deadline = Some(now + self.period);
+            return;
+        }
+        self.clock.sleep_until(deadline);
+        self.deadline = Some(deadline + self.period);
+    }
+
+    /**
+     * Forget the schedule, e.g. after being paused.
+     */
+    pub fn reset(&mut self) {
+        self.deadline = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{cell::Cell, rc::Rc};
+
+    /// time only passes when the pacer sleeps
+    #[derive(Clone)]
+    struct FakeClock(Rc<Cell<Instant>>);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.0.set(self.0.get() + duration);
+        }
+
+        fn sleep_until(&self, deadline: Instant) {
+            self.0.set(self.0.get().max(deadline));
+        }
+    }
+
+    /// an audio device that never plays what it is given
+    struct Stalled;
+
+    impl AudioSink for Stalled {
+        fn queue(&mut self, _samples: &[f32]) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn queued(&self) -> Option<usize> {
+            Some(usize::MAX)
+        }
+    }
+
+    fn fake_pacer(mode: SyncMode) -> (FramePacer, FakeClock) {
+        let clock = FakeClock(Rc::new(Cell::new(Instant::now())));
+        let mut pacer = FramePacer::new(200.0, mode);
+        pacer.clock = Box::new(clock.clone());
+        (pacer, clock)
+    }
+
+    #[test]
+    fn test_paces_without_drift() {
+        let (mut pacer, clock) = fake_pacer(SyncMode::Timer);
+        let start = clock.now();
+        pacer.wait(None);
+        // a frame that overran by 3ms is paid back by the next
+        clock.sleep(Duration::from_millis(8));
+        for _ in 0..20 {
+            pacer.wait(None);
+        }
+        // the first call only starts the clock, then 20 frames of 5ms
+        assert_eq!(clock.now() - start, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_stalled_audio_falls_back_to_the_timer() {
+        let (mut pacer, clock) = fake_pacer(SyncMode::Audio);
+        let start = clock.now();
+        for _ in 0..21 {
+            pacer.wait(Some(&Stalled));
+        }
+        assert_eq!(clock.now() - start, Duration::from_millis(105));
+    }
+}
@@ -0,0 +1,228 @@
+use std::path::Path;
+
+pub type Rgb = (u8, u8, u8);
+
+/**
+ * 2C02 colours as RGB, indexed by the 6 bit values stored in palette RAM.
+ */
+#[rustfmt::skip]
+pub static SYSTEM_PALETTE: [Rgb; 64] = [
+   (0x80, 0x80, 0x80), (0x00, 0x3D, 0xA6), (0x00, 0x12, 0xB0), (0x44, 0x00, 0x96), (0xA1, 0x00, 0x5E),
+   (0xC7, 0x00, 0x28), (0xBA, 0x06, 0x00), (0x8C, 0x17, 0x00), (0x5C, 0x2F, 0x00), (0x10, 0x45, 0x00),
+   (0x05, 0x4A, 0x00), (0x00, 0x47, 0x2E), (0x00, 0x41, 0x66), (0x00, 0x00, 0x00), (0x05, 0x05, 0x05),
+   (0x05, 0x05, 0x05), (0xC7, 0xC7, 0xC7), (0x00, 0x77, 0xFF), (0x21, 0x55, 0xFF), (0x82, 0x37, 0xFA),
+   (0xEB, 0x2F, 0xB5), (0xFF, 0x29, 0x50), (0xFF, 0x22, 0x00), (0xD6, 0x32, 0x00), (0xC4, 0x62, 0x00),
+   (0x35, 0x80, 0x00), (0x05, 0x8F, 0x00), (0x00, 0x8A, 0x55), (0x00, 0x99, 0xCC), (0x21, 0x21, 0x21),
+   (0x09, 0x09, 0x09), (0x09, 0x09, 0x09), (0xFF, 0xFF, 0xFF), (0x0F, 0xD7, 0xFF), (0x69, 0xA2, 0xFF),
+   (0xD4, 0x80, 0xFF), (0xFF, 0x45, 0xF3), (0xFF, 0x61, 0x8B), (0xFF, 0x88, 0x33), (0xFF, 0x9C, 0x12),
+   (0xFA, 0xBC, 0x20), (0x9F, 0xE3, 0x0E), (0x2B, 0xF0, 0x35), (0x0C, 0xF0, 0xA4), (0x05, 0xFB, 0xFF),
+   (0x5E, 0x5E, 0x5E), (0x0D, 0x0D, 0x0D), (0x0D, 0x0D, 0x0D), (0xFF, 0xFF, 0xFF), (0xA6, 0xFC, 0xFF),
+   (0xB3, 0xEC, 0xFF), (0xDA, 0xAB, 0xEB), (0xFF, 0xA8, 0xF9), (0xFF, 0xAB, 0xB3), (0xFF, 0xD2, 0xB0),
+   (0xFF, 0xEF, 0xA6), (0xFF, 0xF7, 0x9C), (0xD7, 0xE8, 0x95), (0xA6, 0xED, 0xAF), (0xA2, 0xF2, 0xDA),
+   (0x99, 0xFF, 0xFC), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11),
+];
+
+/// colours in a `.pal` file without emphasis
+pub const PALETTE_SIZE: usize = 64;
+/// colours in a `.pal` file with all 8 emphasis combinations, 64 after another
+pub const EMPHASIS_PALETTE_SIZE: usize = 8 * PALETTE_SIZE;
+
+/**
+ * Picks a palette by its `[video] palette` setting: `default`, `ntsc` for one
+ * generated from `ntsc`, or the path of a `.pal` file.
+ */
+pub fn load(name: &str, ntsc: &NtscParams) -> Result<Vec<Rgb>, String> {
+    match name {
+        "" | "default" => Ok(SYSTEM_PALETTE.to_vec()),
+        "ntsc" => Ok(generate_ntsc(ntsc)),
+        path => load_pal(Path::new(path)),
+    }
+}
+
+pub fn load_pal(path: &Path) -> Result<Vec<Rgb>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+    parse_pal(&bytes).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/**
+ * A `.pal` file is RGB triples and nothing else: 64 colours, or 512 when it
+ * also covers the emphasis bits.
+ */
+pub fn parse_pal(bytes: &[u8]) -> Result<Vec<Rgb>, String> {
+    let colours = bytes.len() / 3;
+    match colours {
+        PALETTE_SIZE | EMPHASIS_PALETTE_SIZE if colours * 3 == bytes.len() => Ok(bytes
+            .chunks(3)
+            .map(|rgb| (rgb[0], rgb[1], rgb[2]))
+            .collect()),
+        _ => Err(format!(
+            "expected {} or {} bytes of RGB, got {}",
+            PALETTE_SIZE * 3,
+            EMPHASIS_PALETTE_SIZE * 3,
+            bytes.len()
+        )),
+    }
+}
+
+pub fn save_pal(path: &Path, palette: &[Rgb]) -> Result<(), String> {
+    let bytes: Vec<u8> = palette.iter().flat_map(|&(r, g, b)| [r, g, b]).collect();
+    std::fs::write(path, bytes).map_err(|e| format!("Cannot write {}: {e}", path.display()))
+}
+
+/**
+ * Knobs for `generate_ntsc` and the NTSC filter, as on a TV. The defaults decode
+ * the signal as is.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct NtscParams {
+    /// degrees added to every colour's phase
+    pub hue: f64,
+    pub saturation: f64,
+    pub contrast: f64,
+    /// added to luma, where 1.0 is the gap between black and white
+    pub brightness: f64,
+    /// values above 1 lighten the mid tones
+    pub gamma: f64,
+}
+
+impl Default for NtscParams {
+    fn default() -> Self {
+        NtscParams {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+/// 2C02 output in volts for luma levels 0-3, at the low and high halves of the
+/// square wave it draws for a colour
+const SIGNAL_LOW: [f64; 4] = [0.350, 0.518, 0.962, 1.550];
+const SIGNAL_HIGH: [f64; 4] = [1.094, 1.506, 1.962, 1.962];
+const BLACK: f64 = 0.518;
+const WHITE: f64 = 1.962;
+/// how much an emphasis bit darkens the signal during its colour's phases
+const EMPHASIS_ATTENUATION: f64 = 0.746;
+/// the colours (hues) whose phases the red, green and blue emphasis bits darken
+const EMPHASIS_HUES: [usize; 3] = [0x0C, 0x04, 0x08];
+/// turns the decoder's reference phase so that $x6 comes out red
+const PHASE_OFFSET: f64 = 4.0;
+
+/**
+ * Builds all 512 colours by simulating the PPU's composite output and decoding it
+ * as a TV would. Each hue is a square wave 6 of 12 colour clock phases high,
+ * emphasis darkens chosen phases, and the wave is split back into luma (YIQ's Y)
+ * and the two chroma components (I and Q).
+ * https://www.nesdev.org/wiki/NTSC_video
+ */
+pub fn generate_ntsc(params: &NtscParams) -> Vec<Rgb> {
+    (0..EMPHASIS_PALETTE_SIZE)
+        .map(|entry| ntsc_colour(entry % PALETTE_SIZE, entry / PALETTE_SIZE, params))
+        .collect()
+}
+
+fn ntsc_colour(index: usize, emphasis: usize, params: &NtscParams) -> Rgb {
+    let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+    for phase in 0..12 {
+        let signal = ntsc_signal(index, emphasis, phase);
+        let angle = ntsc_angle(phase as f64, params);
+        y += signal / 12.0;
+        i += signal * angle.cos() / 6.0;
+        q += signal * angle.sin() / 6.0;
+    }
+    yiq_to_rgb(y, i, q, params)
+}
+
+/**
+ * The PPU's output for a colour at one of the 12 phases of the colour subcarrier,
+ * scaled so that black is 0.0 and white 1.0.
+ */
+pub fn ntsc_signal(index: usize, emphasis: usize, phase: usize) -> f64 {
+    let (hue, level) = (index & 0x0F, (index >> 4) & 3);
+    let in_phase = |hue: usize| (hue + phase) % 12 < 6;
+    let signal = match hue {
+        0x00 => SIGNAL_HIGH[level],
+        0x0D => SIGNAL_LOW[level],
+        // $xE and $xF are black at every level
+        0x0E | 0x0F => return 0.0,
+        _ if in_phase(hue) => SIGNAL_HIGH[level],
+        _ => SIGNAL_LOW[level],
+    };
+    let signal = (signal - BLACK) / (WHITE - BLACK);
+    let darkened = (0..3).any(|bit| emphasis & (1 << bit) != 0 && in_phase(EMPHASIS_HUES[bit]));
+    if darkened {
+        signal * EMPHASIS_ATTENUATION
+    } else {
+        signal
+    }
+}
+
+/**
+ * Where the decoder's reference wave is at `phase`, in radians.
+ */
+pub fn ntsc_angle(phase: f64, params: &NtscParams) -> f64 {
+    std::f64::consts::PI * (phase + PHASE_OFFSET + params.hue / 30.0) / 6.0
+}
+
+/**
+ * Decoded luma and chroma to RGB, after the TV's knobs.
+ */
+pub fn yiq_to_rgb(y: f64, i: f64, q: f64, params: &NtscParams) -> Rgb {
+    let y = y * params.contrast + params.brightness;
+    let (i, q) = (
+        i * params.contrast * params.saturation,
+        q * params.contrast * params.saturation,
+    );
+    let channel = |value: f64| {
+        let value = value.clamp(0.0, 1.0).powf(1.0 / params.gamma);
+        (value * 255.0).round() as u8
+    };
+    (
+        channel(y + 0.956 * i + 0.621 * q),
+        channel(y - 0.272 * i - 0.647 * q),
+        channel(y - 1.106 * i + 1.703 * q),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_generated_ntsc_palette() {
+        let palette = generate_ntsc(&NtscParams::default());
+        assert_eq!(palette.len(), EMPHASIS_PALETTE_SIZE);
+        assert_eq!(palette[0x0F], (0, 0, 0));
+        assert_eq!(palette[0x30], (255, 255, 255));
+        let (r, g, b) = palette[0x10];
+        assert!(r == g && g == b);
+        // the hue of each primary lies where the hand-made default puts it
+        let strongest =
+            |(r, g, b): Rgb| [r, g, b].iter().enumerate().max_by_key(|c| c.1).unwrap().0;
+        for index in [0x16, 0x1A, 0x12] {
+            assert_eq!(strongest(palette[index]), strongest(SYSTEM_PALETTE[index]));
+        }
+        // red emphasis tints white red, and never touches black
+        let (r, g, b) = palette[PALETTE_SIZE + 0x30];
+        assert!(r > g && r > b);
+        assert_eq!(palette[7 * PALETTE_SIZE + 0x0F], (0, 0, 0));
+
+        let darker = generate_ntsc(&NtscParams {
+            brightness: -0.1,
+            ..NtscParams::default()
+        });
+        assert!(darker[0x10].0 < palette[0x10].0);
+    }
+
+    #[test]
+    fn test_pal_files() {
+        let palette = generate_ntsc(&NtscParams::default());
+        let bytes: Vec<u8> = palette.iter().flat_map(|&(r, g, b)| [r, g, b]).collect();
+        assert_eq!(parse_pal(&bytes), Ok(palette.clone()));
+        assert_eq!(parse_pal(&bytes[..192]).unwrap(), palette[..64]);
+        assert!(parse_pal(&bytes[..191]).is_err());
+        assert!(parse_pal(&bytes[..384]).is_err());
+    }
+}
@@ -1,6 +1,13 @@
 mod registers;
+mod render;
+pub mod viewer;
 
-use crate::{cpu::Mem, rom::Mirroring};
+use crate::{
+    cdl::{ChrAccess, SharedLogger},
+    cpu::{Mem, Peek},
+    region::Region,
+    rom::Mirroring,
+};
 use registers::{
     address::AddressRegister, control::ControlRegister, mask::MaskRegister, oam::Oam,
     scroll::ScrollRegister, status::StatusRegister,
@@ -15,7 +22,6 @@ pub const PALETTE_START_ADDR: u16 = 0x3f00;
 pub const BEFORE_MIRROR_RANGE: u16 = 0x3FFF;
 
 // CLOCK
-const SCAN_LINES_PER_FRAME: u16 = 262;
 const CLOCK_CYCLES_PER_SCAN_LINE: usize  = 341;
 const SCAN_LINE_INTERRUPT: u16  = 241;
 
@@ -32,8 +38,60 @@ pub struct PPU {
     oam: Oam,
     data_buffer: u8,
     scan_line: u16,
+    scan_lines_per_frame: u16,
+    region: Region,
     cycles: usize,
-    nmi: Option<bool>
+    nmi: Option<bool>,
+    cdl: Option<SharedLogger>,
+}
+
+/**
+ * $3F00-$3FFF repeats the 32 bytes of palette RAM, and the backdrop entries of the
+ * sprite palettes ($3F10/$3F14/$3F18/$3F1C) are those of the background ones.
+ */
+fn palette_index(addr: u16) -> usize {
+    let index = (addr - PALETTE_START_ADDR) as usize % 32;
+    if index >= 16 && index & 0b11 == 0 {
+        index - 16
+    } else {
+        index
+    }
+}
+
+/**
+ * The registers as `mem_read` would see them, without clearing vblank, resetting
+ * the latches or moving the VRAM address. Write-only registers read as 0.
+ */
+impl Peek for PPU {
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x2002 => self.status.bits(),
+            0x2004 => self.oam.read_data(),
+            // palette reads skip the buffer
+            0x2007 => match self.addr.get() {
+                addr @ PALETTE_START_ADDR..=BEFORE_MIRROR_RANGE => self.peek_vram(addr),
+                _ => self.data_buffer,
+            },
+            _ => 0,
+        }
+    }
+
+    /**
+     * $2000 never raises an NMI, $2004 and $2007 leave their addresses where they
+     * are, and $2005/$2006 are refused since each write flips a latch.
+     */
+    fn poke(&mut self, addr: u16, data: u8) -> Result<(), String> {
+        match addr {
+            0x2000 => self.control = ControlRegister::from_bits_truncate(data),
+            0x2001 => self.mask.update(data),
+            0x2002 => self.status = StatusRegister::from_bits_truncate(data),
+            0x2003 => self.oam.write_addr(data),
+            0x2004 => self.oam.poke(self.oam.addr(), data),
+            0x2007 => self.poke_vram(self.addr.get(), data),
+            _ => return Err(format!("${addr:04X} cannot be written without side effects")),
+        }
+        Ok(())
+    }
 }
 
 impl Mem for PPU {
@@ -86,12 +144,30 @@ impl PPU {
             oam: Oam::new(),
             data_buffer: 0,
             scan_line: 0,
+            scan_lines_per_frame: Region::Ntsc.scanlines_per_frame(),
+            region: Region::Ntsc,
             cycles: 0,
-            nmi: None
+            nmi: None,
+            cdl: None,
         }
     }
 
+    pub fn set_region(&mut self, region: Region) {
+        self.scan_lines_per_frame = region.scanlines_per_frame();
+        self.region = region;
+    }
+
+    pub fn attach_cdl(&mut self, logger: SharedLogger) {
+        self.cdl = Some(logger);
+    }
+
+    /**
+     * Returns true when vblank starts, which is when a finished frame can be shown.
+     */
     pub fn tick(&mut self, cycles: u8) -> bool {
+        if self.is_sprite_zero_hit(self.cycles) {
+            self.status.set_sprite_zero_hit(true);
+        }
         self.cycles += cycles as usize;
         if self.cycles >= CLOCK_CYCLES_PER_SCAN_LINE {
             self.cycles = self.cycles - CLOCK_CYCLES_PER_SCAN_LINE;
@@ -103,23 +179,84 @@ impl PPU {
                 if self.control.generate_nmi() {
                     self.nmi = Some(true);
                 }
+                return true;
             }
 
-            if self.scan_line >= SCAN_LINES_PER_FRAME {
+            if self.scan_line >= self.scan_lines_per_frame {
                 self.scan_line = 0;
                 self.nmi = None;
                 self.status.set_sprite_zero_hit(false);
                 self.status.reset_vblank();
-                return true;
             }
         }
         return false;
     }
     
+    /**
+     * Approximation: sprite 0 hits as soon as the beam passes its top left corner,
+     * whether or not it overlaps an opaque background pixel. Sprites are drawn one
+     * line below their OAM Y.
+     */
+    fn is_sprite_zero_hit(&self, cycle: usize) -> bool {
+        let oam = self.oam.data();
+        let top = oam[0] as usize + 1;
+        let x = oam[3] as usize;
+        top == self.scan_line as usize && x <= cycle && self.mask.show_sprites()
+    }
+
+    pub fn write_oam_dma(&mut self, page: &[u8; 256]) {
+        self.oam.write_dma(page);
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scan_line
+    }
+
+    pub fn dot(&self) -> usize {
+        self.cycles
+    }
+
     pub fn poll_nmi(&mut self) -> Option<bool> {
         self.nmi.take()
     }
 
+    pub fn oam(&self) -> &[u8; 256] {
+        self.oam.data()
+    }
+
+    pub fn poke_oam(&mut self, index: u8, data: u8) {
+        self.oam.poke(index, data);
+    }
+
+    /**
+     * Reads PPU address space ($0000-$3FFF) directly, as the PPU itself sees it.
+     */
+    pub fn peek_vram(&self, addr: u16) -> u8 {
+        match addr & BEFORE_MIRROR_RANGE {
+            addr @ 0..=CHR_ROM_END_ADDR => self.chr_rom.get(addr as usize).copied().unwrap_or(0),
+            addr @ NAME_TABLE_START_ADDR..=0x3EFF => self.vram[self.mirror_vram(addr) as usize],
+            addr => self.palette_table[palette_index(addr)],
+        }
+    }
+
+    /**
+     * Writes PPU address space directly. Unlike $2007, CHR ROM can be patched.
+     */
+    pub fn poke_vram(&mut self, addr: u16, data: u8) {
+        match addr & BEFORE_MIRROR_RANGE {
+            addr @ 0..=CHR_ROM_END_ADDR => {
+                if let Some(byte) = self.chr_rom.get_mut(addr as usize) {
+                    *byte = data;
+                }
+            }
+            addr @ NAME_TABLE_START_ADDR..=0x3EFF => {
+                let index = self.mirror_vram(addr) as usize;
+                self.vram[index] = data;
+            }
+            addr => self.palette_table[palette_index(addr)] = data,
+        }
+    }
+
     fn read_status(&mut self) -> u8 {
         let status = self.status.bits();
         self.status.reset_vblank();
@@ -134,6 +271,9 @@ impl PPU {
         match ppu_addr {
             0..=CHR_ROM_END_ADDR => {
                 let data = self.data_buffer;
+                if let Some(cdl) = &self.cdl {
+                    cdl.borrow_mut().log_chr(ppu_addr as usize, ChrAccess::READ);
+                }
                 self.data_buffer = self.chr_rom[ppu_addr as usize];
                 data
             }
@@ -216,3 +356,84 @@ impl PPU {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frame::Frame;
+
+    #[test]
+    fn test_frame_ends_when_vblank_starts() {
+        let mut ppu = PPU::new(vec![0; 0x2000], Mirroring::HORIZONTAL);
+        // one scanline is 341 dots: 31 ticks of 11
+        let frame = 31 * ppu.scan_lines_per_frame as usize;
+        let mut ends = vec![];
+        for tick in 0..2 * frame {
+            if ppu.tick(11) {
+                ends.push((tick, ppu.scanline(), ppu.status.is_in_vblank()));
+            }
+        }
+        assert_eq!(
+            ends,
+            vec![(30 + 31 * 240, 241, true), (30 + 31 * 240 + frame, 241, true)]
+        );
+    }
+
+    fn draw_test_scene() -> PPU {
+        let mut chr = vec![0; 0x2000];
+        // top row of tile 1 uses colour 1
+        chr[16] = 0xFF;
+        let mut ppu = PPU::new(chr, Mirroring::HORIZONTAL);
+        ppu.palette_table[0] = 0x21;
+        ppu.palette_table[1] = 0x16;
+        ppu.palette_table[0x11] = 0x2A;
+        ppu.vram[0] = 1;
+        // sprite 0: tile 1 with its top row on line 10, from x 50
+        ppu.oam.write_dma(&std::array::from_fn(|i| [9, 1, 0, 50][i % 4]));
+        // background and sprites, including the leftmost 8 pixels
+        ppu.mem_write(0x2001, 0b0001_1110);
+        ppu
+    }
+
+    #[test]
+    fn test_render_scrolled_background_and_sprites() {
+        let mut ppu = draw_test_scene();
+        let mut frame = Frame::new();
+        ppu.render(&mut frame);
+        assert_eq!(frame.pixel(0, 0), 0x16);
+        assert_eq!(frame.pixel(7, 0), 0x16);
+        assert_eq!(frame.pixel(8, 0), 0x21);
+        assert_eq!(frame.pixel(0, 1), 0x21);
+        assert_eq!(frame.pixel(50, 10), 0x2A);
+        assert_eq!(frame.pixel(50, 11), 0x21);
+
+        // 8 pixels right: the tile moves off the left edge and comes back on the right
+        // from the next nametable, which horizontal mirroring makes the same one
+        ppu.mem_write(0x2005, 8);
+        ppu.mem_write(0x2005, 0);
+        ppu.render(&mut frame);
+        assert_eq!(frame.pixel(0, 0), 0x21);
+        assert_eq!(frame.pixel(248, 0), 0x16);
+        assert_eq!(frame.pixel(50, 10), 0x2A);
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_timing() {
+        let mut ppu = draw_test_scene();
+        let hit = |ppu: &mut PPU| ppu.mem_read(0x2002) & 0b0100_0000 != 0;
+        while (ppu.scanline(), ppu.dot()) < (10, 50) {
+            ppu.tick(1);
+            assert!(!hit(&mut ppu));
+        }
+        while ppu.dot() <= 50 {
+            ppu.tick(1);
+        }
+        assert!(hit(&mut ppu));
+
+        // cleared when vblank starts
+        while ppu.scanline() != 241 {
+            ppu.tick(1);
+        }
+        assert!(!hit(&mut ppu));
+    }
+}
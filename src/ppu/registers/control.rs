@@ -29,7 +29,7 @@ impl ControlRegister {
     }
 
     pub fn get_nametable_address(&self) -> u16 {
-        match self.bits() & 0x11 {
+        match self.bits() & 0b11 {
             0 => 0x2000,
             1 => 0x2400,
             2 => 0x2800,
@@ -81,3 +81,17 @@ impl ControlRegister {
         return self.contains(ControlRegister::GENERATE_NMI);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_nametable_address() {
+        let mut control = ControlRegister::new();
+        for (bits, address) in [(0b00, 0x2000), (0b01, 0x2400), (0b10, 0x2800), (0b11, 0x2C00)] {
+            control.update(0b1001_0000 | bits, false);
+            assert_eq!(control.get_nametable_address(), address);
+        }
+    }
+}
@@ -1,5 +1,7 @@
 
 use bitflags::bitflags;
+
+use crate::region::Region;
 bitflags! {
     pub struct MaskRegister: u8 {
         const GREYSCALE                 = 0b0000_0001;
@@ -43,26 +45,28 @@ impl MaskRegister {
         return self.contains(MaskRegister::SHOW_SPRITES);
     }
 
-    pub fn emphasise(&self) -> Vec<Colour> {
-        let mut colours = vec![];
-        if self.contains(MaskRegister::EMPHASISE_RED) {
-            colours.push(Colour::RED);
+    /**
+     * The emphasis bits as red, green and blue in bits 0-2, the order `Frame` uses.
+     * The 2C07 and the Dendy's PAL-derived PPU wire the red and green bits the other
+     * way round.
+     */
+    pub fn emphasis(&self, region: Region) -> u8 {
+        let (red, green) = match region {
+            Region::Ntsc => (MaskRegister::EMPHASISE_RED, MaskRegister::EMPHASISE_GREEN),
+            Region::Pal | Region::Dendy => {
+                (MaskRegister::EMPHASISE_GREEN, MaskRegister::EMPHASISE_RED)
+            }
+        };
+        let mut emphasis = 0;
+        if self.contains(red) {
+            emphasis |= 0b001;
         }
-
-        if self.contains(MaskRegister::EMPHASISE_GREEN) {
-            colours.push(Colour::GREEN);
+        if self.contains(green) {
+            emphasis |= 0b010;
         }
-
         if self.contains(MaskRegister::EMPHASISE_BLUE) {
-            colours.push(Colour::BLUE);
+            emphasis |= 0b100;
         }
-
-        return colours;
+        emphasis
     }
 }
-
-enum Colour {
-    RED,
-    GREEN,
-    BLUE,
-}
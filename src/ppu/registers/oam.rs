@@ -15,10 +15,34 @@ impl Oam {
         self.addr = addr;
     }
 
+    pub fn addr(&self) -> u8 {
+        self.addr
+    }
+
     pub fn read_data(&self) -> u8 {
         self.data[self.addr as usize]
     }
 
+    pub fn data(&self) -> &[u8; 256] {
+        &self.data
+    }
+
+    /**
+     * Sets one byte, leaving OAMADDR alone.
+     */
+    pub fn poke(&mut self, index: u8, data: u8) {
+        self.data[index as usize] = data;
+    }
+
+    /**
+     * $4014 copies a whole page, starting at the current OAMADDR.
+     */
+    pub fn write_dma(&mut self, page: &[u8; 256]) {
+        for &byte in page {
+            self.write_data(byte);
+        }
+    }
+
     pub fn write_data(&mut self, data: u8) {
         self.data[self.addr as usize] = data;
         self.addr = self.addr.wrapping_add(1);
@@ -11,15 +11,43 @@ impl ScrollRegister {
     }
 
     pub fn write(&mut self, data: u8) {
+        // first write is X, second is Y
         if self.address_latch {
-            self.scroll_x = data
-        } else {
             self.scroll_y = data
+        } else {
+            self.scroll_x = data
         }
         self.address_latch = !self.address_latch;
     }
 
+    pub fn x(&self) -> u8 {
+        self.scroll_x
+    }
+
+    pub fn y(&self) -> u8 {
+        self.scroll_y
+    }
+
     pub fn reset_latch(&mut self) {
         self.address_latch = false;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_first_write_is_x() {
+        let mut scroll = ScrollRegister::new();
+        scroll.write(0x12);
+        scroll.write(0x34);
+        assert_eq!((scroll.x(), scroll.y()), (0x12, 0x34));
+
+        // reading PPUSTATUS starts the pair again
+        scroll.write(0x56);
+        scroll.reset_latch();
+        scroll.write(0x78);
+        assert_eq!((scroll.x(), scroll.y()), (0x78, 0x34));
+    }
+}
@@ -0,0 +1,215 @@
+use super::PPU;
+use crate::{
+    cdl::ChrAccess,
+    frame::{Frame, HEIGHT, WIDTH},
+};
+
+pub const ATTRIBUTE_TABLE_OFFSET: u16 = 0x3C0;
+pub const TILE_SIZE: usize = 16;
+/// tiles in the two pattern tables
+const PATTERN_TILES: usize = 0x2000 / TILE_SIZE;
+
+/**
+ * Draws the whole picture in one go from the current nametables, OAM and palettes.
+ * Mid-frame register changes are not seen, so split-screen effects show whatever
+ * the registers held when vblank started.
+ */
+impl PPU {
+    pub fn render(&self, frame: &mut Frame) {
+        frame.pixels.fill(self.palette_colour(0));
+        frame.emphasis = self.mask.emphasis(self.region);
+        // pixels where the background is not transparent, for sprite priority
+        let mut opaque = vec![false; WIDTH * HEIGHT];
+        let mut drawn = [false; PATTERN_TILES];
+
+        if self.mask.show_background() {
+            self.render_background(frame, &mut opaque, &mut drawn);
+        }
+        if self.mask.show_sprites() {
+            self.render_sprites(frame, &opaque, &mut drawn);
+        }
+        self.log_drawn_tiles(&drawn);
+    }
+
+    /**
+     * The four nametables form a 512x480 plane; scrolling picks the 256x240 window
+     * into it, wrapping around, and mirroring decides which of them are the same.
+     */
+    fn render_background(&self, frame: &mut Frame, opaque: &mut [bool], drawn: &mut [bool]) {
+        let bank = self.control.get_background_pattern_table_address();
+        let (origin_x, origin_y) = self.scroll_origin();
+
+        for y in 0..HEIGHT {
+            let world_y = (origin_y + y) % (2 * HEIGHT);
+            for x in 0..WIDTH {
+                if x < 8 && !self.mask.show_leftmost_background() {
+                    continue;
+                }
+                let world_x = (origin_x + x) % (2 * WIDTH);
+                let nametable =
+                    0x2000 + 0x400 * ((world_x / WIDTH) + 2 * (world_y / HEIGHT)) as u16;
+                let column = (world_x % WIDTH) / 8;
+                let row = (world_y % HEIGHT) / 8;
+
+                let tile_index = self.nametable_byte(nametable + (row * 32 + column) as u16);
+                let tile = self.tile(bank, tile_index as u16, drawn);
+                let value = tile_pixel(tile, world_x % 8, world_y % 8);
+                if value == 0 {
+                    continue;
+                }
+
+                let attribute = self.nametable_byte(
+                    nametable + ATTRIBUTE_TABLE_OFFSET + ((row / 4) * 8 + column / 4) as u16,
+                );
+                let shift = ((row % 4) / 2) * 4 + ((column % 4) / 2) * 2;
+                let palette = (attribute >> shift) & 0b11;
+                frame.set_pixel(x, y, self.palette_colour(palette * 4 + value));
+                opaque[y * WIDTH + x] = true;
+            }
+        }
+    }
+
+    /**
+     * Lower OAM entries win, so draw from the back of the table to the front.
+     */
+    fn render_sprites(&self, frame: &mut Frame, opaque: &[bool], drawn: &mut [bool]) {
+        let tall = self.control.get_sprite_size() == 16;
+        let oam = self.oam.data();
+        for sprite in oam.chunks(4).rev() {
+            let (top, index, attributes, left) = (
+                sprite[0] as usize + 1,
+                sprite[1],
+                sprite[2],
+                sprite[3] as usize,
+            );
+            let flip_x = attributes & 0b0100_0000 != 0;
+            let flip_y = attributes & 0b1000_0000 != 0;
+            let behind_background = attributes & 0b0010_0000 != 0;
+            let palette = 4 + (attributes & 0b11);
+            let height = if tall { 16 } else { 8 };
+
+            for row in 0..height {
+                let y = top + row;
+                if y >= HEIGHT {
+                    break;
+                }
+                let tile_row = if flip_y { height - 1 - row } else { row };
+                let tile = if tall {
+                    let bank = (index as u16 & 1) * 0x1000;
+                    self.tile(bank, (index & 0xFE) as u16 + (tile_row / 8) as u16, drawn)
+                } else {
+                    self.tile(
+                        self.control.get_sprite_pattern_table_address(),
+                        index as u16,
+                        drawn,
+                    )
+                };
+
+                for column in 0..8 {
+                    let x = left + column;
+                    if x >= WIDTH || (x < 8 && !self.mask.show_leftmost_sprites()) {
+                        continue;
+                    }
+                    let tile_column = if flip_x { 7 - column } else { column };
+                    let value = tile_pixel(tile, tile_column, tile_row % 8);
+                    if value == 0 || (behind_background && opaque[y * WIDTH + x]) {
+                        continue;
+                    }
+                    frame.set_pixel(x, y, self.palette_colour(palette * 4 + value));
+                }
+            }
+        }
+    }
+
+    /**
+     * The top left corner of the picture in the 512x480 nametable plane.
+     */
+    pub fn scroll_origin(&self) -> (usize, usize) {
+        let base = self.control.get_nametable_address() - 0x2000;
+        let origin_x = self.scroll.x() as usize + ((base / 0x400) & 1) as usize * WIDTH;
+        let origin_y = self.scroll.y() as usize + ((base / 0x400) >> 1) as usize * HEIGHT;
+        (origin_x, origin_y)
+    }
+
+    fn nametable_byte(&self, addr: u16) -> u8 {
+        self.vram[self.mirror_vram(addr) as usize]
+    }
+
+    /**
+     * Greyscale keeps only the luma of a colour, leaving the $x0 column.
+     */
+    fn palette_colour(&self, entry: u8) -> u8 {
+        let mask = if self.mask.is_greyscale() { 0x30 } else { 0x3F };
+        self.palette_table[entry as usize] & mask
+    }
+
+    /**
+     * The 16 bytes of a tile, which is marked in `drawn`. Boards with CHR RAM have no
+     * `chr_rom`, and read as blank.
+     */
+    fn tile(&self, bank: u16, index: u16, drawn: &mut [bool]) -> &[u8] {
+        let start = (bank + index * TILE_SIZE as u16) as usize;
+        let Some(tile) = self.chr_rom.get(start..start + TILE_SIZE) else {
+            return &[0; TILE_SIZE];
+        };
+        drawn[start / TILE_SIZE] = true;
+        tile
+    }
+
+    /**
+     * Tiles are logged once a frame rather than on every fetch, which would mean
+     * hundreds of thousands of logger calls.
+     */
+    fn log_drawn_tiles(&self, drawn: &[bool]) {
+        let Some(cdl) = &self.cdl else {
+            return;
+        };
+        let mut cdl = cdl.borrow_mut();
+        for (tile, _) in drawn.iter().enumerate().filter(|(_, &marked)| marked) {
+            for offset in tile * TILE_SIZE..(tile + 1) * TILE_SIZE {
+                cdl.log_chr(offset, ChrAccess::DRAWN);
+            }
+        }
+    }
+}
+
+/**
+ * 2 bit colour of a pixel: low plane in the first 8 bytes, high plane in the next 8.
+ */
+pub fn tile_pixel(tile: &[u8], x: usize, y: usize) -> u8 {
+    let lo = (tile[y] >> (7 - x)) & 1;
+    let hi = (tile[y + 8] >> (7 - x)) & 1;
+    hi << 1 | lo
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cpu::Mem, palette::SYSTEM_PALETTE, region::Region, rom::Mirroring};
+
+    #[test]
+    fn test_greyscale_and_emphasis() {
+        let mut ppu = PPU::new(vec![0; 0x2000], Mirroring::HORIZONTAL);
+        ppu.palette_table[0] = 0x16;
+        let mut frame = Frame::new();
+
+        // greyscale, emphasise red
+        ppu.mem_write(0x2001, 0b0010_0001);
+        ppu.render(&mut frame);
+        assert_eq!(frame.pixel(0, 0), 0x10);
+        assert_eq!(frame.emphasis, 0b001);
+        let (r, g, b) = SYSTEM_PALETTE[0x10];
+        let darker = |c: u8| (c as f32 * 0.746) as u8;
+        assert_eq!(frame.to_rgb(&SYSTEM_PALETTE)[..3], [r, darker(g), darker(b)]);
+
+        // the 2C07 swaps red and green
+        ppu.set_region(Region::Pal);
+        ppu.render(&mut frame);
+        assert_eq!(frame.emphasis, 0b010);
+
+        ppu.mem_write(0x2001, 0);
+        ppu.render(&mut frame);
+        assert_eq!(frame.pixel(0, 0), 0x16);
+        assert_eq!(frame.emphasis, 0);
+    }
+}
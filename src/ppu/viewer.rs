@@ -0,0 +1,328 @@
+use std::fmt;
+
+use super::{
+    render::{tile_pixel, ATTRIBUTE_TABLE_OFFSET, TILE_SIZE},
+    PPU,
+};
+use crate::{
+    frame::{HEIGHT, WIDTH},
+    palette::Rgb,
+    video::Image,
+};
+
+/// side of a swatch in the palette viewer
+const SWATCH: usize = 16;
+/// room for one 8x16 sprite plus a border in the OAM viewer
+const SPRITE_CELL: (usize, usize) = (10, 18);
+const SPRITE_BACKGROUND: Rgb = (0x30, 0x30, 0x30);
+
+/**
+ * The debug pictures of PPU state. None of them touch the PPU's registers or
+ * the code/data log.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Viewer {
+    Nametables,
+    PatternTables,
+    Palette,
+    Oam,
+}
+
+impl Viewer {
+    /**
+     * The size of the picture `render` draws.
+     */
+    pub fn size(&self) -> (usize, usize) {
+        match self {
+            Viewer::Nametables => (2 * WIDTH, 2 * HEIGHT),
+            Viewer::PatternTables => (256, 128),
+            Viewer::Palette => (16 * SWATCH, 2 * SWATCH),
+            Viewer::Oam => (8 * SPRITE_CELL.0, 8 * SPRITE_CELL.1),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Viewer::Nametables => "nametables",
+            Viewer::PatternTables => "patterns",
+            Viewer::Palette => "palette",
+            Viewer::Oam => "oam",
+        }
+    }
+
+    /**
+     * `pattern_palette` (0-7) colours the pattern tables: 0-3 are the background
+     * palettes, 4-7 the sprite ones.
+     */
+    pub fn render(&self, ppu: &PPU, pattern_palette: u8, palette: &[Rgb]) -> Image {
+        match self {
+            Viewer::Nametables => ppu.nametables_view(palette),
+            Viewer::PatternTables => ppu.pattern_tables_view(pattern_palette, palette),
+            Viewer::Palette => ppu.palette_view(palette),
+            Viewer::Oam => ppu.oam_view(palette),
+        }
+    }
+}
+
+/**
+ * One OAM entry, decoded.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sprite {
+    pub number: usize,
+    pub x: u8,
+    /// the line above the sprite's first one, as stored
+    pub y: u8,
+    pub tile: u8,
+    /// 4-7
+    pub palette: u8,
+    pub behind_background: bool,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+impl fmt::Display for Sprite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:02}: x {:3} y {:3} tile ${:02X} palette {} {}{}{}",
+            self.number,
+            self.x,
+            self.y,
+            self.tile,
+            self.palette,
+            if self.behind_background {
+                "back"
+            } else {
+                "front"
+            },
+            if self.flip_x { " flip-x" } else { "" },
+            if self.flip_y { " flip-y" } else { "" },
+        )
+    }
+}
+
+impl PPU {
+    /**
+     * All four nametables as the 512x480 plane the background scrolls over, with
+     * mirrored ones repeated, and the visible area outlined in inverted colours.
+     */
+    pub fn nametables_view(&self, palette: &[Rgb]) -> Image {
+        let (width, height) = Viewer::Nametables.size();
+        let mut image = Image::new(width, height);
+        let bank = self.control.get_background_pattern_table_address();
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let nametable = 0x2000 + 0x400 * ((x / WIDTH) + 2 * (y / HEIGHT)) as u16;
+                let (column, row) = ((x % WIDTH) / 8, (y % HEIGHT) / 8);
+                let tile = self.view_byte(nametable + (row * 32 + column) as u16);
+                let value = tile_pixel(&self.view_tile(bank, tile as u16), x % 8, y % 8);
+                let attribute = self.view_byte(
+                    nametable + ATTRIBUTE_TABLE_OFFSET + ((row / 4) * 8 + column / 4) as u16,
+                );
+                let shift = ((row % 4) / 2) * 4 + ((column % 4) / 2) * 2;
+                let entry = ((attribute >> shift) & 0b11) * 4 + value;
+                image.set_pixel(x, y, self.view_colour(entry, palette));
+            }
+        }
+
+        let (origin_x, origin_y) = self.scroll_origin();
+        let mut invert = |x: usize, y: usize| {
+            let (x, y) = (x % image.width, y % image.height);
+            let (r, g, b) = image.pixel(x, y);
+            image.set_pixel(x, y, (!r, !g, !b));
+        };
+        for x in origin_x..origin_x + WIDTH {
+            invert(x, origin_y);
+            invert(x, origin_y + HEIGHT - 1);
+        }
+        for y in origin_y + 1..origin_y + HEIGHT - 1 {
+            invert(origin_x, y);
+            invert(origin_x + WIDTH - 1, y);
+        }
+        image
+    }
+
+    /**
+     * Both pattern tables side by side, 16x16 tiles each, in palette `number`.
+     */
+    pub fn pattern_tables_view(&self, number: u8, palette: &[Rgb]) -> Image {
+        let (width, height) = Viewer::PatternTables.size();
+        let mut image = Image::new(width, height);
+        for table in 0..2 {
+            for index in 0..256 {
+                let tile = self.view_tile(table * 0x1000, index);
+                let (left, top) = (
+                    table as usize * 128 + (index as usize % 16) * 8,
+                    (index as usize / 16) * 8,
+                );
+                for y in 0..8 {
+                    for x in 0..8 {
+                        let value = tile_pixel(&tile, x, y);
+                        let colour = self.view_colour((number & 7) * 4 + value, palette);
+                        image.set_pixel(left + x, top + y, colour);
+                    }
+                }
+            }
+        }
+        image
+    }
+
+    /**
+     * The 32 bytes of palette RAM, background palettes on the top row.
+     */
+    pub fn palette_view(&self, palette: &[Rgb]) -> Image {
+        let (width, height) = Viewer::Palette.size();
+        let mut image = Image::new(width, height);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let entry = self.palette_table[(y / SWATCH) * 16 + x / SWATCH] & 0x3F;
+                image.set_pixel(x, y, palette[entry as usize % palette.len()]);
+            }
+        }
+        image
+    }
+
+    /**
+     * The 64 sprites in an 8x8 grid, unflipped and wherever they are on screen.
+     */
+    pub fn oam_view(&self, palette: &[Rgb]) -> Image {
+        let (cell_width, cell_height) = SPRITE_CELL;
+        let (width, height) = Viewer::Oam.size();
+        let mut image = Image::new(width, height);
+        image.rgb.chunks_mut(3).for_each(|pixel| {
+            pixel.copy_from_slice(&[
+                SPRITE_BACKGROUND.0,
+                SPRITE_BACKGROUND.1,
+                SPRITE_BACKGROUND.2,
+            ])
+        });
+        let tall = self.control.get_sprite_size() == 16;
+        for sprite in self.sprites() {
+            let left = (sprite.number % 8) * cell_width + 1;
+            let top = (sprite.number / 8) * cell_height + 1;
+            let height = if tall { 16 } else { 8 };
+            for row in 0..height {
+                let tile = if tall {
+                    let bank = (sprite.tile as u16 & 1) * 0x1000;
+                    self.view_tile(bank, (sprite.tile & 0xFE) as u16 + row as u16 / 8)
+                } else {
+                    let bank = self.control.get_sprite_pattern_table_address();
+                    self.view_tile(bank, sprite.tile as u16)
+                };
+                for column in 0..8 {
+                    let value = tile_pixel(&tile, column, row % 8);
+                    if value != 0 {
+                        let colour = self.view_colour(sprite.palette * 4 + value, palette);
+                        image.set_pixel(left + column, top + row, colour);
+                    }
+                }
+            }
+        }
+        image
+    }
+
+    pub fn sprites(&self) -> Vec<Sprite> {
+        self.oam
+            .data()
+            .chunks(4)
+            .enumerate()
+            .map(|(number, sprite)| Sprite {
+                number,
+                x: sprite[3],
+                y: sprite[0],
+                tile: sprite[1],
+                palette: 4 + (sprite[2] & 0b11),
+                behind_background: sprite[2] & 0b0010_0000 != 0,
+                flip_x: sprite[2] & 0b0100_0000 != 0,
+                flip_y: sprite[2] & 0b1000_0000 != 0,
+            })
+            .collect()
+    }
+
+    fn view_byte(&self, addr: u16) -> u8 {
+        self.vram[self.mirror_vram(addr) as usize]
+    }
+
+    /**
+     * Like `tile`, without logging the tile as drawn.
+     */
+    fn view_tile(&self, bank: u16, index: u16) -> [u8; TILE_SIZE] {
+        let start = (bank + index * TILE_SIZE as u16) as usize;
+        match self.chr_rom.get(start..start + TILE_SIZE) {
+            Some(tile) => tile.try_into().unwrap(),
+            None => [0; TILE_SIZE],
+        }
+    }
+
+    /**
+     * Colour 0 of every palette is the backdrop.
+     */
+    fn view_colour(&self, entry: u8, palette: &[Rgb]) -> Rgb {
+        let entry = if entry & 0b11 == 0 { 0 } else { entry };
+        let index = self.palette_table[entry as usize] & 0x3F;
+        palette[index as usize % palette.len()]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cpu::Mem, palette::SYSTEM_PALETTE, rom::Mirroring};
+
+    fn test_ppu() -> PPU {
+        let mut chr = vec![0; 0x2000];
+        // tile 1 is a solid block of colour 3
+        chr[16..32].fill(0xFF);
+        let mut ppu = PPU::new(chr, Mirroring::VERTICAL);
+        ppu.palette_table[0] = 0x0F;
+        ppu.palette_table[3] = 0x16;
+        ppu.palette_table[4 * 4 + 3] = 0x2A;
+        ppu
+    }
+
+    #[test]
+    fn test_nametables_follow_mirroring() {
+        let mut ppu = test_ppu();
+        // first tile of $2400, which vertical mirroring repeats at $2C00
+        ppu.vram[0x400] = 1;
+        ppu.mem_write(0x2005, 8);
+        ppu.mem_write(0x2005, 0);
+        let image = ppu.nametables_view(&SYSTEM_PALETTE);
+        assert_eq!((image.width, image.height), (512, 480));
+        assert_eq!(image.pixel(256, 1), SYSTEM_PALETTE[0x16]);
+        assert_eq!(image.pixel(256, 241), SYSTEM_PALETTE[0x16]);
+        assert_eq!(image.pixel(0, 240), SYSTEM_PALETTE[0x0F]);
+        // the viewport's outline starts 8 pixels in
+        let (r, g, b) = SYSTEM_PALETTE[0x0F];
+        assert_eq!(image.pixel(8, 1), (!r, !g, !b));
+        assert_eq!(image.pixel(9, 1), SYSTEM_PALETTE[0x0F]);
+    }
+
+    #[test]
+    fn test_patterns_palette_and_sprites() {
+        let mut ppu = test_ppu();
+        let patterns = ppu.pattern_tables_view(4, &SYSTEM_PALETTE);
+        assert_eq!(patterns.pixel(8, 0), SYSTEM_PALETTE[0x2A]);
+        assert_eq!(patterns.pixel(0, 0), SYSTEM_PALETTE[0x0F]);
+        assert_eq!(
+            ppu.palette_view(&SYSTEM_PALETTE).pixel(3 * SWATCH, 0),
+            SYSTEM_PALETTE[0x16]
+        );
+
+        // sprite 2: tile 1, sprite palette 4, flipped horizontally
+        ppu.write_oam_dma(&{
+            let mut oam = [0; 256];
+            oam[8..12].copy_from_slice(&[20, 1, 0b0100_0000, 30]);
+            oam
+        });
+        let sprite = &ppu.sprites()[2];
+        assert_eq!(
+            sprite.to_string(),
+            "02: x  30 y  20 tile $01 palette 4 front flip-x"
+        );
+        let oam = ppu.oam_view(&SYSTEM_PALETTE);
+        assert_eq!(oam.pixel(2 * SPRITE_CELL.0 + 1, 1), SYSTEM_PALETTE[0x2A]);
+        assert_eq!(oam.pixel(0, 0), SPRITE_BACKGROUND);
+    }
+}
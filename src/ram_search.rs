@@ -0,0 +1,283 @@
+use std::ops::RangeInclusive;
+
+use crate::{
+    bus::{Bus, PRG_RAM, PRG_RAM_END},
+    cpu::Peek,
+};
+
+/// internal RAM without its mirrors, and cartridge RAM
+pub const SEARCH_RANGES: [RangeInclusive<u16>; 2] = [0x0000..=0x07FF, PRG_RAM..=PRG_RAM_END];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+}
+
+impl Comparison {
+    fn holds(&self, a: i64, b: i64) -> bool {
+        match self {
+            Comparison::Equal => a == b,
+            Comparison::NotEqual => a != b,
+            Comparison::Greater => a > b,
+            Comparison::Less => a < b,
+        }
+    }
+}
+
+/**
+ * Which candidates `RamSearch::filter` keeps. A comparison without a value is
+ * against what the candidate held when the previous filter ran.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Compare(Comparison, Option<i64>),
+    /// went up by this much (down if negative), wrapping like the game would
+    ChangedBy(i64),
+}
+
+const COMPARISONS: [(&str, Comparison); 5] = [
+    ("!=", Comparison::NotEqual),
+    ("==", Comparison::Equal),
+    ("=", Comparison::Equal),
+    (">", Comparison::Greater),
+    ("<", Comparison::Less),
+];
+
+impl Filter {
+    /**
+     * `= 3`, `!=`, `> $10`, `<`, `+1`, `-2`, `changed` or `unchanged`.
+     */
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        match text {
+            "changed" => return Ok(Filter::Compare(Comparison::NotEqual, None)),
+            "unchanged" => return Ok(Filter::Compare(Comparison::Equal, None)),
+            _ => {}
+        }
+        for (symbol, comparison) in COMPARISONS {
+            if let Some(value) = text.strip_prefix(symbol) {
+                let value = match value.trim() {
+                    "" => None,
+                    value => Some(parse_value(value)?),
+                };
+                return Ok(Filter::Compare(comparison, value));
+            }
+        }
+        match text.strip_prefix('+') {
+            Some(by) => Ok(Filter::ChangedBy(parse_value(by)?)),
+            None if text.starts_with('-') => Ok(Filter::ChangedBy(parse_value(text)?)),
+            None => Err(format!(
+                "Unknown filter {text}: expected = != > < and an optional value, +N, -N, \
+                 changed or unchanged"
+            )),
+        }
+    }
+}
+
+/**
+ * Decimal, or hex after `$`. Either can be negative.
+ */
+pub fn parse_value(text: &str) -> Result<i64, String> {
+    let (negative, digits) = match text.trim().strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.trim()),
+    };
+    let value = match digits.strip_prefix('$') {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    }
+    .map_err(|_| format!("Invalid value {text}"))?;
+    Ok(if negative { -value } else { value })
+}
+
+/**
+ * The classic cheat search: start with every RAM address, then narrow the
+ * candidates down by how their values change from one filter to the next.
+ */
+pub struct RamSearch {
+    /// 16 bit values are little endian, starting at the candidate's address
+    word: bool,
+    signed: bool,
+    /// addresses still in the running, with their raw values as of the last filter
+    candidates: Vec<(u16, u16)>,
+}
+
+impl RamSearch {
+    pub fn start(bus: &Bus, word: bool, signed: bool) -> Self {
+        let mut search = RamSearch {
+            word,
+            signed,
+            candidates: vec![],
+        };
+        search.candidates = SEARCH_RANGES
+            .iter()
+            .flat_map(|range| {
+                // a word has to fit in the range
+                let end = *range.end() - word as u16;
+                *range.start()..=end
+            })
+            .map(|addr| (addr, search.raw(bus, addr)))
+            .collect();
+        search
+    }
+
+    pub fn is_word(&self) -> bool {
+        self.word
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    /**
+     * E.g. "8 bit unsigned".
+     */
+    pub fn view(&self) -> String {
+        format!(
+            "{} bit {}",
+            if self.word { 16 } else { 8 },
+            if self.signed { "signed" } else { "unsigned" }
+        )
+    }
+
+    pub fn filter(&mut self, bus: &Bus, filter: Filter) {
+        let candidates = std::mem::take(&mut self.candidates);
+        self.candidates = candidates
+            .into_iter()
+            .filter_map(|(addr, previous)| {
+                let current = self.raw(bus, addr);
+                let keep = match filter {
+                    Filter::Compare(comparison, value) => {
+                        comparison.holds(self.value(current), value.unwrap_or(self.value(previous)))
+                    }
+                    Filter::ChangedBy(by) => {
+                        previous.wrapping_add(by as u16) & self.mask() == current
+                    }
+                };
+                keep.then_some((addr, current))
+            })
+            .collect();
+    }
+
+    /**
+     * The candidates with their values now and as of the last filter, as the view
+     * reads them.
+     */
+    pub fn results(&self, bus: &Bus) -> Vec<(u16, i64, i64)> {
+        self.candidates
+            .iter()
+            .map(|&(addr, previous)| {
+                let current = self.raw(bus, addr);
+                (addr, self.value(current), self.value(previous))
+            })
+            .collect()
+    }
+
+    /**
+     * `value` as the bytes to store at a candidate, low byte first.
+     */
+    pub fn bytes(&self, value: i64) -> Result<Vec<u8>, String> {
+        let (min, max) = match (self.word, self.signed) {
+            (false, false) => (0, 0xFF),
+            (false, true) => (-0x80, 0x7F),
+            (true, false) => (0, 0xFFFF),
+            (true, true) => (-0x8000, 0x7FFF),
+        };
+        if value < min || value > max {
+            return Err(format!("{value} does not fit in {}", self.view()));
+        }
+        let [lo, hi] = (value as u16).to_le_bytes();
+        Ok(if self.word { vec![lo, hi] } else { vec![lo] })
+    }
+
+    fn mask(&self) -> u16 {
+        if self.word {
+            0xFFFF
+        } else {
+            0xFF
+        }
+    }
+
+    fn raw(&self, bus: &Bus, addr: u16) -> u16 {
+        if self.word {
+            bus.peek_u16(addr)
+        } else {
+            bus.peek(addr) as u16
+        }
+    }
+
+    fn value(&self, raw: u16) -> i64 {
+        match (self.word, self.signed) {
+            (false, false) => raw as u8 as i64,
+            (false, true) => raw as u8 as i8 as i64,
+            (true, false) => raw as i64,
+            (true, true) => raw as i16 as i64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cpu::Mem, rom::test::test_rom};
+
+    #[test]
+    fn test_narrowing_down() {
+        let mut bus = Bus::new(test_rom());
+        bus.mem_write(0x0042, 3);
+        bus.mem_write(0x6010, 3);
+        let mut search = RamSearch::start(&bus, false, false);
+        assert_eq!(search.len(), 0x800 + 0x2000);
+        search.filter(&bus, Filter::parse("= 3").unwrap());
+        assert_eq!(search.len(), 2);
+
+        // a life is lost, but only at $42
+        bus.mem_write(0x0042, 2);
+        search.filter(&bus, Filter::parse("-1").unwrap());
+        assert_eq!(search.results(&bus), vec![(0x0042, 2, 2)]);
+        bus.mem_write(0x0042, 0);
+        search.filter(&bus, Filter::parse("<").unwrap());
+        assert_eq!(search.len(), 1);
+
+        // $FFFF down to $FFFE, signed 16 bit: -1 then -2
+        bus.mem_write_u16(0x0100, 0xFFFF);
+        let mut search = RamSearch::start(&bus, true, true);
+        search.filter(&bus, Filter::parse("= -1").unwrap());
+        bus.mem_write(0x0100, 0xFE);
+        search.filter(&bus, Filter::parse("changed").unwrap());
+        assert_eq!(search.results(&bus), vec![(0x0100, -2, -2)]);
+        assert_eq!(search.bytes(-2), Ok(vec![0xFE, 0xFF]));
+        assert!(search.bytes(0x8000).is_err());
+
+        assert_eq!(
+            Filter::parse("> $10"),
+            Ok(Filter::Compare(Comparison::Greater, Some(16)))
+        );
+        assert!(Filter::parse("~3").is_err());
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut bus = Bus::new(test_rom());
+        bus.freeze(0x0042, 9).unwrap();
+        bus.freeze(0x7FFF, 1).unwrap();
+        assert!(bus.freeze(0x8000, 1).is_err());
+        bus.mem_write(0x0042, 0);
+        bus.mem_write(0x7FFF, 0);
+        while !bus.take_frame() {
+            bus.tick(1);
+        }
+        assert_eq!((bus.mem_read(0x0042), bus.mem_read(0x7FFF)), (9, 1));
+
+        bus.unfreeze(0x0042);
+        bus.mem_write(0x0042, 0);
+        while !bus.take_frame() {
+            bus.tick(1);
+        }
+        assert_eq!(bus.mem_read(0x0042), 0);
+        assert_eq!(bus.frozen(), &[(0x7FFF, 1)]);
+    }
+}
@@ -0,0 +1,298 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{frame::Frame, palette::Rgb};
+
+const WAV_HEADER_SIZE: u32 = 44;
+
+/**
+ * Records every emulated frame and its samples, including frames skipped while
+ * fast-forwarding, so picture and sound stay in step.
+ *
+ * The output's extension picks the format: `.y4m` (YUV 4:4:4) or `.rgb` (raw RGB24)
+ * with the sound in a `.wav` next to it. Anything else (`.mp4`, `.mkv`, ...) is
+ * recorded to Y4M and WAV first and handed to ffmpeg when recording stops.
+ */
+pub struct Recorder {
+    output: PathBuf,
+    video_path: PathBuf,
+    video: BufWriter<File>,
+    y4m: bool,
+    started: bool,
+    audio: WavWriter,
+    palette: Vec<Rgb>,
+    /// frames per second as a fraction, for the Y4M header
+    frame_rate: (u32, u32),
+    /// mux into `output` with ffmpeg when done
+    mux: bool,
+}
+
+impl Recorder {
+    pub fn create(
+        output: &Path,
+        palette: &[Rgb],
+        frame_rate: (u32, u32),
+        sample_rate: u32,
+    ) -> Result<Self, String> {
+        let extension = output
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        let (video_path, audio_path, y4m, mux) = match extension.as_deref() {
+            Some("y4m") => (
+                output.to_path_buf(),
+                output.with_extension("wav"),
+                true,
+                false,
+            ),
+            Some("rgb") => (
+                output.to_path_buf(),
+                output.with_extension("wav"),
+                false,
+                false,
+            ),
+            _ => {
+                let found = Command::new("ffmpeg")
+                    .arg("-version")
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status()
+                    .is_ok();
+                if !found {
+                    return Err(format!(
+                        "Recording to {} needs ffmpeg, which was not found. Record to .y4m or .rgb instead",
+                        output.display()
+                    ));
+                }
+                let stem = output.with_extension("");
+                let stem = stem.display();
+                (
+                    PathBuf::from(format!("{stem}.video.y4m")),
+                    PathBuf::from(format!("{stem}.audio.wav")),
+                    true,
+                    true,
+                )
+            }
+        };
+        let video = File::create(&video_path)
+            .map_err(|e| format!("Cannot create {}: {e}", video_path.display()))?;
+        Ok(Recorder {
+            output: output.to_path_buf(),
+            video_path,
+            video: BufWriter::new(video),
+            y4m,
+            started: false,
+            audio: WavWriter::create(&audio_path, sample_rate)?,
+            palette: palette.to_vec(),
+            frame_rate,
+            mux,
+        })
+    }
+
+    pub fn record(&mut self, frame: &Frame, samples: &[f32]) -> Result<(), String> {
+        let rgb = frame.to_rgb(&self.palette);
+        let written = if self.y4m {
+            self.write_y4m(frame, &rgb)
+        } else {
+            self.video.write_all(&rgb)
+        };
+        written.map_err(|e| format!("Cannot write {}: {e}", self.video_path.display()))?;
+        self.audio.write(samples)
+    }
+
+    fn write_y4m(&mut self, frame: &Frame, rgb: &[u8]) -> std::io::Result<()> {
+        if !self.started {
+            let (numerator, denominator) = self.frame_rate;
+            writeln!(
+                self.video,
+                "YUV4MPEG2 W{} H{} F{numerator}:{denominator} Ip A1:1 C444",
+                frame.width, frame.height
+            )?;
+            self.started = true;
+        }
+        let pixels = frame.width * frame.height;
+        let mut planes = vec![0u8; pixels * 3];
+        for (i, pixel) in rgb.chunks(3).enumerate() {
+            let (y, u, v) = to_yuv(pixel[0], pixel[1], pixel[2]);
+            planes[i] = y;
+            planes[pixels + i] = u;
+            planes[2 * pixels + i] = v;
+        }
+        self.video.write_all(b"FRAME\n")?;
+        self.video.write_all(&planes)
+    }
+
+    /**
+     * Closes the files, muxing them with ffmpeg if needed, and returns the recording.
+     */
+    pub fn finish(mut self) -> Result<PathBuf, String> {
+        self.video
+            .flush()
+            .map_err(|e| format!("Cannot write {}: {e}", self.video_path.display()))?;
+        let audio_path = self.audio.finish()?;
+        if !self.mux {
+            return Ok(self.output);
+        }
+
+        let status = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error", "-i"])
+            .arg(&self.video_path)
+            .arg("-i")
+            .arg(&audio_path)
+            .args(["-pix_fmt", "yuv420p"])
+            .arg(&self.output)
+            .status()
+            .map_err(|e| format!("Cannot run ffmpeg: {e}"))?;
+        if !status.success() {
+            return Err(format!(
+                "ffmpeg failed ({status}), the recording is in {} and {}",
+                self.video_path.display(),
+                audio_path.display()
+            ));
+        }
+        let _ = std::fs::remove_file(&self.video_path);
+        let _ = std::fs::remove_file(&audio_path);
+        Ok(self.output)
+    }
+}
+
+/**
+ * Where the record hotkey saves: `<dir>/<name>-001.<format>` and up.
+ */
+pub struct Recordings {
+    pub dir: PathBuf,
+    pub name: String,
+    pub format: String,
+    pub palette: Vec<Rgb>,
+}
+
+impl Recordings {
+    pub fn start(&self, frame_rate: (u32, u32), sample_rate: u32) -> Result<Recorder, String> {
+        if !self.dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(&self.dir)
+                .map_err(|e| format!("Cannot create {}: {e}", self.dir.display()))?;
+        }
+        let path = (1..)
+            .map(|n| {
+                self.dir
+                    .join(format!("{}-{n:03}.{}", self.name, self.format))
+            })
+            .find(|path| !path.exists())
+            .unwrap();
+        Recorder::create(&path, &self.palette, frame_rate, sample_rate)
+    }
+}
+
+/**
+ * BT.601 limited range, as players expect from Y4M.
+ */
+fn to_yuv(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+    (y as u8, u as u8, v as u8)
+}
+
+/**
+ * 16 bit mono PCM. The sizes in the header are filled in by `finish`.
+ */
+struct WavWriter {
+    path: PathBuf,
+    file: BufWriter<File>,
+    data_size: u32,
+}
+
+impl WavWriter {
+    fn create(path: &Path, sample_rate: u32) -> Result<Self, String> {
+        let file =
+            File::create(path).map_err(|e| format!("Cannot create {}: {e}", path.display()))?;
+        let mut wav = WavWriter {
+            path: path.to_path_buf(),
+            file: BufWriter::new(file),
+            data_size: 0,
+        };
+        let mut header = vec![];
+        header.extend(b"RIFF");
+        header.extend(0u32.to_le_bytes());
+        header.extend(b"WAVEfmt ");
+        header.extend(16u32.to_le_bytes());
+        // PCM, 1 channel
+        header.extend(1u16.to_le_bytes());
+        header.extend(1u16.to_le_bytes());
+        header.extend(sample_rate.to_le_bytes());
+        header.extend((sample_rate * 2).to_le_bytes());
+        // 2 bytes per frame, 16 bits per sample
+        header.extend(2u16.to_le_bytes());
+        header.extend(16u16.to_le_bytes());
+        header.extend(b"data");
+        header.extend(0u32.to_le_bytes());
+        wav.file.write_all(&header).map_err(|e| wav.error(e))?;
+        Ok(wav)
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        let mut bytes = Vec::with_capacity(samples.len() * 2);
+        for sample in samples {
+            bytes.extend(((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes());
+        }
+        self.data_size += bytes.len() as u32;
+        self.file.write_all(&bytes).map_err(|e| self.error(e))
+    }
+
+    fn finish(mut self) -> Result<PathBuf, String> {
+        let sizes = [
+            (4, WAV_HEADER_SIZE - 8 + self.data_size),
+            (40, self.data_size),
+        ];
+        for (offset, size) in sizes {
+            self.file
+                .seek(SeekFrom::Start(offset))
+                .and_then(|_| self.file.write_all(&size.to_le_bytes()))
+                .map_err(|e| self.error(e))?;
+        }
+        self.file.flush().map_err(|e| self.error(e))?;
+        Ok(self.path)
+    }
+
+    fn error(&self, e: std::io::Error) -> String {
+        format!("Cannot write {}: {e}", self.path.display())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_y4m_and_wav() {
+        let dir = std::env::temp_dir().join(format!("nes-recorder-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("clip.y4m");
+        let palette = [(0, 0, 0), (255, 255, 255)];
+        let mut recorder = Recorder::create(&output, &palette, (60, 1), 8000).unwrap();
+        let mut frame = Frame::with_size(2, 1);
+        frame.set_pixel(1, 0, 1);
+        recorder.record(&frame, &[0.0, 1.0, -1.0]).unwrap();
+        recorder.record(&frame, &[0.5]).unwrap();
+        assert_eq!(recorder.finish().unwrap(), output);
+
+        let video = std::fs::read(&output).unwrap();
+        let header = b"YUV4MPEG2 W2 H1 F60:1 Ip A1:1 C444\n";
+        assert_eq!(&video[..header.len()], header);
+        let frame = &video[header.len()..header.len() + 12];
+        // black and white in limited range, no colour
+        assert_eq!(frame, b"FRAME\n\x10\xEB\x80\x80\x80\x80");
+        assert_eq!(video.len(), header.len() + 2 * 12);
+
+        let wav = std::fs::read(dir.join("clip.wav")).unwrap();
+        assert_eq!(wav.len(), 44 + 8);
+        assert_eq!(&wav[4..8], &(36u32 + 8).to_le_bytes());
+        assert_eq!(&wav[40..44], &8u32.to_le_bytes());
+        assert_eq!(&wav[46..48], &i16::MAX.to_le_bytes());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
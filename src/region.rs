@@ -0,0 +1,92 @@
+/**
+ * Timing differences between the NTSC (2C02) and PAL (2C07) consoles, and the Dendy
+ * famiclone, which has PAL's 312 lines but NTSC's 3 dots per CPU cycle.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Region {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "ntsc" => Ok(Region::Ntsc),
+            "pal" => Ok(Region::Pal),
+            "dendy" => Ok(Region::Dendy),
+            _ => Err(format!(
+                "Unknown region {name}, expected ntsc, pal or dendy"
+            )),
+        }
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            Region::Pal => 50.007,
+            Region::Dendy => 50.007,
+        }
+    }
+
+    /**
+     * `frame_rate` as an exact fraction: the CPU clock over the cycles in a frame.
+     */
+    pub fn frame_rate_ratio(&self) -> (u32, u32) {
+        match self {
+            Region::Ntsc => (39_375_000, 655_171),
+            Region::Pal => (3_325_214, 66_495),
+            Region::Dendy => (136_419, 2_728),
+        }
+    }
+
+    pub fn cpu_clock_hz(&self) -> f64 {
+        match self {
+            Region::Ntsc => 1_789_773.0,
+            Region::Pal => 1_662_607.0,
+            Region::Dendy => 1_773_447.0,
+        }
+    }
+
+    pub fn scanlines_per_frame(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /**
+     * PPU dots per CPU cycle as a fraction: 3 on NTSC and Dendy, 3.2 on PAL.
+     */
+    pub fn dots_per_cycle(&self) -> (usize, usize) {
+        match self {
+            Region::Ntsc | Region::Dendy => (3, 1),
+            Region::Pal => (16, 5),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Region::parse("NTSC"), Ok(Region::Ntsc));
+        assert_eq!(Region::parse("pal"), Ok(Region::Pal));
+        assert_eq!(Region::parse("Dendy"), Ok(Region::Dendy));
+        assert!(Region::parse("secam").is_err());
+    }
+
+    #[test]
+    fn test_frame_rates_match_the_clocks() {
+        for region in [Region::Ntsc, Region::Pal, Region::Dendy] {
+            let (dots, cycles) = region.dots_per_cycle();
+            let dots_per_frame = region.scanlines_per_frame() as f64 * 341.0;
+            let rate = region.cpu_clock_hz() * dots as f64 / cycles as f64 / dots_per_frame;
+            let (num, den) = region.frame_rate_ratio();
+            assert!((rate - region.frame_rate()).abs() < 0.01, "{region:?}");
+            assert!((rate - num as f64 / den as f64).abs() < 0.01, "{region:?}");
+        }
+    }
+}
@@ -1,14 +1,9 @@
-use std::{
-    fs::File,
-    io::{Cursor, Write},
-};
-
-use crate::bus::ROM_START;
+use crate::assembler::assemble_file;
 
 const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
 pub const PRG_ROM_BANK_SIZE: usize = 16 * 1024;
 pub const CHR_ROM_BANK_SIZE: usize = 8 * 1024;
-const HEADER_SIZE: usize = 16;
+pub const HEADER_SIZE: usize = 16;
 const TRAINER_SIZE: usize = 16;
 const NES_IDENTIFIER_SIZE: usize = 4;
 const NUM_PRG_ROM_BANK_POS: usize = 4;
@@ -31,6 +26,12 @@ pub enum Mirroring {
 
 impl Rom {
     pub fn new(rom: &Vec<u8>) -> Result<Self, String> {
+        if rom.len() < HEADER_SIZE {
+            return Err(format!(
+                "Truncated .NES file: {} bytes is shorter than the {HEADER_SIZE} byte header",
+                rom.len()
+            ));
+        }
         if &rom[0..NES_IDENTIFIER_SIZE] != NES_TAG {
             return Err("Not a valid .NES file!".to_string());
         }
@@ -52,8 +53,15 @@ impl Rom {
         let has_trainer = rom[CONTROL_BYTE1_POS] & 0b100 == 1;
         let prg_rom_start = HEADER_SIZE + if has_trainer { TRAINER_SIZE } else { 0 };
         let chr_rom_start = prg_rom_start + prg_rom_size;
-        let prg_rom = rom[prg_rom_start..chr_rom_start].to_vec();
-        let chr_rom = rom[chr_rom_start..chr_rom_start + chr_rom_size].to_vec();
+        let section = |name: &str, start: usize, size: usize| {
+            rom.get(start..start + size).map(<[u8]>::to_vec).ok_or(format!(
+                "Truncated .NES file: the header promises {size} bytes of {name} ROM from \
+                 offset {start}, but the file is {} bytes",
+                rom.len()
+            ))
+        };
+        let prg_rom = section("PRG", prg_rom_start, prg_rom_size)?;
+        let chr_rom = section("CHR", chr_rom_start, chr_rom_size)?;
 
         Ok(Rom {
             chr_rom,
@@ -64,71 +72,38 @@ impl Rom {
     }
 }
 
-pub fn insert_new_cartridge(path_to_game: &str) -> Result<Vec<u8>, String> {
-    match std::fs::read(format!("{path_to_game}.nes")) {
-        Ok(game_bytes) => return Ok(game_bytes),
-        Err(_) => return create_cartridge(path_to_game),
+/**
+ * Reads an iNES image whatever its extension. `.asm` sources are assembled first.
+ */
+pub fn load_rom(path: &str) -> Result<Vec<u8>, String> {
+    if path.ends_with(".asm") {
+        return assemble_file(path)?.to_ines();
     }
+    std::fs::read(path).map_err(|e| format!("Cannot read {path}: {e}"))
 }
 
-fn create_cartridge(path_to_game: &str) -> Result<Vec<u8>, String> {
-    println!("{path_to_game}.nes not found, creating ROM...");
-    let mut buffer = Cursor::new(Vec::new());
-
-    let header = vec![
-        0x4E, 0x45, 0x53, 0x1A, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-        0x00, 0x00,
-    ];
-    let pre = [0; 0x600];
-    buffer
-        .write_all(&header)
-        .map_err(|e| format!("Cannot write header: {e}"))?;
-    buffer
-        .write_all(&pre)
-        .map_err(|e| format!("Cannot write pre-padding: {e}"))?;
-    let raw_bytes = std::fs::read(path_to_game)
-        .map_err(|e| format!("Raw file of {path_to_game} not found: {e}"))?;
-    let hex_string = String::from_utf8_lossy(&raw_bytes);
-    let cleaned_hexu8s = hex_string
-        .trim()
-        .replace("\r\n", "");
-    let bytes: Vec<u8> = cleaned_hexu8s
-        .split(',')
-        .filter_map(|s| {
-            let trimmed = s.trim(); // Remove whitespace
-            if !trimmed.is_empty() {
-                u8::from_str_radix(trimmed.trim_start_matches("0x"), 16).ok()
-            } else {
-                None
-            }
-        })
-        .collect();
-
-    buffer
-        .write_all(&bytes)
-        .map_err(|e| format!("Cannot write raw bytes: {e}"))?;
-
-    // Calculate the current position and fill the remaining space with zeros
-    let pos = 0x600 + bytes.len();
-    let filler_size = ((0xFFFC - ROM_START) as usize).saturating_sub(pos);
-    buffer
-        .write_all(&vec![0; filler_size.into()])
-        .map_err(|e| format!("Cannot write filler: {e}"))?;
-
-    // Write the final bytes
-    buffer
-        .write_all(&[0x0, 0x86, 0, 0])
-        .map_err(|e| format!("Cannot write final bytes: {e}"))?;
-
-    let bytes = buffer.into_inner();
-    let mut file = File::create(format!("{path_to_game}.nes"))
-        .map_err(|e| format!("Cannot create ROM file: {e}"))?;
-    file.write_all(&bytes)
-        .map_err(|e| format!("Cannot write to ROM file: {e}"))?;
-    file.flush()
-        .map_err(|e| format!("Error flushing ROM file: {e}"))?;
-    println!("{path_to_game}.nes created!");
-    Ok(bytes)
+/**
+ * Assembles a program meant to be copied into RAM with `CPU::load`, like snake.asm.
+ */
+pub fn load_program(path_to_source: &str) -> Result<Vec<u8>, String> {
+    Ok(assemble_file(path_to_source)?.to_binary())
+}
+
+/**
+ * iNES 1.0 image for mapper 0 with vertical mirroring.
+ */
+pub fn nrom_image(prg_rom: &[u8], chr_rom: &[u8]) -> Vec<u8> {
+    let mut header = vec![0; HEADER_SIZE];
+    header[..NES_IDENTIFIER_SIZE].copy_from_slice(&NES_TAG);
+    header[NUM_PRG_ROM_BANK_POS] = (prg_rom.len() / PRG_ROM_BANK_SIZE) as u8;
+    header[NUM_CHR_ROM_BANK_POS] = (chr_rom.len() / CHR_ROM_BANK_SIZE) as u8;
+    header[CONTROL_BYTE1_POS] = 0x01;
+
+    let mut image = Vec::with_capacity(HEADER_SIZE + prg_rom.len() + chr_rom.len());
+    image.extend(header);
+    image.extend(prg_rom);
+    image.extend(chr_rom);
+    image
 }
 
 pub mod test {
@@ -172,4 +147,17 @@ pub mod test {
 
         Rom::new(&test_rom).unwrap()
     }
+
+    #[test]
+    fn test_bad_images() {
+        let error = |image: &[u8]| Rom::new(&image.to_vec()).err().unwrap();
+        assert!(error(&[]).contains("shorter than the 16 byte header"));
+        assert!(error(&NES_TAG).contains("Truncated"));
+        assert_eq!(error(&[0xFF; 40_000]), "Not a valid .NES file!");
+
+        let image = nrom_image(&[0; 2 * PRG_ROM_BANK_SIZE], &[0; CHR_ROM_BANK_SIZE]);
+        assert!(error(&image[..HEADER_SIZE + 100]).contains("32768 bytes of PRG ROM"));
+        assert!(error(&image[..image.len() - 1]).contains("8192 bytes of CHR ROM"));
+        assert!(Rom::new(&image).is_ok());
+    }
 }
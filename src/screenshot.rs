@@ -0,0 +1,196 @@
+use std::path::{Path, PathBuf};
+
+use crate::{frame::Frame, palette::Rgb};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// largest stored (uncompressed) deflate block
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/**
+ * Writes `frame` as a PNG, each pixel blown up to `scale` x `scale`.
+ */
+pub fn save_png(path: &Path, frame: &Frame, palette: &[Rgb], scale: usize) -> Result<(), String> {
+    let scale = scale.max(1);
+    let rgb = scale_rgb(&frame.to_rgb(palette), frame.width, frame.height, scale);
+    let png = encode_png(frame.width * scale, frame.height * scale, &rgb);
+    std::fs::write(path, png).map_err(|e| format!("Cannot write {}: {e}", path.display()))
+}
+
+/**
+ * The frame's palette indices, one byte per pixel row by row with no header, so
+ * frames can be compared whatever palette is in use.
+ */
+pub fn save_indices(path: &Path, frame: &Frame) -> Result<(), String> {
+    std::fs::write(path, &frame.pixels).map_err(|e| format!("Cannot write {}: {e}", path.display()))
+}
+
+/**
+ * Where the screenshot hotkey saves to: `<dir>/<name>-001.png` and up, with a copy
+ * at `scale` and optionally the palette indices next to it.
+ */
+pub struct Screenshots {
+    pub dir: PathBuf,
+    pub name: String,
+    pub scale: usize,
+    pub indices: bool,
+    pub palette: Vec<Rgb>,
+}
+
+impl Screenshots {
+    /**
+     * Saves the frame under the first unused number and returns the native size PNG.
+     */
+    pub fn take(&self, frame: &Frame) -> Result<PathBuf, String> {
+        if !self.dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(&self.dir)
+                .map_err(|e| format!("Cannot create {}: {e}", self.dir.display()))?;
+        }
+        let (number, path) = (1..)
+            .map(|n| (n, self.dir.join(format!("{}-{n:03}.png", self.name))))
+            .find(|(_, path)| !path.exists())
+            .unwrap();
+        save_png(&path, frame, &self.palette, 1)?;
+        if self.scale > 1 {
+            let scaled = format!("{}-{number:03}-{}x.png", self.name, self.scale);
+            save_png(&self.dir.join(scaled), frame, &self.palette, self.scale)?;
+        }
+        if self.indices {
+            let indices = format!("{}-{number:03}.idx", self.name);
+            save_indices(&self.dir.join(indices), frame)?;
+        }
+        Ok(path)
+    }
+}
+
+fn scale_rgb(rgb: &[u8], width: usize, height: usize, scale: usize) -> Vec<u8> {
+    if scale == 1 {
+        return rgb.to_vec();
+    }
+    let mut scaled = Vec::with_capacity(rgb.len() * scale * scale);
+    for row in rgb.chunks(width * 3).take(height) {
+        let mut line = Vec::with_capacity(row.len() * scale);
+        for pixel in row.chunks(3) {
+            for _ in 0..scale {
+                line.extend_from_slice(pixel);
+            }
+        }
+        for _ in 0..scale {
+            scaled.extend_from_slice(&line);
+        }
+    }
+    scaled
+}
+
+/**
+ * 8 bit RGB PNG. The image data is stored rather than compressed, which keeps the
+ * encoder tiny at the cost of ~180KiB per native size frame.
+ * https://www.w3.org/TR/png/
+ */
+pub fn encode_png(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    let mut png = PNG_SIGNATURE.to_vec();
+
+    let mut header = vec![];
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    // bit depth 8, colour type 2 (RGB), default compression, filter and no interlace
+    header.extend([8, 2, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // every scanline starts with its filter type, 0 for none
+    let mut raw = Vec::with_capacity(rgb.len() + height);
+    for row in rgb.chunks(width * 3) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // deflate, 32K window, no preset dictionary, fastest; 0x7801 is a multiple of 31
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_png_layout() {
+        let rgb: Vec<u8> = (0..2 * 2 * 3).map(|i| i as u8).collect();
+        let png = encode_png(2, 2, &rgb);
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..24], [0, 0, 0, 2, 0, 0, 0, 2]);
+        // IEND always carries the same CRC
+        assert_eq!(
+            png[png.len() - 12..],
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+        );
+
+        let idat = &png[33 + 8..png.len() - 12 - 4];
+        // zlib header, one final stored block of 2 rows of (filter + 6 bytes)
+        assert_eq!(idat[..7], [0x78, 0x01, 1, 14, 0, !14, 0xFF]);
+        assert_eq!(idat[7..14], [0, 0, 1, 2, 3, 4, 5]);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn test_scale() {
+        let rgb = [1, 2, 3, 4, 5, 6];
+        let scaled = scale_rgb(&rgb, 2, 1, 2);
+        assert_eq!(
+            scaled,
+            [1, 2, 3, 1, 2, 3, 4, 5, 6, 4, 5, 6, 1, 2, 3, 1, 2, 3, 4, 5, 6, 4, 5, 6]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use rand::Rng;
+
+use crate::{
+    bus::Bus,
+    cpu::{Mem, CPU},
+    frame::Frame,
+    frontend::{Command, InputSource, VideoSink},
+    joypad::{JoypadButton, PLAYERS},
+    pacer::{FramePacer, SyncMode},
+    rom::{load_program, nrom_image, Rom, CHR_ROM_BANK_SIZE, PRG_ROM_BANK_SIZE},
+};
+
+const SCREEN: u16 = 0x0200;
+const SCREEN_SIZE: usize = 32;
+const RANDOM: u16 = 0xfe;
+const LAST_KEY: u16 = 0xff;
+/// upper bound on instructions between polls, in case the screen stops changing
+const INSTRUCTIONS_PER_POLL: usize = 100_000;
+
+/**
+ * easy6502's snake: a 32x32 screen at $0200, a random byte at $FE and the last key
+ * pressed at $FF. The d-pad stands in for WASD.
+ */
+pub fn run(video: &mut dyn VideoSink, input: &mut dyn InputSource) -> Result<(), String> {
+    // the game lives in RAM, the cartridge is blank
+    let rom = Rom::new(&nrom_image(
+        &vec![0; 2 * PRG_ROM_BANK_SIZE],
+        &vec![0; CHR_ROM_BANK_SIZE],
+    ))?;
+    let mut cpu = CPU::new(Bus::new(rom));
+    cpu.load(load_program("snake.asm")?);
+    cpu.program_counter = 0x0600;
+
+    let mut frame = Frame::with_size(SCREEN_SIZE, SCREEN_SIZE);
+    let mut pads = [JoypadButton::empty(); PLAYERS];
+    let mut rng = rand::thread_rng();
+    // one move per screen update, as the original did with vsync
+    let mut pacer = FramePacer::new(60.0, SyncMode::Timer);
+    loop {
+        if input.poll(&mut pads).contains(&Command::Quit) {
+            return Ok(());
+        }
+        if let Some(key) = key(pads[0]) {
+            cpu.mem_write(LAST_KEY, key);
+        }
+
+        for _ in 0..INSTRUCTIONS_PER_POLL {
+            cpu.mem_write(RANDOM, rng.gen_range(0..=16));
+            if !cpu.step() {
+                return Ok(());
+            }
+            if read_screen_state(&mut cpu, &mut frame) {
+                break;
+            }
+        }
+        video.present(&frame)?;
+        pacer.wait(None);
+    }
+}
+
+fn key(buttons: JoypadButton) -> Option<u8> {
+    if buttons.contains(JoypadButton::UP) {
+        Some(0x77)
+    } else if buttons.contains(JoypadButton::DOWN) {
+        Some(0x73)
+    } else if buttons.contains(JoypadButton::LEFT) {
+        Some(0x61)
+    } else if buttons.contains(JoypadButton::RIGHT) {
+        Some(0x64)
+    } else {
+        None
+    }
+}
+
+/**
+ * easy6502 colour numbers as the closest NES palette entries.
+ */
+fn color(byte: u8) -> u8 {
+    match byte {
+        0 => 0x0f,      // black
+        1 => 0x30,      // white
+        2 | 9 => 0x00,  // grey
+        3 | 10 => 0x16, // red
+        4 | 11 => 0x2a, // green
+        5 | 12 => 0x12, // blue
+        6 | 13 => 0x24, // magenta
+        7 | 14 => 0x28, // yellow
+        _ => 0x2c,      // cyan
+    }
+}
+
+fn read_screen_state(cpu: &mut CPU, frame: &mut Frame) -> bool {
+    let mut update = false;
+    for (i, pixel) in frame.pixels.iter_mut().enumerate() {
+        let colour = color(cpu.mem_read(SCREEN + i as u16));
+        if *pixel != colour {
+            *pixel = colour;
+            update = true;
+        }
+    }
+    update
+}
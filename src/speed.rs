@@ -0,0 +1,139 @@
+/**
+ * Pause, frame advance, fast-forward and slow motion for the run loop.
+ *
+ * Fast-forward runs `turbo_factor` frames for every one presented (frame skipping).
+ * Slow motion presents every frame but paces them at `slow_factor` of normal speed.
+ * Audio is muted whenever the emulator isn't running at normal speed.
+ */
+pub struct SpeedControl {
+    paused: bool,
+    pending_frames: u32,
+    fast_forward: bool,
+    slow_motion: bool,
+    pub turbo_factor: u32,
+    pub slow_factor: f64,
+}
+
+impl SpeedControl {
+    pub fn new() -> Self {
+        SpeedControl {
+            paused: false,
+            pending_frames: 0,
+            fast_forward: false,
+            slow_motion: false,
+            turbo_factor: 4,
+            slow_factor: 0.25,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.pending_frames = 0;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.is_paused() {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    /**
+     * Pauses if running, otherwise lets exactly one more frame through.
+     */
+    pub fn advance_frame(&mut self) {
+        if self.is_paused() {
+            self.pending_frames += 1;
+        } else {
+            self.pause();
+        }
+    }
+
+    pub fn set_fast_forward(&mut self, on: bool) {
+        self.fast_forward = on;
+    }
+
+    pub fn set_slow_motion(&mut self, on: bool) {
+        self.slow_motion = on;
+    }
+
+    pub fn toggle_slow_motion(&mut self) {
+        self.set_slow_motion(!self.slow_motion);
+    }
+
+    /**
+     * How many frames to emulate before presenting the next one. Zero while paused.
+     */
+    pub fn frames_to_run(&mut self) -> u32 {
+        if self.paused {
+            let frames = self.pending_frames.min(1);
+            self.pending_frames -= frames;
+            frames
+        } else if self.fast_forward {
+            self.turbo_factor.max(1)
+        } else {
+            1
+        }
+    }
+
+    /**
+     * Multiplier for the frame pacer.
+     */
+    pub fn pacing(&self) -> f64 {
+        if self.slow_motion && !self.fast_forward && !self.paused {
+            self.slow_factor
+        } else {
+            1.0
+        }
+    }
+
+    pub fn audible(&self) -> bool {
+        !self.paused && !self.fast_forward && !self.slow_motion
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pause_and_frame_advance() {
+        let mut speed = SpeedControl::new();
+        assert_eq!(speed.frames_to_run(), 1);
+
+        speed.advance_frame();
+        assert!(speed.is_paused());
+        assert_eq!(speed.frames_to_run(), 0);
+        speed.advance_frame();
+        speed.advance_frame();
+        assert_eq!(speed.frames_to_run(), 1);
+        assert_eq!(speed.frames_to_run(), 1);
+        assert_eq!(speed.frames_to_run(), 0);
+        assert!(!speed.audible());
+
+        speed.toggle_pause();
+        assert!(!speed.is_paused());
+        speed.set_fast_forward(true);
+        assert_eq!(speed.frames_to_run(), 4);
+        assert_eq!(speed.pacing(), 1.0);
+        speed.set_fast_forward(false);
+        speed.toggle_slow_motion();
+        assert_eq!(speed.frames_to_run(), 1);
+        assert_eq!(speed.pacing(), 0.25);
+        assert!(!speed.audible());
+        speed.set_slow_motion(true);
+        assert_eq!(speed.pacing(), 0.25);
+        speed.set_slow_motion(false);
+        assert_eq!(speed.pacing(), 1.0);
+        assert!(speed.audible());
+    }
+}
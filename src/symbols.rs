@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+
+use crate::{
+    bus::ROM_START,
+    disasm::Symbols,
+    rom::{HEADER_SIZE, PRG_ROM_BANK_SIZE},
+};
+
+/**
+ * Labels imported from ca65/ld65 debug info (.dbg) or FCEUX name lists (.nl).
+ * Symbols in PRG ROM remember which 16KiB bank they belong to, so the same CPU
+ * address can carry different names depending on what is mapped in.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u16,
+    /// PRG bank for ROM symbols, `None` for RAM and registers
+    pub bank: Option<usize>,
+}
+
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    by_addr: HashMap<(u16, Option<usize>), usize>,
+    by_name: HashMap<String, usize>,
+    /// PRG banks currently mapped at $8000 and $C000
+    windows: [usize; 2],
+}
+
+impl SymbolTable {
+    pub fn new(prg_len: usize) -> Self {
+        let banks = (prg_len / PRG_ROM_BANK_SIZE).max(1);
+        SymbolTable {
+            symbols: vec![],
+            by_addr: HashMap::new(),
+            by_name: HashMap::new(),
+            windows: [0, banks - 1],
+        }
+    }
+
+    /**
+     * Picks up whatever symbol files sit next to the ROM:
+     * `game.dbg` from ld65, and FCEUX's `game.nes.ram.nl` / `game.nes.<bank>.nl`.
+     */
+    pub fn load_for_rom(rom_path: &str, prg_len: usize) -> Result<Self, String> {
+        let mut table = SymbolTable::new(prg_len);
+        let dbg_path = format!("{}.dbg", rom_path.strip_suffix(".nes").unwrap_or(rom_path));
+        if let Ok(source) = std::fs::read_to_string(&dbg_path) {
+            table
+                .add_dbg(&source)
+                .map_err(|e| format!("{dbg_path}: {e}"))?;
+        }
+        if let Ok(source) = std::fs::read_to_string(format!("{rom_path}.ram.nl")) {
+            table.add_nl(&source, None);
+        }
+        for bank in 0..(prg_len / PRG_ROM_BANK_SIZE) {
+            if let Ok(source) = std::fs::read_to_string(format!("{rom_path}.{bank:X}.nl")) {
+                table.add_nl(&source, Some(bank));
+            }
+        }
+        Ok(table)
+    }
+
+    pub fn add(&mut self, name: &str, addr: u16, bank: Option<usize>) {
+        let bank = if addr < ROM_START { None } else { bank };
+        let index = self.symbols.len();
+        self.symbols.push(Symbol {
+            name: name.to_string(),
+            addr,
+            bank,
+        });
+        // the first name given to an address wins, like ld65's own listings
+        self.by_addr.entry((addr, bank)).or_insert(index);
+        self.by_name.insert(name.to_string(), index);
+    }
+
+    /**
+     * FCEUX name list: one `$ADDR#Name#Comment` per line, with multi-line
+     * comments continued by a trailing backslash. Array entries (`$ADDR/SIZE#`) only
+     * name their first byte.
+     */
+    pub fn add_nl(&mut self, source: &str, bank: Option<usize>) {
+        for line in source.lines() {
+            let Some(line) = line.strip_prefix('$') else {
+                continue;
+            };
+            let mut fields = line.splitn(3, '#');
+            let (Some(addr), Some(name)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let addr = addr.split('/').next().unwrap_or(addr);
+            if let (Ok(addr), false) = (u16::from_str_radix(addr, 16), name.is_empty()) {
+                self.add(name, addr, bank);
+            }
+        }
+    }
+
+    /**
+     * ld65 `--dbgfile` output. Segment records give the file offset of each segment
+     * in the .nes image, which is how a label finds its PRG bank.
+     * https://cc65.github.io/doc/debugging.html
+     */
+    pub fn add_dbg(&mut self, source: &str) -> Result<(), String> {
+        // seg id -> (start, offset in PRG ROM)
+        let mut segments: HashMap<String, (u32, Option<usize>)> = HashMap::new();
+        let mut labels = vec![];
+
+        for (n, line) in source.lines().enumerate() {
+            let Some((kind, fields)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let fields = dbg_fields(fields);
+            let field = |key: &str| fields.get(key).copied();
+            match kind {
+                "seg" => {
+                    let id = field("id").ok_or(format!("line {}: seg without id", n + 1))?;
+                    let start = parse_dbg_number(field("start").unwrap_or("0"))
+                        .map_err(|e| format!("line {}: {e}", n + 1))?;
+                    let offset = match field("ooffs") {
+                        Some(ooffs) => parse_dbg_number(ooffs)
+                            .map_err(|e| format!("line {}: {e}", n + 1))?
+                            .checked_sub(HEADER_SIZE as u32)
+                            .map(|o| o as usize),
+                        None => None,
+                    };
+                    segments.insert(id.to_string(), (start, offset));
+                }
+                "sym" if field("type") == Some("lab") => {
+                    let (Some(name), Some(val)) = (field("name"), field("val")) else {
+                        continue;
+                    };
+                    let val = parse_dbg_number(val).map_err(|e| format!("line {}: {e}", n + 1))?;
+                    labels.push((name.to_string(), val, field("seg").map(str::to_string)));
+                }
+                _ => {}
+            }
+        }
+
+        for (name, val, seg) in labels {
+            let bank = seg
+                .and_then(|seg| segments.get(&seg))
+                .and_then(|&(start, offset)| Some(offset? + (val.checked_sub(start)? as usize)))
+                .map(|offset| offset / PRG_ROM_BANK_SIZE);
+            self.add(&name, val as u16, bank);
+        }
+        Ok(())
+    }
+
+    /**
+     * Which PRG banks are mapped at $8000 and $C000. NROM keeps the defaults.
+     */
+    pub fn map_prg(&mut self, low: usize, high: usize) {
+        self.windows = [low, high];
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.by_name.get(name).map(|&i| &self.symbols[i])
+    }
+
+    /**
+     * Parses a `$C3A4`/`0xC3A4` address, or falls back to a symbol name.
+     */
+    pub fn resolve(&self, text: &str) -> Result<u16, String> {
+        let hex = text.strip_prefix('$').or_else(|| text.strip_prefix("0x"));
+        match hex {
+            Some(hex) => {
+                u16::from_str_radix(hex, 16).map_err(|e| format!("Invalid address {text}: {e}"))
+            }
+            None => self
+                .lookup(text)
+                .map(|symbol| symbol.addr)
+                .ok_or(format!("Unknown symbol {text}")),
+        }
+    }
+
+    fn mapped_bank(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xBFFF => Some(self.windows[0]),
+            0xC000..=0xFFFF => Some(self.windows[1]),
+            _ => None,
+        }
+    }
+}
+
+impl Symbols for SymbolTable {
+    fn label(&self, addr: u16) -> Option<&str> {
+        self.by_addr
+            .get(&(addr, self.mapped_bank(addr)))
+            .or_else(|| self.by_addr.get(&(addr, None)))
+            .map(|&i| self.symbols[i].name.as_str())
+    }
+}
+
+/**
+ * `id=3,name="CODE",start=0x00C000` -> {id: 3, name: CODE, start: 0x00C000}
+ */
+fn dbg_fields(fields: &str) -> HashMap<&str, &str> {
+    let mut map = HashMap::new();
+    let mut rest = fields.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let (value, tail) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+            }
+            None => value.split_once(',').map_or((value, ""), |(v, t)| (v, t)),
+        };
+        map.insert(key.trim(), value);
+        rest = tail.trim_start_matches(',');
+    }
+    map
+}
+
+fn parse_dbg_number(text: &str) -> Result<u32, String> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse::<u32>(),
+    }
+    .map_err(|e| format!("Invalid number {text}: {e}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_nl_symbols() {
+        let mut table = SymbolTable::new(2 * PRG_ROM_BANK_SIZE);
+        table.add_nl(
+            "$0010#playerX#\n$0300/20#oamBuffer#sprites\\\nsecond line\n",
+            None,
+        );
+        table.add_nl("$C3A4#UpdatePlayer#\n", Some(1));
+        table.add_nl("$C3A4#TitleScreen#\n", Some(0));
+
+        assert_eq!(table.label(0x0010), Some("playerX"));
+        assert_eq!(table.label(0x0300), Some("oamBuffer"));
+        assert_eq!(table.label(0xC3A4), Some("UpdatePlayer"));
+        table.map_prg(1, 0);
+        assert_eq!(table.label(0xC3A4), Some("TitleScreen"));
+        assert_eq!(table.resolve("UpdatePlayer"), Ok(0xC3A4));
+        assert_eq!(table.resolve("$0300"), Ok(0x0300));
+        assert!(table.resolve("nope").is_err());
+    }
+
+    #[test]
+    fn test_dbg_symbols() {
+        let dbg = r#"version	major=2,minor=0
+info	csym=0,file=2,lib=0,line=10,mod=1,scope=2,seg=3,span=5,sym=3,type=4
+seg	id=0,name="ZEROPAGE",start=0x000000,size=0x0002,addrsize=zeropage,type=rw
+seg	id=1,name="BANK0",start=0x008000,size=0x4000,addrsize=absolute,type=ro,oname="game.nes",ooffs=16
+seg	id=2,name="FIXED",start=0x00C000,size=0x4000,addrsize=absolute,type=ro,oname="game.nes",ooffs=16400
+sym	id=0,name="frame",addrsize=zeropage,size=1,scope=0,def=1,ref=4,val=0x1,seg=0,type=lab
+sym	id=1,name="UpdatePlayer",addrsize=absolute,scope=0,def=2,ref=5,val=0xC3A4,seg=2,type=lab
+sym	id=2,name="PPUCTRL",addrsize=absolute,scope=0,def=3,val=0x2000,type=equ
+"#;
+        let mut table = SymbolTable::new(2 * PRG_ROM_BANK_SIZE);
+        table.add_dbg(dbg).unwrap();
+
+        assert_eq!(
+            table.lookup("UpdatePlayer"),
+            Some(&Symbol {
+                name: "UpdatePlayer".to_string(),
+                addr: 0xC3A4,
+                bank: Some(1)
+            })
+        );
+        assert_eq!(table.label(0x0001), Some("frame"));
+        assert_eq!(table.lookup("PPUCTRL"), None);
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    ops::RangeInclusive,
+};
+
+use crate::{
+    cpu::{Peek, CPU},
+    disasm::Symbols,
+    logger::{log, log_mesen},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceFormat {
+    /// `logger::log`, matching nestest.log
+    Nestest,
+    /// disassembly with PPU scanline/dot and CPU cycles
+    Mesen,
+    /// fixed 8 byte records: PC (little endian), opcode, A, X, Y, P, SP
+    Binary,
+}
+
+impl TraceFormat {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "nestest" => Ok(TraceFormat::Nestest),
+            "mesen" => Ok(TraceFormat::Mesen),
+            "binary" => Ok(TraceFormat::Binary),
+            _ => Err(format!(
+                "Unknown trace format {name}, expected nestest, mesen or binary"
+            )),
+        }
+    }
+}
+
+pub const BINARY_RECORD_SIZE: usize = 8;
+
+/**
+ * Instruction trace, called once per instruction from the run loop. Output is
+ * buffered, so call `flush` before exiting without dropping the tracer.
+ */
+pub struct Tracer {
+    sink: BufWriter<Box<dyn Write>>,
+    format: TraceFormat,
+    enabled: bool,
+    trigger: Option<u16>,
+    pc_range: Option<RangeInclusive<u16>>,
+    bank: Option<usize>,
+    symbols: Box<dyn Symbols>,
+}
+
+impl Tracer {
+    pub fn new(sink: Box<dyn Write>, format: TraceFormat) -> Self {
+        Tracer {
+            sink: BufWriter::new(sink),
+            format,
+            enabled: true,
+            trigger: None,
+            pc_range: None,
+            bank: None,
+            symbols: Box::new(()),
+        }
+    }
+
+    pub fn to_file(path: &str, format: TraceFormat) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Cannot create {path}: {e}"))?;
+        Ok(Tracer::new(Box::new(file), format))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if enabled {
+            self.trigger = None;
+        }
+    }
+
+    /**
+     * Stays quiet until the CPU reaches `addr`, then traces from there on.
+     */
+    pub fn trigger_at(&mut self, addr: u16) {
+        self.enabled = false;
+        self.trigger = Some(addr);
+    }
+
+    pub fn filter_pc(&mut self, range: RangeInclusive<u16>) {
+        self.pc_range = Some(range);
+    }
+
+    /**
+     * Only traces instructions running from this 16KiB PRG bank.
+     */
+    pub fn filter_bank(&mut self, bank: usize) {
+        self.bank = Some(bank);
+    }
+
+    pub fn set_symbols(&mut self, symbols: Box<dyn Symbols>) {
+        self.symbols = symbols;
+    }
+
+    pub fn trace(&mut self, cpu: &CPU) -> Result<(), String> {
+        let pc = cpu.program_counter;
+        if self.trigger == Some(pc) {
+            self.set_enabled(true);
+        }
+        if !self.enabled {
+            return Ok(());
+        }
+        if let Some(range) = &self.pc_range {
+            if !range.contains(&pc) {
+                return Ok(());
+            }
+        }
+        if self.bank.is_some() && cpu.bus.prg_bank(pc) != self.bank {
+            return Ok(());
+        }
+
+        let written = match self.format {
+            TraceFormat::Nestest => {
+                writeln!(self.sink, "{}", log(cpu, self.symbols.as_ref()))
+            }
+            TraceFormat::Mesen => {
+                writeln!(self.sink, "{}", log_mesen(cpu, self.symbols.as_ref()))
+            }
+            TraceFormat::Binary => {
+                let opcode = cpu.peek(pc);
+                let [lo, hi] = pc.to_le_bytes();
+                self.sink.write_all(&[
+                    lo,
+                    hi,
+                    opcode,
+                    cpu.register_a,
+                    cpu.register_x,
+                    cpu.register_y,
+                    cpu.status.bits(),
+                    cpu.stack_ptr,
+                ])
+            }
+        };
+        written.map_err(|e| format!("Cannot write trace: {e}"))
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.sink
+            .flush()
+            .map_err(|e| format!("Cannot write trace: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cpu::Mem;
+    use crate::rom::test::test_rom;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Sink the test can read back after the tracer is done with it
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run(tracer: &mut Tracer) {
+        let mut bus = Bus::new(test_rom());
+        // LDX #$01; DEX; DEY; BRK
+        for (i, byte) in [0xa2, 0x01, 0xca, 0x88, 0x00].iter().enumerate() {
+            bus.mem_write(0x64 + i as u16, *byte);
+        }
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x64;
+        cpu.run_with_callback(|cpu| tracer.trace(cpu).unwrap());
+        tracer.flush().unwrap();
+    }
+
+    #[test]
+    fn test_trigger_and_range() {
+        let out = Shared::default();
+        let mut tracer = Tracer::new(Box::new(out.clone()), TraceFormat::Nestest);
+        tracer.trigger_at(0x66);
+        tracer.filter_pc(0x00..=0x67);
+        run(&mut tracer);
+
+        let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("0066  CA        DEX"));
+        assert!(lines[1].starts_with("0067  88        DEY"));
+    }
+
+    #[test]
+    fn test_mesen_and_binary_formats() {
+        let out = Shared::default();
+        let mut tracer = Tracer::new(Box::new(out.clone()), TraceFormat::Mesen);
+        run(&mut tracer);
+        let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+        assert_eq!(
+            text.lines().nth(1).unwrap(),
+            "0066  DEX                            A:00 X:01 Y:00 S:FD P:nvUbdIzc V:0   H:6   Cy:2"
+        );
+
+        let out = Shared::default();
+        let mut tracer = Tracer::new(Box::new(out.clone()), TraceFormat::Binary);
+        run(&mut tracer);
+        let bytes = out.0.borrow();
+        assert_eq!(bytes.len(), 4 * BINARY_RECORD_SIZE);
+        assert_eq!(
+            &bytes[8..16],
+            &[0x66, 0x00, 0xca, 0x00, 0x01, 0x00, 0x24, 0xfd]
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use crate::trace::BINARY_RECORD_SIZE;
+
+/**
+ * Compares two instruction traces and finds where they first part ways.
+ *
+ * Lines are parsed by their `KEY:value` fields rather than by column, so the output
+ * of `logger::log`, the reference nestest.log (with `PPU:` and `CYC:`) and Mesen
+ * traces (`S:`, `P:nvUbdIzc`, `V:`/`H:`, `Cy:`) can be compared with each other.
+ * Fields missing from either side are skipped. Binary traces from `Tracer` are
+ * read as well.
+ */
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TraceRecord {
+    pub line: String,
+    pub pc: u16,
+    pub opcode: Option<u8>,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    /// value at the end of the `... = vv` memory annotation
+    pub memory: Option<u8>,
+    pub cycles: Option<u64>,
+    pub ppu: Option<(u16, u16)>,
+}
+
+pub struct Divergence {
+    /// index of the diverging record in each trace
+    pub expected_index: usize,
+    pub actual_index: usize,
+    pub context: Vec<String>,
+    pub expected: String,
+    pub actual: String,
+    pub differences: Vec<String>,
+}
+
+pub enum DiffResult {
+    Match(usize),
+    Diverged(Divergence),
+    /// traces agree for as long as both run, one of them keeps going
+    Truncated(usize),
+}
+
+pub fn parse_trace(bytes: &[u8]) -> Result<Vec<TraceRecord>, String> {
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) if !text.contains('\0') => text,
+        _ => return parse_binary(bytes),
+    };
+    Ok(text.lines().filter_map(parse_line).collect())
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<TraceRecord>, String> {
+    if !bytes.len().is_multiple_of(BINARY_RECORD_SIZE) {
+        return Err(format!(
+            "Binary trace is {} bytes, not a multiple of {BINARY_RECORD_SIZE}",
+            bytes.len()
+        ));
+    }
+    Ok(bytes
+        .chunks(BINARY_RECORD_SIZE)
+        .map(|r| {
+            let pc = u16::from_le_bytes([r[0], r[1]]);
+            TraceRecord {
+                line: format!(
+                    "{:04X}  {:02X}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+                    pc, r[2], r[3], r[4], r[5], r[6], r[7]
+                ),
+                pc,
+                opcode: Some(r[2]),
+                a: r[3],
+                x: r[4],
+                y: r[5],
+                p: r[6],
+                sp: r[7],
+                ..Default::default()
+            }
+        })
+        .collect())
+}
+
+/**
+ * Lines that don't start with a PC and carry all five registers (headers, blank
+ * lines, "NMI" markers) are not instructions and get skipped.
+ */
+pub fn parse_line(line: &str) -> Option<TraceRecord> {
+    let mut tokens = line.split_whitespace();
+    let pc = tokens.next()?;
+    let pc = u16::from_str_radix(pc.trim_start_matches('$'), 16).ok()?;
+    let opcode = tokens
+        .next()
+        .map(|t| t.trim_start_matches('$'))
+        .filter(|t| t.len() == 2)
+        .and_then(|t| u8::from_str_radix(t, 16).ok());
+
+    let (before_registers, registers) = line.split_at(line.find(" A:")?);
+    let field = |key: &str| {
+        registers
+            .split_whitespace()
+            .find_map(|t| t.strip_prefix(key))
+            .filter(|v| !v.is_empty())
+    };
+    let hex = |key: &str| field(key).and_then(|v| u8::from_str_radix(v, 16).ok());
+
+    let p = field("P:").and_then(|flags| match flags.len() {
+        8 if flags
+            .chars()
+            .any(|c| c.is_ascii_alphabetic() && !c.is_ascii_hexdigit()) =>
+        {
+            Some(flag_letters(flags))
+        }
+        _ => u8::from_str_radix(flags, 16).ok(),
+    })?;
+    let cycles = field("CYC:")
+        .or_else(|| field("Cy:"))
+        .and_then(|v| v.parse::<u64>().ok());
+    let ppu = nestest_ppu(registers).or_else(|| {
+        let v = field("V:")?.parse::<u16>().ok()?;
+        let h = field("H:")?.parse::<u16>().ok()?;
+        Some((v, h))
+    });
+    let memory = before_registers
+        .rsplit_once("= ")
+        .and_then(|(_, value)| u8::from_str_radix(value.trim(), 16).ok());
+
+    Some(TraceRecord {
+        line: line.to_string(),
+        pc,
+        opcode,
+        a: hex("A:")?,
+        x: hex("X:")?,
+        y: hex("Y:")?,
+        p,
+        sp: hex("SP:").or_else(|| hex("S:"))?,
+        memory,
+        cycles,
+        ppu,
+    })
+}
+
+/**
+ * Mesen writes flags as `NV-BDIZC` letters, upper case when set.
+ */
+fn flag_letters(flags: &str) -> u8 {
+    flags
+        .chars()
+        .enumerate()
+        .filter(|(_, c)| c.is_ascii_uppercase())
+        .fold(0, |p, (i, _)| p | (0x80 >> i))
+}
+
+/**
+ * nestest.log pads the PPU position: `PPU:  0, 21` or `PPU:241,  3`.
+ */
+fn nestest_ppu(registers: &str) -> Option<(u16, u16)> {
+    let rest = &registers[registers.find("PPU:")? + 4..];
+    let (v, rest) = rest.split_once(',')?;
+    let h = rest.split_whitespace().next()?;
+    Some((v.trim().parse().ok()?, h.parse().ok()?))
+}
+
+pub fn diff(expected: &[TraceRecord], actual: &[TraceRecord], context: usize) -> DiffResult {
+    let (skip_expected, skip_actual) = align(expected, actual);
+    let expected = &expected[skip_expected..];
+    let actual = &actual[skip_actual..];
+
+    for (i, (e, a)) in expected.iter().zip(actual).enumerate() {
+        let differences = compare(e, a);
+        if !differences.is_empty() {
+            return DiffResult::Diverged(Divergence {
+                expected_index: skip_expected + i,
+                actual_index: skip_actual + i,
+                context: expected[i.saturating_sub(context)..i]
+                    .iter()
+                    .map(|r| r.line.clone())
+                    .collect(),
+                expected: e.line.clone(),
+                actual: a.line.clone(),
+                differences,
+            });
+        }
+    }
+
+    let compared = expected.len().min(actual.len());
+    if expected.len() == actual.len() {
+        DiffResult::Match(compared)
+    } else {
+        DiffResult::Truncated(compared)
+    }
+}
+
+/**
+ * Traces may start at different points (reset vector vs. nestest's $C000), so drop
+ * records from the front of whichever trace reaches the other's first PC later.
+ */
+fn align(expected: &[TraceRecord], actual: &[TraceRecord]) -> (usize, usize) {
+    let (Some(e), Some(a)) = (expected.first(), actual.first()) else {
+        return (0, 0);
+    };
+    if e.pc == a.pc {
+        return (0, 0);
+    }
+    let in_expected = expected.iter().position(|r| r.pc == a.pc);
+    let in_actual = actual.iter().position(|r| r.pc == e.pc);
+    match (in_expected, in_actual) {
+        (Some(skip), Some(other)) if skip <= other => (skip, 0),
+        (_, Some(skip)) => (0, skip),
+        (Some(skip), None) => (skip, 0),
+        (None, None) => (0, 0),
+    }
+}
+
+fn compare(e: &TraceRecord, a: &TraceRecord) -> Vec<String> {
+    let mut differences = vec![];
+    if e.pc != a.pc {
+        differences.push(format!("PC: expected {:04X}, got {:04X}", e.pc, a.pc));
+    }
+    let mut byte = |name: &str, e: u8, a: u8| {
+        if e != a {
+            differences.push(format!("{name}: expected {e:02X}, got {a:02X}"));
+        }
+    };
+    if let (Some(e), Some(a)) = (e.opcode, a.opcode) {
+        byte("opcode", e, a);
+    }
+    byte("A", e.a, a.a);
+    byte("X", e.x, a.x);
+    byte("Y", e.y, a.y);
+    byte("SP", e.sp, a.sp);
+    if let (Some(e), Some(a)) = (e.memory, a.memory) {
+        byte("memory", e, a);
+    }
+    // the unused bit 5 and B flag are not real CPU state
+    if (e.p ^ a.p) & 0b1100_1111 != 0 {
+        let changed: String = "NV--DIZC"
+            .chars()
+            .enumerate()
+            .filter(|&(i, c)| c != '-' && (e.p ^ a.p) & (0x80 >> i) != 0)
+            .map(|(_, c)| c)
+            .collect();
+        differences.push(format!(
+            "P: expected {:02X}, got {:02X} (flags {changed})",
+            e.p, a.p
+        ));
+    }
+    if let (Some(e), Some(a)) = (e.cycles, a.cycles) {
+        if e != a {
+            differences.push(format!("cycles: expected {e}, got {a}"));
+        }
+    }
+    if let (Some(e), Some(a)) = (e.ppu, a.ppu) {
+        if e != a {
+            differences.push(format!(
+                "PPU: expected scanline {} dot {}, got scanline {} dot {}",
+                e.0, e.1, a.0, a.1
+            ));
+        }
+    }
+    differences
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const NESTEST: &str = "\
+C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 30 CYC:10
+C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 36 CYC:12
+C5F9  86 10     STX $10 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 45 CYC:15
+";
+
+    #[test]
+    fn test_parse_formats() {
+        let record = parse_line(NESTEST.lines().nth(2).unwrap()).unwrap();
+        assert_eq!(record.pc, 0xC5F7);
+        assert_eq!(record.opcode, Some(0x86));
+        assert_eq!(record.p, 0x26);
+        assert_eq!(record.memory, Some(0));
+        assert_eq!(record.cycles, Some(12));
+        assert_eq!(record.ppu, Some((0, 36)));
+
+        let mesen = parse_line(
+            "C5F7  STX $00                        A:00 X:00 Y:00 S:FD P:nvUbdIZc V:0   H:36  Cy:12",
+        )
+        .unwrap();
+        assert_eq!((mesen.p, mesen.sp, mesen.ppu), (0x26, 0xFD, Some((0, 36))));
+        assert_eq!(parse_line("NMI"), None);
+    }
+
+    #[test]
+    fn test_first_divergence() {
+        let expected = parse_trace(NESTEST.as_bytes()).unwrap();
+        // starts one instruction earlier, drops the zero flag on the third
+        let actual = parse_trace(
+            b"\
+FFFC  4C 00 C0  JMP $C000                       A:00 X:00 Y:00 P:24 SP:FD
+C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD
+C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD
+C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:24 SP:FD
+",
+        )
+        .unwrap();
+
+        match diff(&expected, &actual, 5) {
+            DiffResult::Diverged(divergence) => {
+                assert_eq!((divergence.expected_index, divergence.actual_index), (2, 3));
+                assert_eq!(divergence.context.len(), 2);
+                assert_eq!(
+                    divergence.differences,
+                    vec!["P: expected 26, got 24 (flags Z)".to_string()]
+                );
+            }
+            _ => panic!("traces should diverge"),
+        }
+    }
+}
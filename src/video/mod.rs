@@ -0,0 +1,87 @@
+pub mod ntsc;
+pub mod scale;
+
+use crate::{frame::Frame, palette::Rgb};
+use ntsc::NtscFilter;
+use scale::Scaler;
+
+/**
+ * An RGB24 picture on its way from a `Frame` to the screen.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            rgb: vec![0; width * height * 3],
+        }
+    }
+
+    pub fn from_frame(frame: &Frame, palette: &[Rgb]) -> Self {
+        Image {
+            width: frame.width,
+            height: frame.height,
+            rgb: frame.to_rgb(palette),
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Rgb {
+        let start = (y * self.width + x) * 3;
+        (self.rgb[start], self.rgb[start + 1], self.rgb[start + 2])
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, (r, g, b): Rgb) {
+        let start = (y * self.width + x) * 3;
+        self.rgb[start..start + 3].copy_from_slice(&[r, g, b]);
+    }
+}
+
+/**
+ * Turns the palette indices the PPU draws into the picture a window shows: colours
+ * from the palette or the NTSC filter, then scaling, scanlines and aspect
+ * correction, in that order.
+ */
+pub struct VideoPipeline {
+    pub palette: Vec<Rgb>,
+    pub ntsc: Option<NtscFilter>,
+    pub scaler: Scaler,
+    /// how much darker the gaps between lines are, in percent; 0 turns them off
+    pub scanlines: u32,
+    pub aspect_8_7: bool,
+}
+
+impl VideoPipeline {
+    pub fn new(palette: Vec<Rgb>) -> Self {
+        VideoPipeline {
+            palette,
+            ntsc: None,
+            scaler: Scaler::None,
+            scanlines: 0,
+            aspect_8_7: false,
+        }
+    }
+
+    pub fn process(&self, frame: &Frame) -> Image {
+        let mut image = match &self.ntsc {
+            Some(filter) => filter.apply(frame),
+            None => Image::from_frame(frame, &self.palette),
+        };
+        if self.scaler != Scaler::None {
+            image = self.scaler.apply(&image);
+        }
+        if self.scanlines > 0 {
+            image = scale::scanlines(&image, frame.height, self.scanlines);
+        }
+        if self.aspect_8_7 {
+            image = scale::correct_aspect(&image);
+        }
+        image
+    }
+}
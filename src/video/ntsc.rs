@@ -0,0 +1,202 @@
+use super::Image;
+use crate::{
+    frame::Frame,
+    palette::{
+        ntsc_angle, ntsc_signal, yiq_to_rgb, NtscParams, Rgb, EMPHASIS_PALETTE_SIZE, PALETTE_SIZE,
+    },
+};
+
+/// the PPU puts out 8 of the 12 subcarrier phases per pixel
+const SAMPLES_PER_PIXEL: usize = 8;
+/// phases in one cycle of the colour subcarrier
+const PHASES: usize = 12;
+/// a line of 341 dots moves the subcarrier on by 341 * 8 % 12 phases
+const PHASES_PER_LINE: usize = 4;
+/// output pixels for each PPU pixel
+pub const WIDEN: usize = 2;
+
+/**
+ * How the picture reaches the TV, from most to least blurry.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NtscPreset {
+    /// luma and chroma share one wire, so each leaks into the other
+    Composite,
+    /// luma and chroma on separate wires: no crosstalk, but chroma is still blurred
+    SVideo,
+    /// the palette's colours, sharp
+    Rgb,
+}
+
+impl NtscPreset {
+    /**
+     * Reads the `[video] filter` setting, where `none` turns the filter off.
+     */
+    pub fn parse(name: &str) -> Result<Option<Self>, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "" | "none" => Ok(None),
+            "composite" => Ok(Some(NtscPreset::Composite)),
+            "svideo" | "s-video" => Ok(Some(NtscPreset::SVideo)),
+            "rgb" => Ok(Some(NtscPreset::Rgb)),
+            _ => Err(format!(
+                "Unknown filter {name}, expected none, composite, svideo or rgb"
+            )),
+        }
+    }
+}
+
+/**
+ * Rebuilds the signal the PPU sends down the cable, 8 samples per pixel, and
+ * decodes it again the way a TV would. The decoder looks at a whole subcarrier
+ * cycle (1.5 pixels) around each output pixel, which is what blends dithering and
+ * fringes sharp edges with colour. The output is `WIDEN` times as wide as the frame.
+ * https://www.nesdev.org/wiki/NTSC_video
+ */
+pub struct NtscFilter {
+    preset: NtscPreset,
+    params: NtscParams,
+    palette: Vec<Rgb>,
+    /// the signal for each colour, emphasis included, at each phase
+    signals: Vec<[f32; PHASES]>,
+    /// each colour's signal averaged over a cycle, for S-Video's separate luma
+    luma: Vec<f32>,
+    /// the decoder's reference waves for I and Q
+    cos: [f32; PHASES],
+    sin: [f32; PHASES],
+}
+
+impl NtscFilter {
+    /**
+     * `palette` is only used by the RGB preset; the others derive their colours
+     * from the signal and `params`.
+     */
+    pub fn new(preset: NtscPreset, params: &NtscParams, palette: &[Rgb]) -> Self {
+        let signals: Vec<[f32; PHASES]> = (0..EMPHASIS_PALETTE_SIZE)
+            .map(|colour| {
+                std::array::from_fn(|phase| {
+                    ntsc_signal(colour % PALETTE_SIZE, colour / PALETTE_SIZE, phase) as f32
+                })
+            })
+            .collect();
+        let luma = signals
+            .iter()
+            .map(|signal| signal.iter().sum::<f32>() / PHASES as f32)
+            .collect();
+        NtscFilter {
+            preset,
+            params: params.clone(),
+            palette: palette.to_vec(),
+            signals,
+            luma,
+            cos: std::array::from_fn(|phase| ntsc_angle(phase as f64, params).cos() as f32),
+            sin: std::array::from_fn(|phase| ntsc_angle(phase as f64, params).sin() as f32),
+        }
+    }
+
+    pub fn apply(&self, frame: &Frame) -> Image {
+        let mut image = Image::new(frame.width * WIDEN, frame.height);
+        if self.preset == NtscPreset::Rgb {
+            let rgb = frame.to_rgb(&self.palette);
+            for (i, pixel) in rgb.chunks(3).enumerate() {
+                for copy in 0..WIDEN {
+                    let start = (i * WIDEN + copy) * 3;
+                    image.rgb[start..start + 3].copy_from_slice(pixel);
+                }
+            }
+            return image;
+        }
+
+        let emphasis = (frame.emphasis as usize & 0b111) * PALETTE_SIZE;
+        let samples = frame.width * SAMPLES_PER_PIXEL;
+        let mut signal = vec![0.0; samples];
+        let mut luma = vec![0.0; samples];
+        for y in 0..frame.height {
+            let start = y * PHASES_PER_LINE % PHASES;
+            for x in 0..frame.width {
+                let colour = emphasis + (frame.pixel(x, y) & 0x3F) as usize;
+                for s in 0..SAMPLES_PER_PIXEL {
+                    let sample = x * SAMPLES_PER_PIXEL + s;
+                    signal[sample] = self.signals[colour][(start + sample) % PHASES];
+                    luma[sample] = self.luma[colour];
+                }
+            }
+
+            for x in 0..image.width {
+                let centre = x * SAMPLES_PER_PIXEL / WIDEN + SAMPLES_PER_PIXEL / WIDEN / 2;
+                let (mut y_sum, mut i, mut q) = (0.0, 0.0, 0.0);
+                // the picture is black beyond its edges
+                let first = centre.saturating_sub(PHASES / 2);
+                let last = (centre + PHASES / 2).min(samples);
+                for sample in first..last {
+                    let phase = (start + sample) % PHASES;
+                    let chroma = match self.preset {
+                        NtscPreset::SVideo => signal[sample] - luma[sample],
+                        _ => signal[sample],
+                    };
+                    y_sum += signal[sample];
+                    i += chroma * self.cos[phase];
+                    q += chroma * self.sin[phase];
+                }
+                let y_value = match self.preset {
+                    NtscPreset::SVideo => luma[centre],
+                    _ => y_sum / PHASES as f32,
+                };
+                let rgb = yiq_to_rgb(
+                    y_value as f64,
+                    (i / (PHASES / 2) as f32) as f64,
+                    (q / (PHASES / 2) as f32) as f64,
+                    &self.params,
+                );
+                image.set_pixel(x, y, rgb);
+            }
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::palette::{generate_ntsc, SYSTEM_PALETTE};
+
+    fn filter(preset: NtscPreset, frame: &Frame) -> Image {
+        NtscFilter::new(preset, &NtscParams::default(), &SYSTEM_PALETTE).apply(frame)
+    }
+
+    #[test]
+    fn test_flat_colour_decodes_to_the_ntsc_palette() {
+        let mut frame = Frame::with_size(8, 2);
+        frame.pixels.fill(0x16);
+        frame.emphasis = 0b100;
+        let image = filter(NtscPreset::Composite, &frame);
+        assert_eq!((image.width, image.height), (16, 2));
+        let (r, g, b) = generate_ntsc(&NtscParams::default())[4 * PALETTE_SIZE + 0x16];
+        for (x, y) in [(8, 0), (7, 1)] {
+            let (r2, g2, b2) = image.pixel(x, y);
+            assert!(r.abs_diff(r2) <= 1 && g.abs_diff(g2) <= 1 && b.abs_diff(b2) <= 1);
+        }
+    }
+
+    #[test]
+    fn test_presets() {
+        // one pixel wide black and white stripes
+        let mut frame = Frame::with_size(16, 1);
+        for x in 0..16 {
+            frame.set_pixel(x, 0, if x % 2 == 0 { 0x30 } else { 0x0F });
+        }
+        // composite turns the stripes into colour, S-Video keeps them grey
+        let (r, g, b) = filter(NtscPreset::Composite, &frame).pixel(16, 0);
+        assert!(r != g || g != b);
+        let (r, g, b) = filter(NtscPreset::SVideo, &frame).pixel(16, 0);
+        assert!(r == g && g == b);
+
+        let rgb = filter(NtscPreset::Rgb, &frame);
+        assert_eq!(rgb.width, 32);
+        assert_eq!(rgb.pixel(1, 0), SYSTEM_PALETTE[0x30]);
+        assert_eq!(rgb.pixel(2, 0), SYSTEM_PALETTE[0x0F]);
+
+        assert_eq!(NtscPreset::parse("S-Video"), Ok(Some(NtscPreset::SVideo)));
+        assert_eq!(NtscPreset::parse("none"), Ok(None));
+        assert!(NtscPreset::parse("crt").is_err());
+    }
+}
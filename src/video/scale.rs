@@ -0,0 +1,281 @@
+use super::Image;
+use crate::palette::Rgb;
+
+/**
+ * Pixel art scalers, run on the CPU so the window looks the same whatever the
+ * renderer does when it stretches the picture.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scaler {
+    /// leave stretching to the window
+    None,
+    /// repeat every pixel this many times each way
+    Integer(usize),
+    /// EPX: fills in the corners of diagonal edges, doubling the size
+    Scale2x,
+    /// AdvMAME3x, Scale2x's rules for three times the size
+    Scale3x,
+    /// like Scale2x, but finds edges between similar colours rather than equal ones
+    /// and blends along them instead of copying, in the spirit of HQ2x and xBR
+    Hq2x,
+}
+
+impl Scaler {
+    /**
+     * Reads the `[video] scaler` setting; `integer` scales up to `window_scale`.
+     */
+    pub fn parse(name: &str, window_scale: usize) -> Result<Self, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "" | "none" => Ok(Scaler::None),
+            "integer" => Ok(Scaler::Integer(window_scale.max(1))),
+            "scale2x" | "epx" => Ok(Scaler::Scale2x),
+            "scale3x" => Ok(Scaler::Scale3x),
+            "hq2x" | "xbr" => Ok(Scaler::Hq2x),
+            _ => Err(format!(
+                "Unknown scaler {name}, expected none, integer, scale2x, scale3x or hq2x"
+            )),
+        }
+    }
+
+    pub fn apply(&self, image: &Image) -> Image {
+        match *self {
+            Scaler::None | Scaler::Integer(1) => image.clone(),
+            Scaler::Integer(factor) => nearest(image, factor),
+            Scaler::Scale2x => scale_by(image, 2, scale2x),
+            Scaler::Scale3x => scale_by(image, 3, scale3x),
+            Scaler::Hq2x => scale_by(image, 2, hq2x),
+        }
+    }
+}
+
+fn nearest(image: &Image, factor: usize) -> Image {
+    let mut scaled = Image::new(image.width * factor, image.height * factor);
+    for y in 0..scaled.height {
+        for x in 0..scaled.width {
+            scaled.set_pixel(x, y, image.pixel(x / factor, y / factor));
+        }
+    }
+    scaled
+}
+
+/**
+ * Runs `rule` on each pixel's 3x3 neighbourhood (edges repeat), which returns the
+ * `factor` x `factor` block that replaces it, row by row.
+ */
+fn scale_by(image: &Image, factor: usize, rule: fn(&[Rgb; 9]) -> Vec<Rgb>) -> Image {
+    let mut scaled = Image::new(image.width * factor, image.height * factor);
+    let (w, h) = (image.width as isize, image.height as isize);
+    for y in 0..h {
+        for x in 0..w {
+            let around: [Rgb; 9] = std::array::from_fn(|i| {
+                let (dx, dy) = (i as isize % 3 - 1, i as isize / 3 - 1);
+                let nx = (x + dx).clamp(0, w - 1) as usize;
+                let ny = (y + dy).clamp(0, h - 1) as usize;
+                image.pixel(nx, ny)
+            });
+            for (i, colour) in rule(&around).into_iter().enumerate() {
+                let sx = x as usize * factor + i % factor;
+                let sy = y as usize * factor + i / factor;
+                scaled.set_pixel(sx, sy, colour);
+            }
+        }
+    }
+    scaled
+}
+
+/*
+ * The neighbourhoods below are named
+ *   A B C
+ *   D E F
+ *   G H I
+ */
+
+fn scale2x(&[_, b, _, d, e, f, _, h, _]: &[Rgb; 9]) -> Vec<Rgb> {
+    if b == h || d == f {
+        return vec![e; 4];
+    }
+    vec![
+        if d == b { d } else { e },
+        if b == f { f } else { e },
+        if d == h { d } else { e },
+        if h == f { f } else { e },
+    ]
+}
+
+fn scale3x(&[a, b, c, d, e, f, g, h, i]: &[Rgb; 9]) -> Vec<Rgb> {
+    if b == h || d == f {
+        return vec![e; 9];
+    }
+    let pick = |take: bool, colour| if take { colour } else { e };
+    vec![
+        pick(d == b, d),
+        pick((d == b && e != c) || (b == f && e != a), b),
+        pick(b == f, f),
+        pick((d == b && e != g) || (d == h && e != a), d),
+        e,
+        pick((b == f && e != i) || (h == f && e != c), f),
+        pick(d == h, d),
+        pick((d == h && e != i) || (h == f && e != g), h),
+        pick(h == f, f),
+    ]
+}
+
+fn hq2x(&[_, b, _, d, e, f, _, h, _]: &[Rgb; 9]) -> Vec<Rgb> {
+    // a corner lies on an edge when its two neighbours match each other but not
+    // the pixels across from them
+    let corner = |one: Rgb, two: Rgb, across_one: Rgb, across_two: Rgb| {
+        if similar(one, two) && !similar(one, across_two) && !similar(two, across_one) {
+            blend(&[(e, 2), (one, 1), (two, 1)])
+        } else {
+            e
+        }
+    };
+    vec![
+        corner(d, b, f, h),
+        corner(b, f, h, d),
+        corner(h, d, b, f),
+        corner(f, h, d, b),
+    ]
+}
+
+/**
+ * HQx's test: close enough in luma and both chroma components.
+ */
+fn similar(a: Rgb, b: Rgb) -> bool {
+    let yuv = |(r, g, b): Rgb| {
+        let (r, g, b) = (r as i32, g as i32, b as i32);
+        (
+            (r + g + b) / 3,
+            (r - b) / 4 + 128,
+            (2 * g - r - b) / 8 + 128,
+        )
+    };
+    let ((y1, u1, v1), (y2, u2, v2)) = (yuv(a), yuv(b));
+    (y1 - y2).abs() <= 48 && (u1 - u2).abs() <= 7 && (v1 - v2).abs() <= 6
+}
+
+fn blend(colours: &[(Rgb, u32)]) -> Rgb {
+    let total: u32 = colours.iter().map(|(_, weight)| weight).sum();
+    let channel = |pick: fn(Rgb) -> u8| {
+        let sum: u32 = colours
+            .iter()
+            .map(|&(c, weight)| pick(c) as u32 * weight)
+            .sum();
+        ((sum + total / 2) / total) as u8
+    };
+    (channel(|c| c.0), channel(|c| c.1), channel(|c| c.2))
+}
+
+/**
+ * Darkens the last row of every picture line by `strength` percent, like the gaps
+ * between a CRT's scanlines. Lines drawn one row high are doubled first.
+ */
+pub fn scanlines(image: &Image, lines: usize, strength: u32) -> Image {
+    let image = match image.height / lines.max(1) {
+        0 | 1 => stretch_rows(image, 2),
+        _ => image.clone(),
+    };
+    let rows_per_line = image.height / lines.max(1);
+    let mut darkened = image;
+    let keep = 100 - strength.min(100);
+    let row_bytes = darkened.width * 3;
+    for (y, row) in darkened.rgb.chunks_mut(row_bytes).enumerate() {
+        if y % rows_per_line == rows_per_line - 1 {
+            for channel in row {
+                *channel = (*channel as u32 * keep / 100) as u8;
+            }
+        }
+    }
+    darkened
+}
+
+fn stretch_rows(image: &Image, factor: usize) -> Image {
+    let row_bytes = image.width * 3;
+    let mut stretched = Image::new(image.width, image.height * factor);
+    for (y, row) in image.rgb.chunks(row_bytes).enumerate() {
+        for copy in 0..factor {
+            let start = (y * factor + copy) * row_bytes;
+            stretched.rgb[start..start + row_bytes].copy_from_slice(row);
+        }
+    }
+    stretched
+}
+
+/**
+ * Widens the picture to the 8:7 shape of the NES's pixels on a TV, blending
+ * neighbouring columns.
+ */
+pub fn correct_aspect(image: &Image) -> Image {
+    let width = (image.width * 8 + 3) / 7;
+    let mut wide = Image::new(width, image.height);
+    for x in 0..width {
+        let source = ((x as f32 + 0.5) * image.width as f32 / width as f32 - 0.5).max(0.0);
+        let left = source as usize;
+        let right = (left + 1).min(image.width - 1);
+        let weight = ((source - left as f32) * 16.0).round() as u32;
+        for y in 0..image.height {
+            let colour = blend(&[
+                (image.pixel(left, y), 16 - weight),
+                (image.pixel(right, y), weight),
+            ]);
+            wide.set_pixel(x, y, colour);
+        }
+    }
+    wide
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const K: Rgb = (0, 0, 0);
+    const W: Rgb = (255, 255, 255);
+
+    fn image(width: usize, pixels: &[Rgb]) -> Image {
+        let mut image = Image::new(width, pixels.len() / width);
+        for (i, &colour) in pixels.iter().enumerate() {
+            image.set_pixel(i % width, i / width, colour);
+        }
+        image
+    }
+
+    fn pixels(image: &Image) -> Vec<Rgb> {
+        (0..image.width * image.height)
+            .map(|i| image.pixel(i % image.width, i / image.width))
+            .collect()
+    }
+
+    #[test]
+    fn test_scalers_round_diagonals() {
+        // a white diagonal step
+        let step = image(2, &[W, K, W, W]);
+        assert_eq!(
+            pixels(&Scaler::Integer(2).apply(&step)),
+            [W, W, K, K, W, W, K, K, W, W, W, W, W, W, W, W]
+        );
+        let scaled = Scaler::Scale2x.apply(&step);
+        assert_eq!((scaled.width, scaled.height), (4, 4));
+        // the black pixel loses the corner facing the white diagonal
+        assert_eq!((scaled.pixel(2, 1), scaled.pixel(3, 1)), (W, K));
+        let scaled = Scaler::Scale3x.apply(&step);
+        assert_eq!((scaled.pixel(3, 2), scaled.pixel(5, 0)), (W, K));
+        // which HQ2x blends instead
+        let hq = Scaler::Hq2x.apply(&step);
+        assert_eq!(hq.pixel(3, 0), K);
+        assert!(hq.pixel(2, 1) != K && hq.pixel(2, 1) != W);
+
+        // flat areas are left alone
+        let flat = image(2, &[W; 4]);
+        assert!(pixels(&Scaler::Hq2x.apply(&flat)).iter().all(|&c| c == W));
+        assert!(Scaler::parse("bilinear", 3).is_err());
+    }
+
+    #[test]
+    fn test_scanlines_and_aspect() {
+        let lines = scanlines(&image(1, &[W, W]), 2, 25);
+        assert_eq!(pixels(&lines), [W, (191, 191, 191), W, (191, 191, 191)]);
+        let wide = correct_aspect(&image(7, &[W; 7]));
+        assert_eq!(wide.width, 8);
+        assert!(pixels(&wide).iter().all(|&c| c == W));
+    }
+}